@@ -0,0 +1,19 @@
+use bevy_app::{App, Update};
+use bevy_ecs::schedule::common_conditions::in_state;
+use bevy_ecs::schedule::IntoSystemConfigs;
+use etheryal_extension::prelude::*;
+
+// An extension which never finishes its updates once its registration is
+// confirmed, running out of the fuel the etheryal Server gives it for each
+// update. The etheryal Server runs the updates of the extension.
+#[etheryal_extension::main(id = "example:busy_extension", host_tick)]
+fn main(app: &mut App) {
+    app.add_systems(Update, spin.run_if(in_state(ExtensionState::Running)));
+}
+
+/// This system will never return, so the etheryal Server suspends the update
+fn spin() {
+    loop {
+        std::hint::spin_loop();
+    }
+}
@@ -0,0 +1,49 @@
+//! Run conditions that only run a system when an extension message arrived
+use bevy_ecs::event::EventReader;
+use etheryal_extension_common::message::GuestMessage;
+
+use crate::ExtensionEvent;
+
+/// A run condition that is true if a message of type `T` was received since
+/// the condition was last checked
+///
+/// ```ignore
+/// app.add_systems(Update, on_pong.run_if(on_extension_message::<Pong>()));
+/// ```
+pub fn on_extension_message<T>() -> impl FnMut(EventReader<ExtensionEvent<T>>) -> bool + Clone
+where
+    T: GuestMessage, {
+    // The reader is cleared so the same messages don't trigger the condition
+    // again
+    move |mut reader: EventReader<ExtensionEvent<T>>| {
+        let received = !reader.is_empty();
+        reader.clear();
+        received
+    }
+}
+
+/// A run condition that is true if a message of type `T` matching the
+/// predicate was received since the condition was last checked
+///
+/// ```ignore
+/// app.add_systems(
+///     Update,
+///     on_admin_chat.run_if(extension_message_matches(|message: &ChatMessage| message.is_admin())),
+/// );
+/// ```
+pub fn extension_message_matches<T, F>(
+    predicate: F,
+) -> impl FnMut(EventReader<ExtensionEvent<T>>) -> bool + Clone
+where
+    T: GuestMessage,
+    F: Fn(&T) -> bool + Clone + Send + Sync + 'static, {
+    move |mut reader: EventReader<ExtensionEvent<T>>| {
+        // Every message is read, so the unmatched ones after a match are not
+        // checked again next time
+        let mut matched = false;
+        for event in reader.iter() {
+            matched |= predicate(event);
+        }
+        matched
+    }
+}
@@ -0,0 +1,271 @@
+//! Bevy diagnostics of the messages exchanged with the extension host
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use bevy_app::{App, Last, Plugin};
+use bevy_diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic};
+use bevy_ecs::prelude::*;
+use etheryal_extension_common::message::registry;
+use etheryal_extension_common::message::stats::{GuestStats, MessageTypeStats};
+use tracing::error;
+
+use crate::ExtensionGuest;
+
+/// The maximum number of measurements kept by each diagnostic
+const MAX_HISTORY_LENGTH: usize = 20;
+
+/// A diagnostic of a single message type
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MessageDiagnostic {
+    /// The number of messages sent during the update
+    Sent,
+    /// The number of bytes sent during the update
+    SentBytes,
+    /// The number of messages received during the update
+    Received,
+    /// The number of bytes received during the update
+    ReceivedBytes,
+    /// The most received messages waiting to be dispatched during the update
+    QueueDepth,
+    /// The number of received messages dropped during the update because the
+    /// queue was full
+    Dropped,
+}
+
+impl MessageDiagnostic {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::SentBytes => "sent_bytes",
+            Self::Received => "received",
+            Self::ReceivedBytes => "received_bytes",
+            Self::QueueDepth => "queue_depth",
+            Self::Dropped => "dropped",
+        }
+    }
+
+    fn value(self, stats: &MessageTypeStats, previous: Option<&MessageTypeStats>) -> f64 {
+        let delta = |value: fn(&MessageTypeStats) -> u64| {
+            value(stats).saturating_sub(previous.map_or(0, value)) as f64
+        };
+        match self {
+            Self::Sent => delta(MessageTypeStats::sent),
+            Self::SentBytes => delta(MessageTypeStats::sent_bytes),
+            Self::Received => delta(MessageTypeStats::received),
+            Self::ReceivedBytes => delta(MessageTypeStats::received_bytes),
+            Self::QueueDepth => stats.queue_depth() as f64,
+            Self::Dropped => delta(MessageTypeStats::dropped),
+        }
+    }
+}
+
+/// Adds diagnostics of the messages exchanged with the extension host, in
+/// total and for each message type, and optionally sends them to the host as
+/// a [GuestStats] message
+#[derive(Default)]
+pub struct ExtensionDiagnosticsPlugin {
+    guest_stats_interval: Option<u32>,
+}
+
+impl ExtensionDiagnosticsPlugin {
+    /// The number of bytes received from the extension host during the update
+    pub const BYTES_RECEIVED: DiagnosticId =
+        DiagnosticId::from_u128(0x2D66B51268634272A37976EAE265554D);
+    /// The number of bytes sent to the extension host during the update
+    pub const BYTES_SENT: DiagnosticId =
+        DiagnosticId::from_u128(0x22A85ABBBE4C4E5BBDD2EEE2A4572002);
+    /// The time spent decoding messages during the update, in milliseconds
+    pub const DECODE_TIME: DiagnosticId =
+        DiagnosticId::from_u128(0x9029AE400FBF4F88B7617C9853470067);
+    /// The time spent encoding messages during the update, in milliseconds
+    pub const ENCODE_TIME: DiagnosticId =
+        DiagnosticId::from_u128(0x064D8F9F5A25439E84EE55A1AE225B9D);
+    /// The number of messages received from the extension host during the
+    /// update
+    pub const MESSAGES_RECEIVED: DiagnosticId =
+        DiagnosticId::from_u128(0x5A9DFCDC665644C5A75EF8E1F6CF020B);
+    /// The number of messages sent to the extension host during the update
+    pub const MESSAGES_SENT: DiagnosticId =
+        DiagnosticId::from_u128(0x82DE75B54E174E37BF945FA8FA05BC0F);
+    /// The most received messages waiting to be dispatched during the update,
+    /// summed over the message types
+    pub const QUEUE_DEPTH: DiagnosticId =
+        DiagnosticId::from_u128(0xC5E5B9660AB842E8A2707F4012527192);
+
+    /// Returns the id of the diagnostic of the message type with the given name
+    pub fn message_diagnostic_id(type_name: &str, diagnostic: MessageDiagnostic) -> DiagnosticId {
+        let mut hasher = DefaultHasher::new();
+        type_name.hash(&mut hasher);
+        diagnostic.hash(&mut hasher);
+        DiagnosticId::from_u128(0x3D483D7300E04D77AAF6FBB8D291865F ^ u128::from(hasher.finish()))
+    }
+
+    /// Sends a [GuestStats] message to the extension host every `ticks`
+    /// updates
+    pub fn with_guest_stats_interval(mut self, ticks: u32) -> Self {
+        self.guest_stats_interval = Some(ticks.max(1));
+        self
+    }
+}
+
+/// The diagnostics of each message type
+#[derive(Resource, Default)]
+struct MessageDiagnostics {
+    diagnostics: Vec<(&'static str, MessageDiagnostic, DiagnosticId)>,
+}
+
+/// The number of updates between two [GuestStats] messages
+#[derive(Resource)]
+struct GuestStatsInterval {
+    ticks: u32,
+}
+
+impl Plugin for ExtensionDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        for (id, name) in [
+            (Self::MESSAGES_SENT, "extension/messages_sent"),
+            (Self::MESSAGES_RECEIVED, "extension/messages_received"),
+            (Self::BYTES_SENT, "extension/bytes_sent"),
+            (Self::BYTES_RECEIVED, "extension/bytes_received"),
+            (Self::QUEUE_DEPTH, "extension/queue_depth"),
+        ] {
+            app.register_diagnostic(Diagnostic::new(id, name, MAX_HISTORY_LENGTH));
+        }
+        for (id, name) in [
+            (Self::ENCODE_TIME, "extension/encode_time"),
+            (Self::DECODE_TIME, "extension/decode_time"),
+        ] {
+            app.register_diagnostic(
+                Diagnostic::new(id, name, MAX_HISTORY_LENGTH).with_suffix("ms"),
+            );
+        }
+
+        let mut message_diagnostics = MessageDiagnostics::default();
+        let host_messages = registry::host_messages().flat_map(|registration| {
+            [MessageDiagnostic::Sent, MessageDiagnostic::SentBytes]
+                .map(|diagnostic| (registration.type_name(), diagnostic))
+        });
+        let guest_messages = registry::guest_messages().flat_map(|registration| {
+            [
+                MessageDiagnostic::Received,
+                MessageDiagnostic::ReceivedBytes,
+                MessageDiagnostic::QueueDepth,
+                MessageDiagnostic::Dropped,
+            ]
+            .map(|diagnostic| (registration.type_name(), diagnostic))
+        });
+        for (type_name, diagnostic) in host_messages.chain(guest_messages) {
+            let id = Self::message_diagnostic_id(type_name, diagnostic);
+            let short_name = type_name.rsplit("::").next().unwrap_or(type_name);
+            let name = format!("extension/{short_name}/{}", diagnostic.name());
+            app.register_diagnostic(Diagnostic::new(id, name, MAX_HISTORY_LENGTH));
+            message_diagnostics
+                .diagnostics
+                .push((type_name, diagnostic, id));
+        }
+
+        app.insert_resource(message_diagnostics)
+            .add_systems(Last, measure_message_diagnostics);
+
+        if let Some(ticks) = self.guest_stats_interval {
+            app.insert_resource(GuestStatsInterval { ticks })
+                .add_systems(Last, send_guest_stats);
+        }
+    }
+}
+
+fn measure_message_diagnostics(
+    mut diagnostics: Diagnostics, guest: Res<ExtensionGuest>,
+    message_diagnostics: Res<MessageDiagnostics>, mut previous: Local<GuestStats>,
+) {
+    let stats = guest.stats();
+
+    let total = |value: fn(&MessageTypeStats) -> u64, stats: &GuestStats| -> u64 {
+        stats.messages().iter().map(value).sum()
+    };
+    let delta = |value: fn(&MessageTypeStats) -> u64| {
+        total(value, &stats).saturating_sub(total(value, &previous)) as f64
+    };
+    diagnostics.add_measurement(ExtensionDiagnosticsPlugin::MESSAGES_SENT, || {
+        delta(MessageTypeStats::sent)
+    });
+    diagnostics.add_measurement(ExtensionDiagnosticsPlugin::MESSAGES_RECEIVED, || {
+        delta(MessageTypeStats::received)
+    });
+    diagnostics.add_measurement(ExtensionDiagnosticsPlugin::BYTES_SENT, || {
+        delta(MessageTypeStats::sent_bytes)
+    });
+    diagnostics.add_measurement(ExtensionDiagnosticsPlugin::BYTES_RECEIVED, || {
+        delta(MessageTypeStats::received_bytes)
+    });
+    diagnostics.add_measurement(ExtensionDiagnosticsPlugin::QUEUE_DEPTH, || {
+        total(MessageTypeStats::queue_depth, &stats) as f64
+    });
+
+    let elapsed_ms =
+        |nanos: u64, previous_nanos: u64| nanos.saturating_sub(previous_nanos) as f64 / 1_000_000.0;
+    diagnostics.add_measurement(ExtensionDiagnosticsPlugin::ENCODE_TIME, || {
+        elapsed_ms(stats.encode_time_nanos(), previous.encode_time_nanos())
+    });
+    diagnostics.add_measurement(ExtensionDiagnosticsPlugin::DECODE_TIME, || {
+        elapsed_ms(stats.decode_time_nanos(), previous.decode_time_nanos())
+    });
+
+    for &(type_name, diagnostic, id) in &message_diagnostics.diagnostics {
+        if let Some(message_stats) = stats.message(type_name) {
+            diagnostics.add_measurement(id, || {
+                diagnostic.value(message_stats, previous.message(type_name))
+            });
+        }
+    }
+
+    *previous = stats;
+}
+
+fn send_guest_stats(
+    guest: Res<ExtensionGuest>, interval: Res<GuestStatsInterval>, mut ticks: Local<u32>,
+) {
+    *ticks += 1;
+    if *ticks < interval.ticks {
+        return;
+    }
+    *ticks = 0;
+
+    if let Err(err) = guest.send_message(guest.stats()) {
+        error!("Failed to send the guest stats to the extension host: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_diagnostic::DiagnosticsStore;
+    use etheryal_extension_common::message::debug::Pong;
+
+    use super::*;
+    use crate::{test_host, EtheryalExtensionPlugin};
+
+    fn queue_depth(app: &App) -> Option<f64> {
+        let id = ExtensionDiagnosticsPlugin::message_diagnostic_id(
+            std::any::type_name::<Pong>(),
+            MessageDiagnostic::QueueDepth,
+        );
+        app.world.resource::<DiagnosticsStore>().get(id)?.value()
+    }
+
+    #[test]
+    fn test_queue_depth() {
+        let _host = test_host::lock();
+        let mut app = test_host::app(EtheryalExtensionPlugin::new(test_host::info()));
+        app.add_plugins(ExtensionDiagnosticsPlugin::default());
+        app.update();
+
+        for _ in 0..3 {
+            test_host::queue(Pong);
+        }
+        app.update();
+        assert_eq!(queue_depth(&app), Some(3.0));
+
+        app.update();
+        assert_eq!(queue_depth(&app), Some(0.0));
+    }
+}
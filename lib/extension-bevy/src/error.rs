@@ -1,3 +1,4 @@
+use bevy_ecs::event::Event;
 use thiserror::Error;
 
 /// An error that can occur when interacting with the extension host
@@ -18,4 +19,19 @@ pub enum ExtensionError {
     /// Failed to downcast a message
     #[error("Failed to downcast message")]
     Downcast,
+
+    /// A snapshot taken by another version of the extension could not be
+    /// migrated
+    #[error("Failed to migrate the snapshot: {0}")]
+    Migration(String),
+}
+
+/// An event sent when a [ToHost](crate::ToHost) message could not be sent to
+/// the extension host
+#[derive(Event, Debug)]
+pub struct ExtensionSendError {
+    /// The name of the type of the message that could not be sent
+    pub type_name: &'static str,
+    /// The reason the message could not be sent
+    pub error: ExtensionError,
 }
@@ -1,43 +1,132 @@
-use std::any::TypeId;
+use std::any::{type_name, Any, TypeId};
+use std::time::Instant;
 
 use bevy_ecs::prelude::*;
-use crossbeam_queue::SegQueue;
 use dashmap::DashMap;
-use etheryal_extension_common::message::{GuestMessageEnum, HostMessageEnum};
+use etheryal_extension_common::message::direct::DirectMessage;
+use etheryal_extension_common::message::registry::HostMessageRegistration;
+use etheryal_extension_common::message::stats::GuestStats;
+use etheryal_extension_common::message::topic::{Publish, Subscribe, TopicPattern, Unsubscribe};
+use etheryal_extension_common::message::{encode_message, HostMessage, MessageTag};
+use etheryal_extension_common::ExtensionModuleInfo;
+use etheryal_identifier::NamespacedIdentifier;
+use serde::Serialize;
 use tracing::trace;
 
 use crate::error::ExtensionError;
+use crate::queue::GuestMessageQueue;
+use crate::stats;
 
 /// A Bevy resource that allows the extension guest to interact with the
 /// extension host.
 #[derive(Resource)]
 pub struct ExtensionGuest {
-    pub(crate) guest_messages: DashMap<TypeId, SegQueue<GuestMessageEnum>>,
+    info: ExtensionModuleInfo,
+    pub(crate) guest_messages: DashMap<TypeId, GuestMessageQueue>,
 }
 
 impl ExtensionGuest {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(info: ExtensionModuleInfo) -> Self {
         Self {
+            info,
             guest_messages: DashMap::new(),
         }
     }
 }
 
 impl ExtensionGuest {
+    /// Returns the information about this extension module sent to the
+    /// extension host
+    pub fn info(&self) -> &ExtensionModuleInfo {
+        &self.info
+    }
+
+    /// Returns the statistics of the messages exchanged with the extension
+    /// host since the extension started
+    pub fn stats(&self) -> GuestStats {
+        stats::snapshot(
+            self.guest_messages
+                .iter()
+                .map(|queue| (queue.type_name, queue.peak_depth())),
+        )
+    }
+
     /// Send a message to the extension host
-    pub fn send_message<H: Into<HostMessageEnum>>(&self, message: H) -> Result<(), ExtensionError> {
-        send_message(message.into())
+    pub fn send_message<H>(&self, message: H) -> Result<(), ExtensionError>
+    where
+        H: HostMessage + MessageTag + Serialize, {
+        send_message(&message)
+    }
+
+    /// Send a message to another extension, routed by the extension host if
+    /// this extension depends on it, or if it accepts messages from this
+    /// extension
+    ///
+    /// The other extension receives it as a `DirectMessageReceived` message,
+    /// and decodes it with
+    /// [DirectMessageReceived::decode](etheryal_extension_common::message::direct::DirectMessageReceived::decode).
+    pub fn send_to<T>(
+        &self, target: NamespacedIdentifier, message: &T,
+    ) -> Result<(), ExtensionError>
+    where
+        T: Serialize + ?Sized, {
+        self.send_message(DirectMessage::new(target, message)?)
+    }
+
+    /// Receive the messages published on the topics matching the pattern, as
+    /// `TopicMessage` messages
+    pub fn subscribe(&self, pattern: TopicPattern) -> Result<(), ExtensionError> {
+        self.send_message(Subscribe::new(pattern))
     }
+
+    /// Stop receiving the messages published on the topics of a previous
+    /// subscription
+    pub fn unsubscribe(&self, pattern: TopicPattern) -> Result<(), ExtensionError> {
+        self.send_message(Unsubscribe::new(pattern))
+    }
+
+    /// Publish a message on the topic, sent by the extension host to every
+    /// extension subscribed to it
+    ///
+    /// The subscribers decode it with
+    /// [TopicMessage::decode](etheryal_extension_common::message::topic::TopicMessage::decode).
+    pub fn publish<T>(
+        &self, topic: NamespacedIdentifier, message: &T,
+    ) -> Result<(), ExtensionError>
+    where
+        T: Serialize + ?Sized, {
+        self.send_message(Publish::new(topic, message)?)
+    }
+}
+
+pub(crate) fn send_message<H>(message: &H) -> Result<(), ExtensionError>
+where
+    H: HostMessage + MessageTag + Serialize, {
+    send_encoded(type_name::<H>(), || encode_message(message))
+}
+
+/// Sends a message of the type of the registration, drained from its `ToHost`
+/// events
+pub(crate) fn send_registered(
+    registration: &HostMessageRegistration, message: &dyn Any,
+) -> Result<(), ExtensionError> {
+    send_encoded(registration.type_name(), || registration.encode(message))
 }
 
-fn send_message(message: HostMessageEnum) -> Result<(), ExtensionError> {
-    let encoded = rmp_serde::to_vec_named(&message)?;
+fn send_encoded(
+    type_name: &'static str, encode: impl FnOnce() -> Result<Vec<u8>, rmp_serde::encode::Error>,
+) -> Result<(), ExtensionError> {
+    let start = Instant::now();
+    let encoded = encode();
+    stats::record_encode(start.elapsed());
+    let encoded = encoded?;
 
     let len = encoded.len();
     trace!("Sending message of {len} bytes");
 
-    // SAFETY: This is safe because the extension host will only call this function
-    // after sending extension info.
+    // SAFETY: This is safe because the extension info is sent in `PreStartup`,
+    // before any other system can send a message.
     unsafe { etheryal_extension_sys::send_message(len, encoded.as_ptr()) };
+    stats::record_sent(type_name, len);
     Ok(())
 }
@@ -1,89 +1,291 @@
 //! A Bevy plugin that provides utilities for creating etheryal WebAssembly
 //! extensions.
 #![deny(missing_docs, clippy::missing_safety_doc)]
-use std::any::{type_name, TypeId};
-
-use bevy_app::{App, Plugin, PreUpdate};
-use bevy_ecs::event::EventWriter;
-use bevy_ecs::system::Res;
-use crossbeam_queue::SegQueue;
-use error::ExtensionError;
-use etheryal_extension_common::message::debug::Pong;
-use etheryal_extension_common::message::events::ShutdownGuest;
-use etheryal_extension_common::message::{GuestMessage, GuestMessageEnum};
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use bevy_app::{App, Last, Plugin, PostUpdate, PreStartup, PreUpdate};
+use bevy_ecs::schedule::common_conditions::in_state;
+use bevy_ecs::schedule::{
+    BoxedScheduleLabel, IntoSystemConfigs, IntoSystemSetConfigs, OnEnter, ScheduleLabel,
+};
+pub use condition::{extension_message_matches, on_extension_message};
+pub use diagnostics::{ExtensionDiagnosticsPlugin, MessageDiagnostic};
+pub use error::{ExtensionError, ExtensionSendError};
+pub use etheryal_extension_common::message::event::{ExtensionEvent, ToHost};
+use etheryal_extension_common::message::log::LogLevel;
+pub use etheryal_extension_common::message::snapshot::{PersistedValues, Snapshot};
+pub use etheryal_extension_common::message::topic::{TopicMessage, TopicPattern};
+use etheryal_extension_common::message::{registry, GuestMessage};
 use etheryal_extension_common::ExtensionModuleInfo;
-pub use event::ExtensionEvent;
 pub use guest::ExtensionGuest;
-use tracing::{debug, warn};
+pub use log::{log_level, set_log_level, ExtensionLogLayer, DEFAULT_LOG_LEVEL};
+pub use param::ExtensionMessages;
+use queue::GuestMessageQueue;
+pub use queue::{MessageQueueConfig, OverflowPolicy, DEFAULT_QUEUE_CAPACITY};
+pub use set::ExtensionSet;
+use shutdown::ShutdownGracePeriod;
+pub use shutdown::DEFAULT_SHUTDOWN_GRACE_TICKS;
+use snapshot::PersistRegistry;
+pub use snapshot::{Persist, PersistApp, SnapshotMigration};
+pub use state::ExtensionState;
+pub use tick::{tick, HostTick};
+use tracing::warn;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
+mod condition;
+mod diagnostics;
 mod error;
-mod event;
 mod guest;
+mod log;
+mod panic;
+mod param;
+mod queue;
+mod set;
+mod shutdown;
+mod snapshot;
+mod state;
+mod stats;
 mod systems;
+#[cfg(test)]
+mod test_host;
+mod tick;
 
 /// A Bevy plugin that provides utilities for creating etheryal extensions.
 pub struct EtheryalExtensionPlugin {
     guest_info: ExtensionModuleInfo,
+    shutdown_grace_ticks: u32,
+    schedule: BoxedScheduleLabel,
+    flush_schedule: BoxedScheduleLabel,
+    host_tick: bool,
+    log_level: Option<LogLevel>,
+    panic_hook: bool,
+    default_queue: MessageQueueConfig,
+    message_queues: HashMap<TypeId, MessageQueueConfig>,
 }
 
 impl EtheryalExtensionPlugin {
     /// Create a new plugin with the given extension module information
     pub fn new(guest_info: ExtensionModuleInfo) -> Self {
-        Self { guest_info }
+        Self {
+            guest_info,
+            shutdown_grace_ticks: DEFAULT_SHUTDOWN_GRACE_TICKS,
+            schedule: Box::new(PreUpdate),
+            flush_schedule: Box::new(PostUpdate),
+            host_tick: false,
+            log_level: Some(DEFAULT_LOG_LEVEL),
+            panic_hook: true,
+            default_queue: MessageQueueConfig::default(),
+            message_queues: HashMap::new(),
+        }
+    }
+
+    /// Sets the capacity and overflow policy of the queues of every guest
+    /// message type without its own configuration
+    pub fn with_default_queue(mut self, config: MessageQueueConfig) -> Self {
+        self.default_queue = config;
+        self
+    }
+
+    /// Sets the capacity and overflow policy of the queue of the guest message
+    /// `T`
+    pub fn with_message_queue<T>(mut self, config: MessageQueueConfig) -> Self
+    where
+        T: GuestMessage, {
+        self.message_queues.insert(TypeId::of::<T>(), config);
+        self
+    }
+
+    /// Doesn't install the panic hook reporting the panics of the extension to
+    /// the extension host
+    pub fn without_panic_hook(mut self) -> Self {
+        self.panic_hook = false;
+        self
+    }
+
+    /// Sets the most verbose level of the `tracing` events forwarded to the
+    /// extension host, until the host changes it
+    pub fn with_log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    /// Doesn't install the [ExtensionLogLayer] as the global `tracing`
+    /// subscriber, so the app can install its own
+    pub fn without_log_forwarding(mut self) -> Self {
+        self.log_level = None;
+        self
+    }
+
+    /// Runs the app only when the extension host calls the `etheryal_tick`
+    /// export, instead of on its own clock, with the time elapsed since the
+    /// previous tick in the [HostTick] resource
+    ///
+    /// The export is generated by `#[etheryal_extension::main(host_tick)]`,
+    /// which must be used instead of the `ScheduleRunnerPlugin`.
+    pub fn with_host_tick(mut self) -> Self {
+        self.host_tick = true;
+        self
+    }
+
+    /// Sets the schedule the [ExtensionSet::Receive] and
+    /// [ExtensionSet::Dispatch] sets run in, `PreUpdate` by default
+    pub fn with_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.schedule = Box::new(schedule);
+        self
+    }
+
+    /// Sets the schedule the [ExtensionSet::Flush] set runs in, `PostUpdate` by
+    /// default
+    pub fn with_flush_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.flush_schedule = Box::new(schedule);
+        self
+    }
+
+    /// Sets the number of updates run in [ExtensionState::ShuttingDown] after
+    /// the one the shutdown was requested in, giving systems time to flush
+    /// their data, [DEFAULT_SHUTDOWN_GRACE_TICKS] by default
+    ///
+    /// With 0, the extension exits at the end of the update the shutdown was
+    /// requested in.
+    pub fn with_shutdown_grace_ticks(mut self, ticks: u32) -> Self {
+        self.shutdown_grace_ticks = ticks;
+        self
     }
 }
 
 impl Plugin for EtheryalExtensionPlugin {
     fn build(&self, app: &mut App) {
-        set_extension_info(&self.guest_info).expect("extension info should be set");
+        if self.host_tick {
+            app.init_resource::<HostTick>()
+                .set_runner(tick::host_tick_runner);
+        }
 
-        // Register the guest messages
-        let guest = ExtensionGuest::new();
-        listen_for_guest_message::<Pong>(app, &guest);
-        listen_for_guest_message::<ShutdownGuest>(app, &guest);
+        if self.panic_hook {
+            panic::install_panic_hook();
+        }
+        if let Some(level) = self.log_level {
+            set_log_level(level);
+            if tracing_subscriber::registry()
+                .with(ExtensionLogLayer)
+                .try_init()
+                .is_err()
+            {
+                warn!(
+                    "A global tracing subscriber is already set, logs are not forwarded to the \
+                     host"
+                );
+            }
+        }
+
+        // Listen for every message the extension host can send, including the
+        // messages derived outside of `etheryal-extension-common`
+        let guest = ExtensionGuest::new(self.guest_info.clone());
+        let mut tags = HashMap::new();
+        for registration in registry::guest_messages() {
+            if let Some(other) = tags.insert(registration.tag(), registration.type_name()) {
+                panic!(
+                    "The guest messages '{other}' and '{}' have the same tag '{}'",
+                    registration.type_name(),
+                    registration.tag()
+                );
+            }
+            let config = self
+                .message_queues
+                .get(&registration.type_id())
+                .copied()
+                .unwrap_or(self.default_queue);
+            guest.guest_messages.insert(
+                registration.type_id(),
+                GuestMessageQueue::new(registration, config),
+            );
+            registration.add_event(app);
+        }
+
+        for schedule in [&self.schedule, &self.flush_schedule] {
+            app.configure_sets(
+                schedule.clone(),
+                (
+                    ExtensionSet::Receive,
+                    ExtensionSet::Dispatch,
+                    ExtensionSet::Flush,
+                )
+                    .chain(),
+            );
+        }
+        // Send the `ToHost` events of every message the extension host can receive
+        for registration in registry::host_messages() {
+            registration.add_to_host_event(app);
+        }
 
         app.insert_resource(guest)
-            .add_systems(PreUpdate, systems::send_guest_message_events);
-    }
-}
+            .add_event::<ExtensionSendError>()
+            .add_systems(
+                self.schedule.clone(),
+                (
+                    systems::send_guest_message_events,
+                    systems::report_dropped_messages,
+                )
+                    .chain()
+                    .in_set(ExtensionSet::Receive),
+            )
+            .add_systems(
+                self.schedule.clone(),
+                systems::send_message_events.in_set(ExtensionSet::Dispatch),
+            )
+            .add_systems(
+                self.flush_schedule.clone(),
+                systems::send_to_host_events.in_set(ExtensionSet::Flush),
+            );
 
-fn set_extension_info(extension_info: &ExtensionModuleInfo) -> Result<(), ExtensionError> {
-    let encoded = rmp_serde::to_vec_named(extension_info)?;
-    unsafe { etheryal_extension_sys::extension_info(encoded.len(), encoded.as_ptr()) };
-    Ok(())
-}
+        // Send the extension info before any user system runs, then wait for the
+        // host to confirm the registration
+        app.add_state::<ExtensionState>()
+            .add_systems(PreStartup, state::register_extension)
+            .add_systems(
+                self.schedule.clone(),
+                state::confirm_registration
+                    .in_set(ExtensionSet::Dispatch)
+                    .after(systems::send_message_events)
+                    .run_if(in_state(ExtensionState::Handshaking)),
+            );
 
-fn listen_for_guest_message<T>(app: &mut App, guest: &ExtensionGuest)
-where
-    T: GuestMessage,
-    GuestMessageEnum: TryInto<T, Error = &'static str>, {
-    let type_id = TypeId::of::<T>();
-
-    assert!(
-        !guest.guest_messages.contains_key(&type_id),
-        "Duplicate registration of a guest Message: {}",
-        type_name::<T>()
-    );
-    guest.guest_messages.insert(type_id, SegQueue::new());
-
-    app.add_event::<ExtensionEvent<T>>()
-        .add_systems(PreUpdate, send_message_event::<T>);
-}
+        // Reply to `SnapshotRequest` if the extension persists any state, and
+        // restore the snapshot before the registration is confirmed
+        app.init_resource::<PersistRegistry>().add_systems(
+            self.schedule.clone(),
+            (
+                snapshot::take_snapshot,
+                snapshot::restore_snapshot.before(state::confirm_registration),
+            )
+                .in_set(ExtensionSet::Dispatch)
+                .after(systems::send_message_events),
+        );
 
-fn send_message_event<T>(guest: Res<ExtensionGuest>, mut events: EventWriter<ExtensionEvent<T>>)
-where
-    T: GuestMessage,
-    GuestMessageEnum: TryInto<T, Error = &'static str>, {
-    let Some(messages) = guest.guest_messages.get(&TypeId::of::<T>()) else {
-        return;
-    };
-
-    while let Some(message) = messages.pop() {
-        let Ok(inner) = message.try_into() else {
-            warn!("Failed to downcast guest message to '{}'", type_name::<T>());
-            continue;
-        };
-        debug!("Received an extension guest message: {}", type_name::<T>());
-        events.send(ExtensionEvent::new(inner));
+        // Exit once the grace period after a `ShutdownGuest` message is over
+        app.insert_resource(ShutdownGracePeriod {
+            ticks: self.shutdown_grace_ticks,
+        })
+        .add_systems(
+            self.schedule.clone(),
+            shutdown::request_shutdown
+                .in_set(ExtensionSet::Dispatch)
+                .after(systems::send_message_events),
+        )
+        .add_systems(
+            self.schedule.clone(),
+            (log::update_log_level, systems::log_exhausted_resources)
+                .in_set(ExtensionSet::Dispatch)
+                .after(systems::send_message_events),
+        )
+        .add_systems(
+            OnEnter(ExtensionState::ShuttingDown),
+            shutdown::start_shutdown_countdown,
+        )
+        .add_systems(
+            Last,
+            shutdown::shutdown_countdown.run_if(in_state(ExtensionState::ShuttingDown)),
+        );
     }
 }
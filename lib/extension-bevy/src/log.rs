@@ -0,0 +1,249 @@
+//! Forwards the `tracing` events of the extension guest to the extension host
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use bevy_ecs::event::EventReader;
+use etheryal_extension_common::message::log::{LogLevel, LogRecord, LogSpan, SetLogLevel};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::{guest, state, ExtensionEvent};
+
+/// The default most verbose level forwarded to the extension host
+pub const DEFAULT_LOG_LEVEL: LogLevel = LogLevel::Info;
+
+/// The most verbose level forwarded to the extension host, as a [LogLevel]
+/// discriminant
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(DEFAULT_LOG_LEVEL as u8);
+
+/// The most records kept until the extension guest is registered, the later
+/// ones are dropped
+const MAX_PENDING_RECORDS: usize = 256;
+
+/// The records of the events recorded before the extension guest is
+/// registered, as the extension host traps messages sent before the extension
+/// info
+static PENDING_RECORDS: Mutex<Vec<LogRecord>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Whether an event is being forwarded, so the events recorded while
+    /// sending it are not forwarded in turn
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns the most verbose level forwarded to the extension host
+pub fn log_level() -> LogLevel {
+    match LOG_LEVEL.load(Ordering::Relaxed) {
+        level if level == LogLevel::Error as u8 => LogLevel::Error,
+        level if level == LogLevel::Warn as u8 => LogLevel::Warn,
+        level if level == LogLevel::Info as u8 => LogLevel::Info,
+        level if level == LogLevel::Debug as u8 => LogLevel::Debug,
+        _ => LogLevel::Trace,
+    }
+}
+
+/// Sets the most verbose level forwarded to the extension host
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// A [Layer] that sends every `tracing` event, with the spans it was recorded
+/// in, to the extension host as a [LogRecord] message
+///
+/// Events more verbose than [log_level] are ignored, the extension host can
+/// change the level at runtime with a [SetLogLevel] message. The events
+/// recorded before the extension info is sent are kept, and forwarded once it
+/// is.
+#[derive(Default, Debug)]
+pub struct ExtensionLogLayer;
+
+/// The fields recorded on a span, stored in the span extensions
+struct SpanFields(BTreeMap<String, String>);
+
+impl<S> Layer<S> for ExtensionLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // The level can change at runtime, so it is checked for every event
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        *metadata.level() <= tracing::Level::from(log_level())
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanFields(visitor.fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            let mut visitor = FieldVisitor {
+                fields: std::mem::take(fields),
+                ..Default::default()
+            };
+            values.record(&mut visitor);
+            *fields = visitor.fields;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if FORWARDING.with(|forwarding| forwarding.replace(true)) {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let spans = ctx
+            .event_scope(event)
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let fields = span
+                    .extensions()
+                    .get::<SpanFields>()
+                    .map(|SpanFields(fields)| fields.clone())
+                    .unwrap_or_default();
+                LogSpan::new(span.name().into(), fields)
+            })
+            .collect();
+
+        let metadata = event.metadata();
+        let record = LogRecord::new(
+            (*metadata.level()).into(),
+            metadata.target().into(),
+            visitor.message.unwrap_or_default(),
+            visitor.fields,
+            spans,
+        );
+        forward(record);
+
+        FORWARDING.with(|forwarding| forwarding.set(false));
+    }
+}
+
+/// Sends the record to the extension host, or keeps it until the extension
+/// guest is registered
+fn forward(record: LogRecord) {
+    if !state::is_registered() {
+        let mut pending = PENDING_RECORDS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if pending.len() < MAX_PENDING_RECORDS {
+            pending.push(record);
+        }
+        return;
+    }
+
+    // The error cannot be logged without recording another event
+    let _ = guest::send_message(&record);
+}
+
+/// Sends the records of the events recorded before the extension guest was
+/// registered
+pub(crate) fn forward_pending_records() {
+    let pending = std::mem::take(
+        &mut *PENDING_RECORDS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner),
+    );
+    for record in pending {
+        let _ = guest::send_message(&record);
+    }
+}
+
+/// Collects the fields of an event or a span, formatted with `Debug`
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: BTreeMap<String, String>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.into());
+        } else {
+            self.fields.insert(field.name().into(), value.into());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{value:?}"));
+        } else {
+            self.fields
+                .insert(field.name().into(), format!("{value:?}"));
+        }
+    }
+}
+
+pub(crate) fn update_log_level(mut events: EventReader<ExtensionEvent<SetLogLevel>>) {
+    if let Some(event) = events.iter().last() {
+        set_log_level(*event.level());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use etheryal_extension_common::message::HostMessageEnum;
+    use tracing::info;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{test_host, EtheryalExtensionPlugin};
+
+    /// Returns the messages of the log records sent to the extension host
+    fn sent_records() -> Vec<String> {
+        test_host::take_sent()
+            .into_iter()
+            .filter_map(|message| match message {
+                HostMessageEnum::LogRecord(record) => Some(record.message().clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_forward_records_after_registration() {
+        let _host = test_host::lock();
+        let subscriber = tracing_subscriber::registry().with(ExtensionLogLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            let mut app = test_host::app(EtheryalExtensionPlugin::new(test_host::info()));
+            info!("Recorded before the registration");
+            assert!(sent_records().is_empty());
+
+            app.update();
+            info!("Recorded after the registration");
+            let records = sent_records();
+            assert_eq!(
+                records.first().map(String::as_str),
+                Some("Recorded before the registration")
+            );
+            assert_eq!(
+                records.last().map(String::as_str),
+                Some("Recorded after the registration")
+            );
+            assert!(!test_host::trapped());
+        });
+    }
+}
@@ -0,0 +1,126 @@
+//! Reports the panics of the extension guest to the extension host before the
+//! extension traps
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::panic::{self, PanicHookInfo};
+use std::sync::Mutex;
+
+use etheryal_extension_common::message::events::GuestPanicked;
+
+use crate::state;
+
+/// The size of the buffer the panic report is encoded in
+const PANIC_BUFFER_LEN: usize = 4096;
+
+/// The longest panic message sent when the whole report doesn't fit in the
+/// buffer
+const TRUNCATED_MESSAGE_LEN: usize = 1024;
+
+/// The buffer the panic report is encoded in, allocated ahead of time so a
+/// panic caused by running out of memory can still be reported
+static PANIC_BUFFER: Mutex<[u8; PANIC_BUFFER_LEN]> = Mutex::new([0; PANIC_BUFFER_LEN]);
+
+/// Installs a panic hook sending a [GuestPanicked] message to the extension
+/// host, then calling the previous hook
+///
+/// A panic before the extension info is sent is only reported by the previous
+/// hook, writing it to stderr by default, as the extension host traps messages
+/// sent before the extension info.
+pub(crate) fn install_panic_hook() {
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        report_panic(info);
+        previous_hook(info);
+    }));
+}
+
+fn report_panic(info: &PanicHookInfo<'_>) {
+    if !state::is_registered() {
+        return;
+    }
+
+    // The buffer is already locked if reporting the panic panicked
+    let Ok(mut buffer) = PANIC_BUFFER.try_lock() else {
+        return;
+    };
+
+    let message = if let Some(message) = info.payload().downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "Box<dyn Any>"
+    };
+
+    // Capturing a backtrace allocates, it is only done if enabled with
+    // `RUST_BACKTRACE`
+    let backtrace = Backtrace::capture();
+    let backtrace =
+        (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string());
+
+    let encoded = GuestPanicked::encode_into(
+        &mut buffer[..],
+        message,
+        info.location(),
+        backtrace.as_deref(),
+    )
+    .or_else(|_| {
+        GuestPanicked::encode_into(
+            &mut buffer[..],
+            truncate(message, TRUNCATED_MESSAGE_LEN),
+            info.location(),
+            None,
+        )
+    });
+    let Ok(len) = encoded else {
+        return;
+    };
+
+    // SAFETY: This is safe because the buffer contains `len` bytes of an encoded
+    // host message.
+    unsafe { etheryal_extension_sys::send_message(len, buffer.as_ptr()) };
+}
+
+/// Truncates the string to at most `len` bytes, on a character boundary
+fn truncate(string: &str, len: usize) -> &str {
+    if string.len() <= len {
+        return string;
+    }
+    let mut end = len;
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+    &string[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use etheryal_extension_common::message::HostMessageEnum;
+
+    use super::*;
+    use crate::{test_host, EtheryalExtensionPlugin};
+
+    #[test]
+    fn test_report_panic_after_registration() {
+        let _host = test_host::lock();
+        let hook = panic::take_hook();
+        install_panic_hook();
+
+        let mut app = test_host::app(EtheryalExtensionPlugin::new(test_host::info()));
+        assert!(panic::catch_unwind(|| panic!("Panicked before the registration")).is_err());
+        assert!(test_host::take_sent().is_empty());
+        assert!(!test_host::trapped());
+
+        app.update();
+        test_host::take_sent();
+        assert!(panic::catch_unwind(|| panic!("Panicked after the registration")).is_err());
+        drop(panic::take_hook());
+        panic::set_hook(hook);
+
+        let sent = test_host::take_sent();
+        let [HostMessageEnum::GuestPanicked(panicked)] = sent.as_slice() else {
+            panic!("Expected a single panic report, got {sent:?}");
+        };
+        assert_eq!(panicked.message(), "Panicked after the registration");
+        assert!(!test_host::trapped());
+    }
+}
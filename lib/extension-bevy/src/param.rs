@@ -0,0 +1,70 @@
+//! A system parameter reading extension messages and replying to them
+use bevy_ecs::event::EventReader;
+use bevy_ecs::system::{Res, SystemParam};
+use etheryal_extension_common::message::{GuestMessage, HostMessage, MessageTag};
+use serde::Serialize;
+
+use crate::error::ExtensionError;
+use crate::{ExtensionEvent, ExtensionGuest};
+
+/// A [SystemParam] reading the messages of type `T` received from the
+/// extension host, which can also send messages back to the host
+///
+/// ```ignore
+/// fn on_ping(mut pings: ExtensionMessages<Ping>) {
+///     for (_, guest) in pings.iter_with_guest() {
+///         guest.send_message(Pong).ok();
+///     }
+/// }
+/// ```
+#[derive(SystemParam)]
+pub struct ExtensionMessages<'w, 's, T>
+where
+    T: GuestMessage, {
+    reader: EventReader<'w, 's, ExtensionEvent<T>>,
+    guest: Res<'w, ExtensionGuest>,
+}
+
+impl<'w, 's, T> ExtensionMessages<'w, 's, T>
+where
+    T: GuestMessage,
+{
+    /// Iterates over the messages received since the system last ran
+    pub fn iter(&mut self) -> impl Iterator<Item = &T> {
+        self.reader.iter().map(|event| &**event)
+    }
+
+    /// Iterates over the messages received since the system last ran, along
+    /// with the [ExtensionGuest] to reply to them
+    pub fn iter_with_guest(&mut self) -> impl Iterator<Item = (&T, &ExtensionGuest)> {
+        let guest = &*self.guest;
+        self.reader.iter().map(move |event| (&**event, guest))
+    }
+
+    /// Returns the number of messages received since the system last ran
+    pub fn len(&self) -> usize {
+        self.reader.len()
+    }
+
+    /// Returns whether no message was received since the system last ran
+    pub fn is_empty(&self) -> bool {
+        self.reader.is_empty()
+    }
+
+    /// Marks every received message as read
+    pub fn clear(&mut self) {
+        self.reader.clear();
+    }
+
+    /// Returns the [ExtensionGuest] to interact with the extension host
+    pub fn guest(&self) -> &ExtensionGuest {
+        &self.guest
+    }
+
+    /// Sends a message to the extension host
+    pub fn reply<H>(&self, message: H) -> Result<(), ExtensionError>
+    where
+        H: HostMessage + MessageTag + Serialize, {
+        self.guest.send_message(message)
+    }
+}
@@ -0,0 +1,207 @@
+//! Bounded queues of the messages received from the extension host, waiting to
+//! be dispatched as events
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use bevy_ecs::world::World;
+use crossbeam_queue::ArrayQueue;
+use etheryal_extension_common::message::registry::GuestMessageRegistration;
+use etheryal_extension_common::message::BoxedMessage;
+
+/// The default number of received messages of a single type waiting to be
+/// dispatched
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// What happens to a message received while the queue of its type is full, or
+/// for [OverflowPolicy::KeepLatest] to every received message
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum OverflowPolicy {
+    /// Drops the oldest message in the queue to make room for the new one
+    #[default]
+    DropOldest,
+    /// Drops the new message
+    DropNewest,
+    /// Coalesces the messages, every new message replacing the queued one, for
+    /// messages where only the latest one matters
+    KeepLatest,
+}
+
+/// The capacity and overflow policy of the queue of a guest message type
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MessageQueueConfig {
+    /// The number of received messages waiting to be dispatched
+    pub capacity: usize,
+    /// What happens to a message received while the queue is full
+    pub overflow_policy: OverflowPolicy,
+}
+
+impl MessageQueueConfig {
+    /// Creates a new queue configuration
+    pub fn new(capacity: usize, overflow_policy: OverflowPolicy) -> Self {
+        Self {
+            capacity,
+            overflow_policy,
+        }
+    }
+}
+
+impl Default for MessageQueueConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUEUE_CAPACITY, OverflowPolicy::default())
+    }
+}
+
+/// The queue of received messages of a single guest message type
+pub(crate) struct GuestMessageQueue {
+    pub(crate) type_name: &'static str,
+    pub(crate) tag: &'static str,
+    pub(crate) messages: ArrayQueue<BoxedMessage>,
+    registration: &'static GuestMessageRegistration,
+    overflow_policy: OverflowPolicy,
+    dropped: AtomicU64,
+    peak_depth: AtomicUsize,
+}
+
+impl GuestMessageQueue {
+    /// Creates the queue of the registered guest message
+    pub(crate) fn new(
+        registration: &'static GuestMessageRegistration, config: MessageQueueConfig,
+    ) -> Self {
+        Self {
+            type_name: registration.type_name(),
+            tag: registration.tag(),
+            messages: ArrayQueue::new(config.capacity.max(1)),
+            registration,
+            overflow_policy: config.overflow_policy,
+            dropped: AtomicU64::new(0),
+            peak_depth: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sends the message as an [ExtensionEvent] of the message type of this
+    /// queue
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not of the message type of this
+    /// queue
+    pub(crate) fn send_event(
+        &self, world: &mut World, message: BoxedMessage,
+    ) -> Result<(), &'static str> {
+        self.registration.send_event(world, message)
+    }
+
+    /// Queues the message, applying the overflow policy if the queue is full,
+    /// or replacing the queued message if it coalesces its messages
+    pub(crate) fn push(&self, message: BoxedMessage) {
+        let dropped = match self.overflow_policy {
+            OverflowPolicy::DropOldest => u64::from(self.messages.force_push(message).is_some()),
+            OverflowPolicy::DropNewest => u64::from(self.messages.push(message).is_err()),
+            OverflowPolicy::KeepLatest => {
+                let mut dropped = 0;
+                while self.messages.pop().is_some() {
+                    dropped += 1;
+                }
+                dropped + u64::from(self.messages.force_push(message).is_some())
+            },
+        };
+        if dropped > 0 {
+            self.dropped.fetch_add(dropped, Ordering::Relaxed);
+        }
+        self.peak_depth
+            .fetch_max(self.messages.len(), Ordering::Relaxed);
+    }
+
+    /// Returns the most messages waiting to be dispatched since the last call
+    /// to [GuestMessageQueue::reset_peak_depth]
+    pub(crate) fn peak_depth(&self) -> usize {
+        self.peak_depth.load(Ordering::Relaxed)
+    }
+
+    /// Starts measuring the peak depth from the messages waiting to be
+    /// dispatched now
+    pub(crate) fn reset_peak_depth(&self) {
+        self.peak_depth
+            .store(self.messages.len(), Ordering::Relaxed);
+    }
+
+    /// Returns the number of messages dropped since the last call
+    pub(crate) fn take_dropped(&self) -> u64 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use etheryal_extension_common::message::log::{LogLevel, SetLogLevel};
+    use etheryal_extension_common::message::{registry, MessageTag};
+
+    use super::*;
+
+    fn queue(config: MessageQueueConfig) -> GuestMessageQueue {
+        let registration = registry::guest_message(SetLogLevel::TAG).unwrap();
+        GuestMessageQueue::new(registration, config)
+    }
+
+    /// Pushes messages of increasingly verbose levels in a queue of 2
+    /// messages, returning the queued levels and the number of dropped messages
+    fn overflow(overflow_policy: OverflowPolicy) -> (Vec<LogLevel>, u64) {
+        let queue = queue(MessageQueueConfig::new(2, overflow_policy));
+        for level in [LogLevel::Error, LogLevel::Warn, LogLevel::Info] {
+            queue.push(Box::new(SetLogLevel::new(level)));
+        }
+
+        let mut levels = Vec::new();
+        while let Some(message) = queue.messages.pop() {
+            let message = message.downcast::<SetLogLevel>().unwrap();
+            levels.push(*message.level());
+        }
+        (levels, queue.take_dropped())
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        assert_eq!(
+            overflow(OverflowPolicy::DropOldest),
+            (vec![LogLevel::Warn, LogLevel::Info], 1)
+        );
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        assert_eq!(
+            overflow(OverflowPolicy::DropNewest),
+            (vec![LogLevel::Error, LogLevel::Warn], 1)
+        );
+    }
+
+    #[test]
+    fn test_keep_latest() {
+        assert_eq!(
+            overflow(OverflowPolicy::KeepLatest),
+            (vec![LogLevel::Info], 2)
+        );
+    }
+
+    #[test]
+    fn test_keep_latest_below_capacity() {
+        let queue = queue(MessageQueueConfig::new(4, OverflowPolicy::KeepLatest));
+        queue.push(Box::new(SetLogLevel::new(LogLevel::Error)));
+        queue.push(Box::new(SetLogLevel::new(LogLevel::Warn)));
+        assert_eq!(queue.messages.len(), 1);
+        assert_eq!(queue.peak_depth(), 1);
+        assert_eq!(queue.take_dropped(), 1);
+        let message = queue.messages.pop().unwrap();
+        let message = message.downcast::<SetLogLevel>().unwrap();
+        assert_eq!(*message.level(), LogLevel::Warn);
+    }
+
+    #[test]
+    fn test_take_dropped() {
+        let queue = queue(MessageQueueConfig::new(1, OverflowPolicy::DropNewest));
+        assert_eq!(queue.tag, "set_log_level");
+        queue.push(Box::new(SetLogLevel::new(LogLevel::Error)));
+        queue.push(Box::new(SetLogLevel::new(LogLevel::Warn)));
+        assert_eq!(queue.take_dropped(), 1);
+        assert_eq!(queue.take_dropped(), 0);
+    }
+}
@@ -0,0 +1,24 @@
+//! The system sets the messages exchanged with the extension host are
+//! processed in
+use bevy_ecs::schedule::SystemSet;
+
+/// The phases of the messages exchanged with the extension host, which always
+/// run in this order
+///
+/// [Receive](ExtensionSet::Receive) and [Dispatch](ExtensionSet::Dispatch) run
+/// in `PreUpdate` and [Flush](ExtensionSet::Flush) runs in `PostUpdate` by
+/// default, see
+/// [EtheryalExtensionPlugin::with_schedule](crate::EtheryalExtensionPlugin::with_schedule)
+/// and
+/// [EtheryalExtensionPlugin::with_flush_schedule](crate::EtheryalExtensionPlugin::with_flush_schedule).
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ExtensionSet {
+    /// Reads the messages sent by the extension host into the message queues
+    Receive,
+    /// Sends the queued messages as [ExtensionEvent](crate::ExtensionEvent)s
+    /// and updates the [ExtensionState](crate::ExtensionState)
+    Dispatch,
+    /// Sends the [ToHost](crate::ToHost) events to the extension host once the
+    /// update is done
+    Flush,
+}
@@ -0,0 +1,109 @@
+//! Shuts the extension guest down gracefully when the extension host sends a
+//! `ShutdownGuest` message
+use bevy_app::AppExit;
+use bevy_ecs::prelude::*;
+use etheryal_extension_common::message::events::{ShutdownAcknowledged, ShutdownGuest};
+use tracing::{error, info};
+
+use crate::{ExtensionEvent, ExtensionGuest, ExtensionState};
+
+/// The default number of updates run after the one a shutdown is requested in
+pub const DEFAULT_SHUTDOWN_GRACE_TICKS: u32 = 1;
+
+/// The number of updates run in [ExtensionState::ShuttingDown] after the one
+/// the shutdown was requested in, before the extension guest exits
+#[derive(Resource, Clone, Copy, Debug)]
+pub(crate) struct ShutdownGracePeriod {
+    pub(crate) ticks: u32,
+}
+
+/// The number of updates left before the extension guest exits
+#[derive(Resource, Debug)]
+pub(crate) struct ShutdownCountdown {
+    remaining: u32,
+}
+
+pub(crate) fn request_shutdown(
+    mut events: EventReader<ExtensionEvent<ShutdownGuest>>, state: Res<State<ExtensionState>>,
+    mut next_state: ResMut<NextState<ExtensionState>>,
+) {
+    if events.iter().count() == 0 || *state.get() == ExtensionState::ShuttingDown {
+        return;
+    }
+
+    info!("The extension host requested the extension guest to shut down");
+    next_state.set(ExtensionState::ShuttingDown);
+}
+
+pub(crate) fn start_shutdown_countdown(
+    mut commands: Commands, grace_period: Res<ShutdownGracePeriod>,
+) {
+    commands.insert_resource(ShutdownCountdown {
+        remaining: grace_period.ticks,
+    });
+}
+
+pub(crate) fn shutdown_countdown(
+    mut countdown: ResMut<ShutdownCountdown>, guest: Res<ExtensionGuest>,
+    mut exit: EventWriter<AppExit>,
+) {
+    // The countdown starts in the update the shutdown was requested in
+    if countdown.remaining > 0 {
+        countdown.remaining -= 1;
+        return;
+    }
+
+    if let Err(err) = guest.send_message(ShutdownAcknowledged) {
+        error!("Failed to acknowledge the shutdown to the extension host: {err}");
+    }
+    info!("Extension guest shut down");
+    exit.send(AppExit);
+}
+
+#[cfg(test)]
+mod tests {
+    use etheryal_extension_common::message::events::ExtensionRegistered;
+    use etheryal_extension_common::message::HostMessageEnum;
+
+    use super::*;
+    use crate::{test_host, EtheryalExtensionPlugin};
+
+    /// Returns the number of updates run after the one the shutdown was
+    /// requested in, before the extension guest exits
+    fn updates_after_shutdown(grace_ticks: u32) -> u32 {
+        let mut app = test_host::app(
+            EtheryalExtensionPlugin::new(test_host::info()).with_shutdown_grace_ticks(grace_ticks),
+        );
+        test_host::queue(ExtensionRegistered);
+        app.update();
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<ExtensionState>>().get(),
+            ExtensionState::Running
+        );
+
+        test_host::queue(ShutdownGuest);
+        app.update();
+        let mut updates = 0;
+        while !test_host::exited(&app) {
+            assert!(updates < 10, "the extension guest never exited");
+            app.update();
+            updates += 1;
+        }
+
+        let sent = test_host::take_sent();
+        assert!(matches!(
+            sent.last(),
+            Some(HostMessageEnum::ShutdownAcknowledged(_))
+        ));
+        updates
+    }
+
+    #[test]
+    fn test_shutdown_grace_ticks() {
+        let _host = test_host::lock();
+        assert_eq!(updates_after_shutdown(0), 0);
+        assert_eq!(updates_after_shutdown(1), 1);
+        assert_eq!(updates_after_shutdown(3), 3);
+    }
+}
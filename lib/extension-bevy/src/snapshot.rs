@@ -0,0 +1,496 @@
+//! Persists the resources and components of the extension guest across the
+//! reloads of the extension, with the `SnapshotRequest` and `RestoreSnapshot`
+//! messages
+use std::collections::BTreeMap;
+
+use bevy_app::App;
+use bevy_ecs::prelude::*;
+use bevy_ecs::world::EntityMut;
+use etheryal_extension_common::message::snapshot::{
+    GuestSnapshot, PersistedValues, RestoreSnapshot, Snapshot, SnapshotRequest,
+};
+use semver::{Version, VersionReq};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::error::ExtensionError;
+use crate::{ExtensionEvent, ExtensionGuest, ExtensionState};
+
+/// A resource or component kept across the reloads of the extension,
+/// registered with [PersistApp]
+///
+/// ```ignore
+/// #[derive(Resource, Serialize, Deserialize)]
+/// struct Score(u32);
+///
+/// impl Persist for Score {
+///     const KEY: &'static str = "score";
+/// }
+///
+/// app.persist_resource::<Score>();
+/// ```
+pub trait Persist: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The key of the value in the snapshots, which must not change between
+    /// the versions of the extension
+    const KEY: &'static str;
+}
+
+/// Upgrades a snapshot taken by another version of the extension, registered
+/// with [PersistApp::add_snapshot_migration]
+pub type SnapshotMigration = fn(&mut Snapshot) -> Result<(), ExtensionError>;
+
+/// Registers the state kept across the reloads of the extension, once the
+/// [EtheryalExtensionPlugin](crate::EtheryalExtensionPlugin) is added
+///
+/// Before the extension is reloaded, the persistent resources and components
+/// are sent to the extension host in a snapshot, which is restored in the new
+/// instance before it enters [ExtensionState::Running]. An extension without
+/// persistent resources or components doesn't send snapshots.
+pub trait PersistApp {
+    /// Keeps the resource `R` across the reloads of the extension
+    fn persist_resource<R>(&mut self) -> &mut Self
+    where
+        R: Persist + Resource;
+
+    /// Keeps the component `C` across the reloads of the extension, restored
+    /// on new entities with the other persistent components of their entity
+    ///
+    /// Entities referenced by the component are not remapped.
+    fn persist_component<C>(&mut self) -> &mut Self
+    where
+        C: Persist + Component;
+
+    /// Migrates the snapshots taken by the versions of the extension matching
+    /// `from` before they are restored
+    ///
+    /// The migrations matching the version of a snapshot taken by another
+    /// version of the extension run in the order they were added.
+    fn add_snapshot_migration(
+        &mut self, from: VersionReq, migration: SnapshotMigration,
+    ) -> &mut Self;
+}
+
+impl PersistApp for App {
+    fn persist_resource<R>(&mut self) -> &mut Self
+    where
+        R: Persist + Resource, {
+        self.world
+            .resource_mut::<PersistRegistry>()
+            .add_resource::<R>();
+        self
+    }
+
+    fn persist_component<C>(&mut self) -> &mut Self
+    where
+        C: Persist + Component, {
+        self.world
+            .resource_mut::<PersistRegistry>()
+            .add_component::<C>();
+        self
+    }
+
+    fn add_snapshot_migration(
+        &mut self, from: VersionReq, migration: SnapshotMigration,
+    ) -> &mut Self {
+        self.world
+            .resource_mut::<PersistRegistry>()
+            .add_migration(from, migration);
+        self
+    }
+}
+
+/// The persistent resources and components of the extension guest, and the
+/// migrations of the snapshots taken by other versions
+#[derive(Resource, Default)]
+pub(crate) struct PersistRegistry {
+    resources: Vec<PersistentResource>,
+    components: Vec<PersistentComponent>,
+    migrations: Vec<(VersionReq, SnapshotMigration)>,
+}
+
+#[derive(Clone, Copy)]
+struct PersistentResource {
+    key: &'static str,
+    save: fn(&World, &mut PersistedValues) -> Result<(), ExtensionError>,
+    restore: fn(&mut World, &PersistedValues) -> Result<(), ExtensionError>,
+}
+
+#[derive(Clone, Copy)]
+struct PersistentComponent {
+    key: &'static str,
+    save: fn(&mut World, &mut BTreeMap<Entity, PersistedValues>) -> Result<(), ExtensionError>,
+    restore: fn(&mut EntityMut, &PersistedValues) -> Result<(), ExtensionError>,
+}
+
+impl PersistRegistry {
+    fn add_resource<R>(&mut self)
+    where
+        R: Persist + Resource, {
+        self.resources.push(PersistentResource {
+            key: R::KEY,
+            save: save_resource::<R>,
+            restore: restore_resource::<R>,
+        });
+    }
+
+    fn add_component<C>(&mut self)
+    where
+        C: Persist + Component, {
+        self.components.push(PersistentComponent {
+            key: C::KEY,
+            save: save_component::<C>,
+            restore: restore_component::<C>,
+        });
+    }
+
+    fn add_migration(&mut self, from: VersionReq, migration: SnapshotMigration) {
+        self.migrations.push((from, migration));
+    }
+
+    /// Returns whether the extension persists no resource or component
+    fn is_empty(&self) -> bool {
+        self.resources.is_empty() && self.components.is_empty()
+    }
+
+    fn take(&self, world: &mut World, version: Version) -> Result<Snapshot, ExtensionError> {
+        let mut snapshot = Snapshot::new(version);
+        for resource in &self.resources {
+            (resource.save)(world, snapshot.resources_mut())?;
+        }
+
+        let mut entities = BTreeMap::new();
+        for component in &self.components {
+            (component.save)(world, &mut entities)?;
+        }
+        snapshot.entities_mut().extend(entities.into_values());
+        Ok(snapshot)
+    }
+
+    fn restore(
+        &self, world: &mut World, mut snapshot: Snapshot, version: &Version,
+    ) -> Result<(), ExtensionError> {
+        if snapshot.version() != version {
+            let migrations: Vec<_> = self
+                .migrations
+                .iter()
+                .filter(|(from, _)| from.matches(snapshot.version()))
+                .collect();
+            if migrations.is_empty() {
+                warn!(
+                    "No migration of the snapshot of version {} to version {version}, restoring \
+                     it as is",
+                    snapshot.version()
+                );
+            }
+            for (_, migrate) in migrations {
+                migrate(&mut snapshot)?;
+            }
+        }
+
+        // A value which can't be decoded anymore is skipped
+        for resource in &self.resources {
+            if let Err(err) = (resource.restore)(world, snapshot.resources()) {
+                warn!("Failed to restore the resource '{}': {err}", resource.key);
+            }
+        }
+        for values in snapshot.entities() {
+            let mut entity = world.spawn_empty();
+            for component in &self.components {
+                if let Err(err) = (component.restore)(&mut entity, values) {
+                    warn!("Failed to restore the component '{}': {err}", component.key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn save_resource<R>(world: &World, values: &mut PersistedValues) -> Result<(), ExtensionError>
+where
+    R: Persist + Resource, {
+    if let Some(resource) = world.get_resource::<R>() {
+        values.insert(R::KEY, resource)?;
+    }
+    Ok(())
+}
+
+fn restore_resource<R>(world: &mut World, values: &PersistedValues) -> Result<(), ExtensionError>
+where
+    R: Persist + Resource, {
+    if let Some(resource) = values.get::<R>(R::KEY) {
+        world.insert_resource(resource?);
+    }
+    Ok(())
+}
+
+fn save_component<C>(
+    world: &mut World, entities: &mut BTreeMap<Entity, PersistedValues>,
+) -> Result<(), ExtensionError>
+where
+    C: Persist + Component, {
+    let mut components = world.query::<(Entity, &C)>();
+    for (entity, component) in components.iter(world) {
+        entities
+            .entry(entity)
+            .or_default()
+            .insert(C::KEY, component)?;
+    }
+    Ok(())
+}
+
+fn restore_component<C>(
+    entity: &mut EntityMut, values: &PersistedValues,
+) -> Result<(), ExtensionError>
+where
+    C: Persist + Component, {
+    if let Some(component) = values.get::<C>(C::KEY) {
+        entity.insert(component?);
+    }
+    Ok(())
+}
+
+pub(crate) fn take_snapshot(
+    mut commands: Commands, mut requests: EventReader<ExtensionEvent<SnapshotRequest>>,
+    registry: Res<PersistRegistry>,
+) {
+    if requests.iter().count() == 0 || registry.is_empty() {
+        return;
+    }
+
+    commands.add(|world: &mut World| {
+        world.resource_scope(|world, registry: Mut<PersistRegistry>| {
+            let version = world.resource::<ExtensionGuest>().info().version().clone();
+            let sent = registry.take(world, version).and_then(|snapshot| {
+                world
+                    .resource::<ExtensionGuest>()
+                    .send_message(GuestSnapshot::new(snapshot))
+            });
+            if let Err(err) = sent {
+                error!("Failed to send the snapshot to the extension host: {err}");
+            }
+        });
+    });
+}
+
+pub(crate) fn restore_snapshot(
+    mut commands: Commands, mut restores: EventReader<ExtensionEvent<RestoreSnapshot>>,
+    state: Res<State<ExtensionState>>,
+) {
+    for restore in restores.iter() {
+        // The systems of the extension start from the restored state
+        if matches!(
+            state.get(),
+            ExtensionState::Running | ExtensionState::ShuttingDown
+        ) {
+            warn!("Ignored a snapshot received after the registration of the extension guest");
+            continue;
+        }
+
+        let snapshot = restore.snapshot().clone();
+        commands.add(|world: &mut World| {
+            world.resource_scope(|world, registry: Mut<PersistRegistry>| {
+                let version = world.resource::<ExtensionGuest>().info().version().clone();
+                let from = snapshot.version().clone();
+                match registry.restore(world, snapshot, &version) {
+                    Ok(()) => info!("Restored the snapshot of version {from}"),
+                    Err(err) => error!("Failed to migrate the snapshot of version {from}: {err}"),
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use etheryal_extension_common::message::events::ExtensionRegistered;
+    use etheryal_extension_common::message::HostMessageEnum;
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{test_host, EtheryalExtensionPlugin};
+
+    #[derive(Resource, Serialize, Deserialize, PartialEq, Debug)]
+    struct Score(u32);
+
+    impl Persist for Score {
+        const KEY: &'static str = "score";
+    }
+
+    #[derive(Component, Serialize, Deserialize, PartialEq, Debug)]
+    struct Position(i32, i32);
+
+    impl Persist for Position {
+        const KEY: &'static str = "position";
+    }
+
+    #[derive(Component, Serialize, Deserialize, PartialEq, Debug)]
+    struct Name(String);
+
+    impl Persist for Name {
+        const KEY: &'static str = "name";
+    }
+
+    fn registry() -> PersistRegistry {
+        let mut registry = PersistRegistry::default();
+        registry.add_resource::<Score>();
+        registry.add_component::<Position>();
+        registry.add_component::<Name>();
+        registry
+    }
+
+    /// Returns the persistent components of each entity of the world, sorted
+    fn entities(world: &mut World) -> Vec<(Option<&Position>, Option<&Name>)> {
+        let mut entities: Vec<_> = world
+            .query::<(Option<&Position>, Option<&Name>)>()
+            .iter(world)
+            .collect();
+        entities.sort_by_key(|(position, _)| position.map(|position| position.0));
+        entities
+    }
+
+    #[test]
+    fn test_take_and_restore() {
+        let registry = registry();
+        let mut world = World::new();
+        world.insert_resource(Score(42));
+        world.spawn((Position(1, 2), Name("first".into())));
+        world.spawn(Position(3, 4));
+        world.spawn(Name("second".into()));
+
+        let snapshot = registry.take(&mut world, Version::new(1, 0, 0)).unwrap();
+        assert_eq!(snapshot.entities().len(), 3);
+
+        let mut restored = World::new();
+        registry
+            .restore(&mut restored, snapshot, &Version::new(1, 0, 0))
+            .unwrap();
+        assert_eq!(restored.get_resource::<Score>(), Some(&Score(42)));
+
+        // The components of an entity are restored together
+        assert_eq!(entities(&mut restored), [
+            (None, Some(&Name("second".into()))),
+            (Some(&Position(1, 2)), Some(&Name("first".into()))),
+            (Some(&Position(3, 4)), None),
+        ]);
+    }
+
+    #[test]
+    fn test_restore_migrated_snapshot() {
+        let mut registry = registry();
+        // The score was a string before version 1.0.0
+        registry.add_migration(VersionReq::parse("<1.0.0").unwrap(), |snapshot| {
+            let score = snapshot
+                .resources()
+                .get::<String>("score")
+                .transpose()?
+                .unwrap_or_default();
+            let score = Score(score.parse().unwrap_or_default());
+            snapshot.resources_mut().insert(Score::KEY, &score)?;
+            Ok(())
+        });
+        registry.add_migration(VersionReq::parse(">=1.0.0").unwrap(), |_| {
+            panic!("The migration of another version ran")
+        });
+
+        let mut snapshot = Snapshot::new(Version::new(0, 9, 0));
+        snapshot
+            .resources_mut()
+            .insert("score", &"42".to_string())
+            .unwrap();
+
+        let mut world = World::new();
+        registry
+            .restore(&mut world, snapshot, &Version::new(1, 0, 0))
+            .unwrap();
+        assert_eq!(world.get_resource::<Score>(), Some(&Score(42)));
+    }
+
+    #[test]
+    fn test_restore_undecodable_value() {
+        let mut snapshot = Snapshot::new(Version::new(1, 0, 0));
+        snapshot
+            .resources_mut()
+            .insert("score", &"not a score")
+            .unwrap();
+        let mut entity = PersistedValues::default();
+        entity.insert("position", &(5, 6)).unwrap();
+        entity.insert("name", &3).unwrap();
+        snapshot.entities_mut().push(entity);
+
+        let mut world = World::new();
+        registry()
+            .restore(&mut world, snapshot, &Version::new(1, 0, 0))
+            .unwrap();
+        assert!(world.get_resource::<Score>().is_none());
+        assert_eq!(entities(&mut world), [(Some(&Position(5, 6)), None)]);
+    }
+
+    fn app() -> App {
+        let mut app = test_host::app(EtheryalExtensionPlugin::new(test_host::info()));
+        app.persist_resource::<Score>();
+        app
+    }
+
+    fn snapshot(score: u32) -> Snapshot {
+        let mut snapshot = Snapshot::new(test_host::info().version().clone());
+        snapshot
+            .resources_mut()
+            .insert(Score::KEY, &Score(score))
+            .unwrap();
+        snapshot
+    }
+
+    #[test]
+    fn test_restore_before_registration() {
+        let _host = test_host::lock();
+        let mut app = app();
+        test_host::queue(RestoreSnapshot::new(snapshot(7)));
+        test_host::queue(ExtensionRegistered);
+        app.update();
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<ExtensionState>>().get(),
+            ExtensionState::Running
+        );
+        assert_eq!(app.world.get_resource::<Score>(), Some(&Score(7)));
+
+        // The restored state is sent back in the next snapshot
+        test_host::take_sent();
+        test_host::queue(SnapshotRequest);
+        app.update();
+        let sent = test_host::take_sent();
+        let snapshot = sent
+            .into_iter()
+            .find_map(|message| match message {
+                HostMessageEnum::GuestSnapshot(snapshot) => Some(snapshot.into_snapshot()),
+                _ => None,
+            })
+            .expect("snapshot sent");
+        assert_eq!(
+            snapshot
+                .resources()
+                .get::<Score>(Score::KEY)
+                .unwrap()
+                .unwrap(),
+            Score(7)
+        );
+    }
+
+    #[test]
+    fn test_ignore_restore_after_registration() {
+        let _host = test_host::lock();
+        let mut app = app();
+        test_host::queue(ExtensionRegistered);
+        app.update();
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<ExtensionState>>().get(),
+            ExtensionState::Running
+        );
+
+        test_host::queue(RestoreSnapshot::new(snapshot(7)));
+        app.update();
+        assert!(app.world.get_resource::<Score>().is_none());
+    }
+}
@@ -0,0 +1,80 @@
+//! The lifecycle of the extension guest, from its registration with the
+//! extension host to its shutdown
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bevy_app::AppExit;
+use bevy_ecs::prelude::*;
+use etheryal_extension_common::message::events::ExtensionRegistered;
+use tracing::{error, info};
+
+use crate::error::ExtensionError;
+use crate::{log, ExtensionEvent, ExtensionGuest};
+
+/// Whether the extension info was sent to the extension host, which traps any
+/// other call to the host before it
+pub(crate) static REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether the extension info was sent to the extension host, so
+/// messages can be sent to it
+pub(crate) fn is_registered() -> bool {
+    REGISTERED.load(Ordering::Acquire)
+}
+
+/// The lifecycle state of the extension guest, managed by the
+/// [EtheryalExtensionPlugin](crate::EtheryalExtensionPlugin)
+///
+/// Systems that exchange messages with the extension host should run with
+/// `run_if(in_state(ExtensionState::Running))`, or on
+/// `OnEnter(ExtensionState::Running)` for one-time setup.
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ExtensionState {
+    /// The extension info has not been sent to the extension host yet
+    #[default]
+    Registering,
+    /// The extension info was sent, and the extension guest is waiting for
+    /// the extension host to confirm its registration
+    Handshaking,
+    /// The extension host confirmed the registration of the extension guest
+    Running,
+    /// The extension host requested the extension guest to shut down, the
+    /// extension exits at the end of the grace period
+    ShuttingDown,
+}
+
+pub(crate) fn register_extension(
+    guest: Res<ExtensionGuest>, mut next_state: ResMut<NextState<ExtensionState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    match send_extension_info(&guest) {
+        Ok(()) => {
+            log::forward_pending_records();
+            next_state.set(ExtensionState::Handshaking);
+        },
+        Err(err) => {
+            error!("Failed to send the extension info to the extension host: {err}");
+            exit.send(AppExit);
+        },
+    }
+}
+
+pub(crate) fn confirm_registration(
+    mut events: EventReader<ExtensionEvent<ExtensionRegistered>>,
+    mut next_state: ResMut<NextState<ExtensionState>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    info!("The extension host confirmed the registration of the extension guest");
+    next_state.set(ExtensionState::Running);
+}
+
+fn send_extension_info(guest: &ExtensionGuest) -> Result<(), ExtensionError> {
+    let encoded = rmp_serde::to_vec_named(guest.info())?;
+
+    // SAFETY: This is safe because the extension info is the first message sent
+    // to the extension host.
+    unsafe { etheryal_extension_sys::extension_info(encoded.len(), encoded.as_ptr()) };
+    REGISTERED.store(true, Ordering::Release);
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! Tracks the messages exchanged with the extension host
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use etheryal_extension_common::message::stats::{GuestStats, MessageTypeStats};
+
+/// The statistics of the messages exchanged since the extension started
+struct Stats {
+    messages: BTreeMap<&'static str, MessageTypeStats>,
+    encode_time: Duration,
+    decode_time: Duration,
+}
+
+/// The statistics are global, as messages are also sent outside of systems,
+/// e.g. by the [ExtensionLogLayer](crate::ExtensionLogLayer)
+static STATS: Mutex<Stats> = Mutex::new(Stats {
+    messages: BTreeMap::new(),
+    encode_time: Duration::ZERO,
+    decode_time: Duration::ZERO,
+});
+
+fn with_stats<R>(f: impl FnOnce(&mut Stats) -> R) -> R {
+    let mut stats = STATS.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&mut stats)
+}
+
+fn message_stats<'a>(
+    messages: &'a mut BTreeMap<&'static str, MessageTypeStats>, type_name: &'static str,
+) -> &'a mut MessageTypeStats {
+    messages
+        .entry(type_name)
+        .or_insert_with(|| MessageTypeStats::new(type_name.into()))
+}
+
+pub(crate) fn record_encode(encode_time: Duration) {
+    with_stats(|stats| stats.encode_time += encode_time);
+}
+
+pub(crate) fn record_decode(decode_time: Duration) {
+    with_stats(|stats| stats.decode_time += decode_time);
+}
+
+pub(crate) fn record_sent(type_name: &'static str, bytes: usize) {
+    with_stats(|stats| message_stats(&mut stats.messages, type_name).record_sent(bytes));
+}
+
+pub(crate) fn record_received(type_name: &'static str, bytes: usize) {
+    with_stats(|stats| message_stats(&mut stats.messages, type_name).record_received(bytes));
+}
+
+pub(crate) fn record_dropped(type_name: &'static str, count: u64) {
+    with_stats(|stats| message_stats(&mut stats.messages, type_name).record_dropped(count));
+}
+
+/// Returns the statistics of the messages, with the depth of the queues of
+/// received messages
+pub(crate) fn snapshot(queue_depths: impl Iterator<Item = (&'static str, usize)>) -> GuestStats {
+    with_stats(|stats| {
+        for (type_name, queue_depth) in queue_depths {
+            message_stats(&mut stats.messages, type_name).set_queue_depth(queue_depth);
+        }
+
+        GuestStats::new(
+            stats.messages.values().cloned().collect(),
+            duration_nanos(stats.encode_time),
+            duration_nanos(stats.decode_time),
+        )
+    })
+}
+
+fn duration_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
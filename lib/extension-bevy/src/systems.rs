@@ -1,23 +1,90 @@
+use std::time::Instant;
+
+use bevy_ecs::event::EventReader;
 use bevy_ecs::system::Res;
-use etheryal_extension_common::message::{GuestMessage, GuestMessageEnum};
-use tracing::{error, trace, warn};
+use bevy_ecs::world::{Mut, World};
+use etheryal_extension_common::message::limits::ResourceExhausted;
+use etheryal_extension_common::message::registry::{self, GuestMessageRegistration};
+use etheryal_extension_common::message::stats::MessagesDropped;
+use etheryal_extension_common::message::BoxedMessage;
+use tracing::{debug, error, trace, warn};
 
-use crate::ExtensionGuest;
+use crate::error::ExtensionSendError;
+use crate::{guest, stats, ExtensionEvent, ExtensionGuest};
 
 pub fn send_guest_message_events(guest: Res<ExtensionGuest>) {
-    while let Some(message) = read_message() {
-        if let Some(packets) = guest.guest_messages.get(&GuestMessage::type_id(&message)) {
-            packets.push(message)
+    // The queues are drained in `ExtensionSet::Dispatch`, so their depth is
+    // measured as they are filled
+    for queue in guest.guest_messages.iter() {
+        queue.reset_peak_depth();
+    }
+
+    while let Some((registration, message)) = read_message() {
+        if let Some(queue) = guest.guest_messages.get(&registration.type_id()) {
+            queue.push(message)
         } else {
             warn!(
                 "Received a guest message for an unregistered message type: {}",
-                message.type_name()
+                registration.type_name()
             );
         }
     }
 }
 
-fn read_message() -> Option<GuestMessageEnum> {
+pub fn report_dropped_messages(guest: Res<ExtensionGuest>) {
+    for queue in guest.guest_messages.iter() {
+        let dropped = queue.take_dropped();
+        if dropped == 0 {
+            continue;
+        }
+
+        let type_name = queue.type_name;
+        warn!("Dropped {dropped} '{type_name}' messages, the queue is full");
+        stats::record_dropped(type_name, dropped);
+
+        let message = MessagesDropped::new(queue.tag.into(), dropped, queue.messages.capacity());
+        if let Err(err) = guest.send_message(message) {
+            error!("Failed to report the dropped messages to the extension host: {err}");
+        }
+    }
+}
+
+pub fn log_exhausted_resources(mut exhausted: EventReader<ExtensionEvent<ResourceExhausted>>) {
+    for exhausted in exhausted.iter() {
+        warn!(
+            "The extension host dropped a message for this extension: {}",
+            **exhausted
+        );
+    }
+}
+
+pub fn send_message_events(world: &mut World) {
+    world.resource_scope(|world, guest: Mut<ExtensionGuest>| {
+        for queue in guest.guest_messages.iter() {
+            while let Some(message) = queue.messages.pop() {
+                if queue.send_event(world, message).is_err() {
+                    warn!("Failed to downcast guest message to '{}'", queue.type_name);
+                    continue;
+                }
+                debug!("Received an extension guest message: {}", queue.type_name);
+            }
+        }
+    });
+}
+
+pub fn send_to_host_events(world: &mut World) {
+    for registration in registry::host_messages() {
+        for message in registration.drain_to_host_events(world) {
+            if let Err(error) = guest::send_registered(registration, message.as_ref()) {
+                let type_name = registration.type_name();
+                error!("Failed to send '{type_name}' to the extension host: {error}");
+                world.send_event(ExtensionSendError { type_name, error });
+            }
+        }
+    }
+}
+
+fn read_message() -> Option<(&'static GuestMessageRegistration, BoxedMessage)> {
     // SAFETY: This is safe because the extension host will only allow calling this
     // function after setting the extension info.
     let len = unsafe { etheryal_extension_sys::recv_message() };
@@ -38,11 +105,116 @@ fn read_message() -> Option<GuestMessageEnum> {
         out.extend_from_slice(&buffer[..read]);
     }
 
-    match rmp_serde::from_slice::<GuestMessageEnum>(&buffer[..]) {
-        Ok(message) => Some(message),
+    let start = Instant::now();
+    let decoded = registry::decode_guest_message(&out);
+    stats::record_decode(start.elapsed());
+
+    match decoded {
+        Ok((registration, message)) => {
+            stats::record_received(registration.type_name(), len);
+            Some((registration, message))
+        },
         Err(err) => {
             error!("Failed to deserialize message: {err}");
             None
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::Update;
+    use bevy_ecs::event::EventWriter;
+    use etheryal_extension_common::message::debug::{Ping, Pong};
+    use etheryal_extension_common::message::HostMessageEnum;
+    use etheryal_extension_derive::ExtensionMessage;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::queue::{MessageQueueConfig, OverflowPolicy};
+    use crate::{test_host, EtheryalExtensionPlugin, ToHost};
+
+    /// A message derived outside of `etheryal-extension-common`, which is not a
+    /// variant of the message enums
+    #[derive(Serialize, Deserialize, Debug, PartialEq, JsonSchema, ExtensionMessage)]
+    #[extension_message(guest, host)]
+    struct Echo {
+        text: String,
+    }
+
+    fn echo(mut echoes: EventReader<ExtensionEvent<Echo>>, mut to_host: EventWriter<ToHost<Echo>>) {
+        for echo in echoes.iter() {
+            to_host.send(ToHost::new(Echo {
+                text: echo.text.clone(),
+            }));
+        }
+    }
+
+    #[test]
+    fn test_send_to_host_events() {
+        let _host = test_host::lock();
+        let mut app = test_host::app(EtheryalExtensionPlugin::new(test_host::info()));
+        app.update();
+        test_host::take_sent();
+
+        app.world.send_event(ToHost::new(Ping));
+        app.world.send_event(ToHost::new(Ping));
+        app.update();
+        let pings = test_host::take_sent()
+            .into_iter()
+            .filter(|message| matches!(message, HostMessageEnum::Ping(Ping)))
+            .count();
+        assert_eq!(pings, 2);
+
+        app.update();
+        assert!(test_host::take_sent()
+            .iter()
+            .all(|message| !matches!(message, HostMessageEnum::Ping(_))));
+    }
+
+    #[test]
+    fn test_external_message() {
+        let _host = test_host::lock();
+        let mut app = test_host::app(EtheryalExtensionPlugin::new(test_host::info()));
+        app.add_systems(Update, echo);
+        app.update();
+
+        test_host::queue(Echo {
+            text: "hello".into(),
+        });
+        app.update();
+        let echoes: Vec<_> = test_host::take_sent_registered()
+            .into_iter()
+            .filter_map(|(_, message)| message.downcast::<Echo>().ok())
+            .collect();
+        assert_eq!(echoes, [Box::new(Echo {
+            text: "hello".into()
+        })]);
+    }
+
+    #[test]
+    fn test_report_dropped_messages() {
+        let _host = test_host::lock();
+        let mut app = test_host::app(
+            EtheryalExtensionPlugin::new(test_host::info())
+                .with_message_queue::<Pong>(MessageQueueConfig::new(1, OverflowPolicy::DropNewest)),
+        );
+        app.update();
+
+        test_host::queue(Pong);
+        test_host::queue(Pong);
+        app.update();
+        let dropped: Vec<_> = test_host::take_sent()
+            .into_iter()
+            .filter_map(|message| match message {
+                HostMessageEnum::MessagesDropped(dropped) => Some(dropped),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].tag(), "pong");
+        assert_eq!(dropped[0].count(), 1);
+        assert_eq!(dropped[0].capacity(), 1);
+    }
+}
@@ -0,0 +1,166 @@
+//! Stands in for the extension host in the unit tests, recording the messages
+//! sent by the extension guest and replaying the messages queued for it
+//!
+//! The state is global, as the systems run on the threads of the task pools,
+//! so the tests using it hold the [lock] to run one at a time.
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use bevy_app::{App, AppExit};
+use bevy_ecs::event::Events;
+use etheryal_extension_common::message::registry::{self, HostMessageRegistration};
+use etheryal_extension_common::message::{
+    encode_message, BoxedMessage, HostMessageEnum, MessageTag,
+};
+use etheryal_extension_common::ExtensionModuleInfo;
+use etheryal_identifier::NamespacedIdentifier;
+use semver::Version;
+use serde::Serialize;
+
+use crate::{state, EtheryalExtensionPlugin};
+
+/// The state of the extension host
+struct Host {
+    registered: bool,
+    trapped: bool,
+    sent: Vec<Vec<u8>>,
+    inbox: VecDeque<Vec<u8>>,
+    received: Vec<u8>,
+}
+
+static HOST: Mutex<Host> = Mutex::new(Host {
+    registered: false,
+    trapped: false,
+    sent: Vec::new(),
+    inbox: VecDeque::new(),
+    received: Vec::new(),
+});
+
+static TEST: Mutex<()> = Mutex::new(());
+
+fn with_host<R>(f: impl FnOnce(&mut Host) -> R) -> R {
+    let mut host = HOST.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&mut host)
+}
+
+/// Waits for the other tests using the extension host to finish, and resets
+/// the extension host
+pub(crate) fn lock() -> MutexGuard<'static, ()> {
+    let guard = TEST.lock().unwrap_or_else(PoisonError::into_inner);
+    with_host(|host| {
+        host.registered = false;
+        host.trapped = false;
+        host.sent.clear();
+        host.inbox.clear();
+        host.received.clear();
+    });
+    state::REGISTERED.store(false, Ordering::Release);
+    guard
+}
+
+/// Returns the info of the extension used in the tests
+pub(crate) fn info() -> ExtensionModuleInfo {
+    ExtensionModuleInfo::builder()
+        .name("test".into())
+        .identifier(NamespacedIdentifier::try_from("test:extension").expect("valid identifier"))
+        .version(Version::new(1, 0, 0))
+        .dependencies(Vec::new())
+        .build()
+}
+
+/// Returns an app with the extension plugin, without the global panic hook and
+/// log forwarding
+pub(crate) fn app(plugin: EtheryalExtensionPlugin) -> App {
+    let mut app = App::new();
+    app.add_plugins(plugin.without_panic_hook().without_log_forwarding());
+    app
+}
+
+/// Returns whether the app sent an [AppExit] event in its last two updates
+pub(crate) fn exited(app: &App) -> bool {
+    !app.world.resource::<Events<AppExit>>().is_empty()
+}
+
+/// Queues a message for the extension guest
+pub(crate) fn queue(message: impl MessageTag + Serialize) {
+    let encoded = encode_message(&message).expect("encodable message");
+    with_host(|host| host.inbox.push_back(encoded));
+}
+
+/// Returns whether the extension guest called the host before sending its
+/// extension info, which traps in the real extension host
+pub(crate) fn trapped() -> bool {
+    with_host(|host| host.trapped)
+}
+
+/// Takes the messages sent by the extension guest
+pub(crate) fn take_sent() -> Vec<HostMessageEnum> {
+    take_sent_encoded()
+        .iter()
+        .map(|encoded| rmp_serde::from_slice(encoded).expect("valid host message"))
+        .collect()
+}
+
+/// Takes the messages sent by the extension guest, decoded with their
+/// registration, including the messages derived outside of
+/// `etheryal-extension-common`
+pub(crate) fn take_sent_registered() -> Vec<(&'static HostMessageRegistration, BoxedMessage)> {
+    take_sent_encoded()
+        .iter()
+        .map(|encoded| registry::decode_host_message(encoded).expect("registered host message"))
+        .collect()
+}
+
+fn take_sent_encoded() -> Vec<Vec<u8>> {
+    with_host(|host| std::mem::take(&mut host.sent))
+}
+
+fn check_registered(host: &mut Host) {
+    if !host.registered {
+        host.trapped = true;
+    }
+}
+
+#[no_mangle]
+extern "C" fn extension_info(_len: usize, _ptr: *const u8) {
+    with_host(|host| host.registered = true);
+}
+
+/// # Safety
+///
+/// `ptr` must be a valid pointer to a buffer of length `len`.
+#[no_mangle]
+unsafe extern "C" fn send_message(len: usize, ptr: *const u8) {
+    // SAFETY: The caller guarantees that the buffer is valid.
+    let encoded = unsafe { std::slice::from_raw_parts(ptr, len) };
+    with_host(|host| {
+        check_registered(host);
+        host.sent.push(encoded.to_vec());
+    });
+}
+
+#[no_mangle]
+extern "C" fn recv_message() -> usize {
+    with_host(|host| {
+        check_registered(host);
+        host.received = host.inbox.pop_front().unwrap_or_default();
+        host.received.len()
+    })
+}
+
+/// # Safety
+///
+/// `ptr` must be a valid pointer to a buffer of length `len`.
+#[no_mangle]
+unsafe extern "C" fn read_message_buf(len: usize, ptr: *mut u8) -> usize {
+    with_host(|host| {
+        check_registered(host);
+        let read = len.min(host.received.len());
+        // SAFETY: The caller guarantees that the buffer is valid, and at most
+        // `len` bytes are written.
+        unsafe { std::ptr::copy_nonoverlapping(host.received.as_ptr(), ptr, read) };
+        host.received.drain(..read);
+        read
+    })
+}
@@ -0,0 +1,190 @@
+//! Runs the extension guest when the extension host calls the `etheryal_tick`
+//! export, instead of on its own clock
+use std::cell::RefCell;
+use std::time::Duration;
+
+use bevy_app::{App, AppExit};
+use bevy_ecs::event::{Events, ManualEventReader};
+use bevy_ecs::system::Resource;
+use tracing::{info, warn};
+
+thread_local! {
+    static HOST_DRIVEN_APP: RefCell<Option<HostDrivenApp>> = const { RefCell::new(None) };
+}
+
+/// The tick of the extension host the current update runs in
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct HostTick {
+    delta: Duration,
+    count: u64,
+}
+
+impl HostTick {
+    /// Returns the time elapsed since the previous tick, as given by the
+    /// extension host
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// Returns the number of ticks run since the extension started, including
+    /// this one
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// The app run by the extension host, kept between ticks
+struct HostDrivenApp {
+    app: App,
+    finished: bool,
+    app_exit_reader: ManualEventReader<AppExit>,
+}
+
+/// Keeps the app until the extension host calls the `etheryal_tick` export
+pub(crate) fn host_tick_runner(app: App) {
+    HOST_DRIVEN_APP.with(|host_driven_app| {
+        *host_driven_app.borrow_mut() = Some(HostDrivenApp {
+            app,
+            finished: false,
+            app_exit_reader: ManualEventReader::default(),
+        });
+    });
+}
+
+/// Runs a single update of the app, called by the `etheryal_tick` export
+/// generated by `#[etheryal_extension::main(host_tick)]`
+///
+/// The app is dropped once it sends an [AppExit] event, and later ticks are
+/// ignored.
+pub fn tick(delta_nanos: u64) {
+    HOST_DRIVEN_APP.with(|host_driven_app| {
+        let mut host_driven_app = host_driven_app.borrow_mut();
+        let Some(HostDrivenApp {
+            app,
+            finished,
+            app_exit_reader,
+        }) = host_driven_app.as_mut()
+        else {
+            warn!("The extension host ticked an extension guest which is not running");
+            return;
+        };
+
+        if !*finished {
+            if !app.ready() {
+                return;
+            }
+            app.finish();
+            app.cleanup();
+            *finished = true;
+        }
+
+        let mut host_tick = app.world.resource_mut::<HostTick>();
+        host_tick.delta = Duration::from_nanos(delta_nanos);
+        host_tick.count += 1;
+
+        app.update();
+
+        let exited = app
+            .world
+            .get_resource::<Events<AppExit>>()
+            .is_some_and(|events| app_exit_reader.iter(events).last().is_some());
+        if exited {
+            info!("The extension guest exited");
+            *host_driven_app = None;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use bevy_app::{Plugin, Update};
+    use bevy_ecs::event::EventWriter;
+    use bevy_ecs::system::Res;
+
+    use super::*;
+
+    /// A plugin which is ready once `ready` is set, counting the calls to
+    /// [Plugin::finish]
+    struct WaitingPlugin {
+        ready: Arc<AtomicBool>,
+        finished: Arc<AtomicUsize>,
+    }
+
+    impl Plugin for WaitingPlugin {
+        fn build(&self, _app: &mut App) {}
+
+        fn ready(&self, _app: &App) -> bool {
+            self.ready.load(Ordering::Relaxed)
+        }
+
+        fn finish(&self, _app: &mut App) {
+            self.finished.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn running() -> bool {
+        HOST_DRIVEN_APP.with(|host_driven_app| host_driven_app.borrow().is_some())
+    }
+
+    #[test]
+    fn test_tick() {
+        let ready = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicUsize::new(0));
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+
+        let mut app = App::new();
+        let recorded_ticks = ticks.clone();
+        app.init_resource::<HostTick>()
+            .add_plugins(WaitingPlugin {
+                ready: ready.clone(),
+                finished: finished.clone(),
+            })
+            .add_systems(
+                Update,
+                move |host_tick: Res<HostTick>, mut exit: EventWriter<AppExit>| {
+                    recorded_ticks
+                        .lock()
+                        .unwrap()
+                        .push((host_tick.count(), host_tick.delta()));
+                    if host_tick.count() == 3 {
+                        exit.send(AppExit);
+                    }
+                },
+            )
+            .set_runner(host_tick_runner);
+        app.run();
+        assert!(running());
+
+        // The app isn't updated until its plugins are ready
+        tick(1_000);
+        assert!(ticks.lock().unwrap().is_empty());
+        assert_eq!(finished.load(Ordering::Relaxed), 0);
+
+        ready.store(true, Ordering::Relaxed);
+        tick(2_000);
+        tick(3_000);
+        assert_eq!(finished.load(Ordering::Relaxed), 1);
+        assert!(running());
+
+        // The app is dropped once it exits, and later ticks are ignored
+        tick(4_000);
+        assert!(!running());
+        tick(5_000);
+        assert_eq!(finished.load(Ordering::Relaxed), 1);
+        assert_eq!(*ticks.lock().unwrap(), [
+            (1, Duration::from_nanos(2_000)),
+            (2, Duration::from_nanos(3_000)),
+            (3, Duration::from_nanos(4_000)),
+        ]);
+    }
+
+    #[test]
+    fn test_tick_without_app() {
+        assert!(!running());
+        tick(1_000);
+        assert!(!running());
+    }
+}
@@ -3,16 +3,25 @@
 #![deny(missing_docs, clippy::missing_safety_doc)]
 use etheryal_identifier::NamespacedIdentifier;
 use getset::Getters;
+use schemars::JsonSchema;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 use typed_builder::TypedBuilder;
 
 pub mod message;
+pub mod schema;
+
+/// The name of the custom section of the WebAssembly module the extension info
+/// is embedded in by `#[etheryal_extension::main]`, so the extension host can
+/// read it without running the extension
+pub const EXTENSION_INFO_SECTION: &str = "etheryal_extension_info";
 
 /// Information about an extension WebAssembly module. This must be sent from
 /// the extension to the host when the extension is loaded and before sending
 /// any other message.
-#[derive(Clone, Debug, Deserialize, Serialize, TypedBuilder, Getters)]
+#[derive(
+    Clone, PartialEq, Eq, Debug, Deserialize, Serialize, TypedBuilder, Getters, JsonSchema,
+)]
 #[getset(get = "pub")]
 pub struct ExtensionModuleInfo {
     /// The human readable name of the extension
@@ -20,6 +29,7 @@ pub struct ExtensionModuleInfo {
     /// The extension's module unique identifier
     identifier: NamespacedIdentifier,
     /// The extension's module version
+    #[schemars(with = "String")]
     version: Version,
     /// The extension's module dependencies
     dependencies: Vec<ExtensionModuleDependency>,
@@ -29,12 +39,15 @@ pub struct ExtensionModuleInfo {
 }
 
 /// Information about an extension WebAssembly module dependency
-#[derive(Clone, Debug, Deserialize, Serialize, TypedBuilder, Getters)]
+#[derive(
+    Clone, PartialEq, Eq, Debug, Deserialize, Serialize, TypedBuilder, Getters, JsonSchema,
+)]
 #[getset(get = "pub")]
 pub struct ExtensionModuleDependency {
     /// The dependency's unique identifier
     identifier: NamespacedIdentifier,
     /// The dependency's required version
+    #[schemars(with = "String")]
     version: VersionReq,
     /// Whether the dependency is optional
     #[builder(default)]
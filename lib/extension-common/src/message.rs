@@ -1,14 +1,40 @@
 //! Contains all the messages that can be sent between the extension host and
 //! guest
-use std::any::TypeId;
+use std::any::{Any, TypeId};
 
 use debug::*;
+use direct::*;
 use enum_dispatch::enum_dispatch;
 use events::*;
+use limits::*;
+use log::*;
+use schemars::JsonSchema;
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::{Deserialize, Serialize};
+use snapshot::*;
+use stats::*;
+use topic::*;
 
 pub mod debug;
+pub mod direct;
+#[cfg(feature = "bevy")]
+pub mod event;
 pub mod events;
+pub mod limits;
+pub mod log;
+pub mod migration;
+pub mod registry;
+pub mod snapshot;
+pub mod stats;
+pub mod topic;
+
+#[doc(hidden)]
+pub mod __private {
+    //! Re-exports used by the code generated by `#[derive(ExtensionMessage)]`.
+    pub use inventory;
+    pub use schemars;
+    pub use serde;
+}
 
 /// A marker trait to signal that this message should be sent *to* the extension
 /// host
@@ -40,26 +66,105 @@ pub trait GuestMessage: Send + Sync + 'static {
     }
 }
 
-/// An enum that contains all possible messages that can be sent *to* the
-/// extension host
+/// The tag of a message in the message enums, the name of its type in snake
+/// case, implemented by `#[derive(ExtensionMessage)]`
+///
+/// Unlike the name of its type, the tag identifies the message in the
+/// protocol, as the `type` field of the encoded message.
+pub trait MessageTag {
+    /// The tag of the message
+    const TAG: &'static str;
+}
+
+/// A decoded message of any type, with the type of its registration in the
+/// [registry]
+pub type BoxedMessage = Box<dyn Any + Send + Sync>;
+
+/// Encodes a message with its tag in the `type` field, like the variants of
+/// the message enums
+///
+/// # Errors
+///
+/// Returns an error if the message can't be encoded
+pub fn encode_message<T>(message: &T) -> Result<Vec<u8>, rmp_serde::encode::Error>
+where
+    T: MessageTag + Serialize, {
+    rmp_serde::to_vec_named(&TaggedRef {
+        tag: T::TAG,
+        message,
+    })
+}
+
+/// Decodes a message encoded by [encode_message], or as a variant of the
+/// message enums
+pub(crate) fn decode_message<T>(encoded: &[u8]) -> Result<T, rmp_serde::decode::Error>
+where
+    T: DeserializeOwned, {
+    rmp_serde::from_slice::<Tagged<T>>(encoded).map(|tagged| tagged.message)
+}
+
+/// A message to encode with its tag
+#[derive(Serialize)]
+struct TaggedRef<'a, T> {
+    #[serde(rename = "type")]
+    tag: &'static str,
+    #[serde(flatten)]
+    message: &'a T,
+}
+
+/// A message decoded along with its tag, which is already known from its
+/// registration
+#[derive(Deserialize)]
+struct Tagged<T> {
+    #[serde(rename = "type")]
+    _tag: IgnoredAny,
+    #[serde(flatten)]
+    message: T,
+}
+
+/// An enum of the messages of this crate sent *to* the extension host, each
+/// tagged with the `type` field
+///
+/// The extension plugins exchange every registered message instead, including
+/// the messages derived outside of this crate.
 #[enum_dispatch(HostMessage)]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 #[allow(missing_docs)]
 pub enum HostMessageEnum {
     ShutdownHost,
+    ShutdownAcknowledged,
+    LogRecord,
+    GuestPanicked,
+    GuestStats,
+    MessagesDropped,
+    GuestSnapshot,
+    DirectMessage,
+    Subscribe,
+    Unsubscribe,
+    Publish,
     Ping,
 }
 
-/// An enum that contains all possible messages that can be sent *to* the
-/// extension guest
+/// An enum of the messages of this crate sent *to* the extension guest, each
+/// tagged with the `type` field
+///
+/// The extension plugins exchange every registered message instead, including
+/// the messages derived outside of this crate.
 #[enum_dispatch(GuestMessage)]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 #[allow(missing_docs)]
 pub enum GuestMessageEnum {
     ShutdownGuest,
+    ExtensionRegistered,
+    SetLogLevel,
+    SnapshotRequest,
+    RestoreSnapshot,
+    ResourceExhausted,
+    DirectMessageReceived,
+    TopicMessage,
     Pong,
 }
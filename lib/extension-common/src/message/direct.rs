@@ -0,0 +1,102 @@
+//! Messages exchanged between extensions, routed by the extension host
+use etheryal_extension_derive::ExtensionMessage;
+use etheryal_identifier::NamespacedIdentifier;
+use getset::Getters;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+/// A message sent from the extension guest to the extension host
+/// to be routed to another extension, which the sender depends on or which
+/// accepts messages from the sender
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+pub struct DirectMessage {
+    /// The identifier of the extension the message is sent to
+    #[getset(get = "pub")]
+    to: NamespacedIdentifier,
+    /// The encoded message
+    #[schemars(with = "crate::schema::Binary")]
+    payload: ByteBuf,
+}
+
+impl DirectMessage {
+    /// Encodes the message sent to the extension `to`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message could not be encoded
+    pub fn new<T>(to: NamespacedIdentifier, message: &T) -> Result<Self, rmp_serde::encode::Error>
+    where
+        T: Serialize + ?Sized, {
+        Ok(Self {
+            to,
+            payload: ByteBuf::from(rmp_serde::to_vec_named(message)?),
+        })
+    }
+
+    /// Returns the encoded message
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// A message sent from the extension host to the extension guest
+/// carrying a `DirectMessage` sent by another extension
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+pub struct DirectMessageReceived {
+    /// The identifier of the extension which sent the message
+    #[getset(get = "pub")]
+    sender: NamespacedIdentifier,
+    /// The encoded message
+    #[schemars(with = "crate::schema::Binary")]
+    payload: ByteBuf,
+}
+
+impl DirectMessageReceived {
+    /// Creates a message carrying the encoded message sent by the extension
+    /// `sender`
+    pub fn new(sender: NamespacedIdentifier, payload: Vec<u8>) -> Self {
+        Self {
+            sender,
+            payload: ByteBuf::from(payload),
+        }
+    }
+
+    /// Returns the encoded message
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Decodes the message sent by the other extension
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not a `T`
+    pub fn decode<T>(&self) -> Result<T, rmp_serde::decode::Error>
+    where
+        T: DeserializeOwned, {
+        rmp_serde::from_slice(&self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direct_message_payload() {
+        let to = NamespacedIdentifier::try_from("etheryal:test").unwrap();
+        let message = DirectMessage::new(to.clone(), &(1u8, "two")).unwrap();
+        assert_eq!(message.to(), &to);
+
+        let received = DirectMessageReceived::new(to, message.payload().to_vec());
+        assert_eq!(
+            received.decode::<(u8, String)>().unwrap(),
+            (1, "two".into())
+        );
+        assert!(received.decode::<u64>().is_err());
+    }
+}
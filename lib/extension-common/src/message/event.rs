@@ -0,0 +1,174 @@
+//! The Bevy events of the messages, added by the extension plugins for every
+//! message of the [registry](crate::message::registry)
+use bevy_app::App;
+use bevy_ecs::prelude::*;
+use derive_more::Deref;
+
+use crate::message::{BoxedMessage, GuestMessage, HostMessage};
+
+/// An event that occurs in an extension
+#[derive(Debug, Deref, Event)]
+pub struct ExtensionEvent<T>
+where
+    T: GuestMessage, {
+    #[deref]
+    inner: T,
+}
+
+impl<T> ExtensionEvent<T>
+where
+    T: GuestMessage,
+{
+    pub(crate) const fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Get the inner data out of it
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+/// An event carrying a message to send to the extension host
+///
+/// The messages are sent once the update is done, in the `ExtensionSet::Flush`
+/// system set of the extension plugin.
+#[derive(Debug, Deref, Event)]
+pub struct ToHost<T>
+where
+    T: HostMessage, {
+    #[deref]
+    inner: T,
+}
+
+impl<T> ToHost<T>
+where
+    T: HostMessage,
+{
+    /// Creates an event sending the message to the extension host
+    pub const fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Get the inner data out of it
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> From<T> for ToHost<T>
+where
+    T: HostMessage,
+{
+    fn from(inner: T) -> Self {
+        Self::new(inner)
+    }
+}
+
+/// An event carrying a message sent by an extension guest, received by the
+/// extension host
+#[derive(Debug, Deref, Event)]
+pub struct HostMessageEvent<T>
+where
+    T: HostMessage, {
+    /// The entity of the extension instance that sent the message
+    pub extension: Entity,
+    /// The message sent by the extension guest
+    #[deref]
+    pub message: T,
+}
+
+impl<T> HostMessageEvent<T>
+where
+    T: HostMessage,
+{
+    /// Creates an event carrying a message sent by the extension instance
+    /// `extension`
+    pub const fn new(extension: Entity, message: T) -> Self {
+        Self { extension, message }
+    }
+
+    /// Get the inner data out of it
+    pub fn into_inner(self) -> T {
+        self.message
+    }
+}
+
+/// The events of a guest message, stored in its registration
+pub(crate) struct GuestMessageEvents {
+    pub(crate) add_event: fn(&mut App),
+    pub(crate) send_event: fn(&mut World, BoxedMessage) -> Result<(), &'static str>,
+}
+
+impl GuestMessageEvents {
+    pub(crate) const fn of<T>() -> Self
+    where
+        T: GuestMessage, {
+        Self {
+            add_event: add_event::<ExtensionEvent<T>>,
+            send_event: send_extension_event::<T>,
+        }
+    }
+}
+
+/// The events of a host message, stored in its registration
+pub(crate) struct HostMessageEvents {
+    pub(crate) add_to_host_event: fn(&mut App),
+    pub(crate) drain_to_host_events: fn(&mut World) -> Vec<BoxedMessage>,
+    pub(crate) add_host_event: fn(&mut App),
+    pub(crate) send_host_event: fn(&mut World, Entity, BoxedMessage) -> Result<(), &'static str>,
+}
+
+impl HostMessageEvents {
+    pub(crate) const fn of<T>() -> Self
+    where
+        T: HostMessage, {
+        Self {
+            add_to_host_event: add_event::<ToHost<T>>,
+            drain_to_host_events: drain_to_host_events::<T>,
+            add_host_event: add_event::<HostMessageEvent<T>>,
+            send_host_event: send_host_event::<T>,
+        }
+    }
+}
+
+fn add_event<E>(app: &mut App)
+where
+    E: Event, {
+    app.add_event::<E>();
+}
+
+fn send_extension_event<T>(world: &mut World, message: BoxedMessage) -> Result<(), &'static str>
+where
+    T: GuestMessage, {
+    let message = message
+        .downcast::<T>()
+        .map_err(|_| "Failed to downcast the guest message")?;
+    world.send_event(ExtensionEvent::new(*message));
+    Ok(())
+}
+
+/// Drains the [ToHost] events of the host message `T`
+fn drain_to_host_events<T>(world: &mut World) -> Vec<BoxedMessage>
+where
+    T: HostMessage, {
+    let Some(mut events) = world.get_resource_mut::<Events<ToHost<T>>>() else {
+        return Vec::new();
+    };
+    events
+        .drain()
+        .map(|event| Box::new(event.into_inner()) as BoxedMessage)
+        .collect()
+}
+
+fn send_host_event<T>(
+    world: &mut World, extension: Entity, message: BoxedMessage,
+) -> Result<(), &'static str>
+where
+    T: HostMessage, {
+    let message = message
+        .downcast::<T>()
+        .map_err(|_| "Failed to downcast the host message")?;
+    world.send_event(HostMessageEvent::new(extension, *message));
+    Ok(())
+}
@@ -1,17 +1,133 @@
 //! Global events that can be sent between the extension host and the extension
 //! guest
+use std::panic::Location;
+
 use etheryal_extension_derive::ExtensionMessage;
+use getset::Getters;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// A message sent from the extension host to the extension guest
 /// when the extension host is shutting down
-#[derive(Serialize, Deserialize, Debug, Clone, ExtensionMessage)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema, ExtensionMessage)]
 #[extension_message(guest)]
 pub struct ShutdownGuest;
 
 /// A message sent from the extension guest to the extension host
 /// when the extension wants to shut down the extension host
 /// (e.g. when the extension wants to close the game server for any reason)
-#[derive(Serialize, Deserialize, Debug, Clone, ExtensionMessage)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema, ExtensionMessage)]
 #[extension_message(host)]
 pub struct ShutdownHost;
+
+/// A message sent from the extension guest to the extension host
+/// when the extension guest finished shutting down after receiving a
+/// `ShutdownGuest` message
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+pub struct ShutdownAcknowledged;
+
+/// A message sent from the extension host to the extension guest
+/// when the extension host accepted the extension info sent by the guest, and
+/// is ready to exchange messages with it
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+pub struct ExtensionRegistered;
+
+/// A message sent from the extension guest to the extension host
+/// when the extension guest panics, right before it traps
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+#[getset(get = "pub")]
+pub struct GuestPanicked {
+    /// The panic message
+    message: String,
+    /// Where the panic occurred in the extension source code
+    location: Option<PanicLocation>,
+    /// The backtrace of the panic, if backtraces are enabled in the extension
+    backtrace: Option<String>,
+}
+
+impl GuestPanicked {
+    /// Encodes the message into the buffer without allocating, as the
+    /// extension guest may be panicking because it ran out of memory
+    ///
+    /// Returns the length of the encoded message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the encoded message doesn't fit in the buffer
+    pub fn encode_into(
+        buffer: &mut [u8], message: &str, location: Option<&Location<'_>>, backtrace: Option<&str>,
+    ) -> Result<usize, rmp_serde::encode::Error> {
+        // Encoded like the `HostMessageEnum::GuestPanicked` variant
+        #[derive(Serialize)]
+        #[serde(tag = "type", rename = "guest_panicked")]
+        struct GuestPanickedRef<'a> {
+            message: &'a str,
+            location: Option<PanicLocationRef<'a>>,
+            backtrace: Option<&'a str>,
+        }
+
+        #[derive(Serialize)]
+        struct PanicLocationRef<'a> {
+            file: &'a str,
+            line: u32,
+            column: u32,
+        }
+
+        let capacity = buffer.len();
+        let mut writer = buffer;
+        rmp_serde::encode::write_named(&mut writer, &GuestPanickedRef {
+            message,
+            location: location.map(|location| PanicLocationRef {
+                file: location.file(),
+                line: location.line(),
+                column: location.column(),
+            }),
+            backtrace,
+        })?;
+        Ok(capacity - writer.len())
+    }
+}
+
+/// The location of a panic in the extension source code
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema)]
+#[getset(get = "pub")]
+pub struct PanicLocation {
+    /// The source file
+    file: String,
+    /// The line in the source file
+    line: u32,
+    /// The column in the line
+    column: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::registry::decode_host_message;
+
+    #[test]
+    fn test_encode_guest_panicked() {
+        let mut buffer = [0; 256];
+        let location = Location::caller();
+        let len = GuestPanicked::encode_into(&mut buffer, "oh no", Some(location), None).unwrap();
+
+        let (_, decoded) = decode_host_message(&buffer[..len]).unwrap();
+        let panicked = decoded.downcast::<GuestPanicked>().unwrap();
+        assert_eq!(panicked.message(), "oh no");
+        assert_eq!(
+            panicked.location().as_ref().map(PanicLocation::line),
+            Some(&location.line())
+        );
+        assert!(panicked.backtrace().is_none());
+    }
+
+    #[test]
+    fn test_encode_guest_panicked_too_long() {
+        let mut buffer = [0; 16];
+        let message = "a".repeat(64);
+        assert!(GuestPanicked::encode_into(&mut buffer, &message, None, None).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+//! The limits the extension host sets on the resources of an extension guest
+use std::fmt;
+
+use etheryal_extension_derive::ExtensionMessage;
+use getset::CopyGetters;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A resource of an extension guest limited by the extension host
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExhaustedResource {
+    /// The size of the linear memory of the extension guest
+    Memory,
+    /// The size of an encoded message exchanged with the extension guest
+    MessageSize,
+}
+
+impl fmt::Display for ExhaustedResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory => f.write_str("linear memory"),
+            Self::MessageSize => f.write_str("message size"),
+        }
+    }
+}
+
+/// A message sent from the extension host to the extension guest
+/// when a message sent to the guest was dropped because it exceeded the
+/// maximum message size of the extension
+///
+/// The extension host also reports the limits the extension guest exceeded
+/// itself, before the guest is stopped.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, CopyGetters, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+#[getset(get_copy = "pub")]
+pub struct ResourceExhausted {
+    /// The exhausted resource
+    resource: ExhaustedResource,
+    /// The number of bytes requested
+    requested: u64,
+    /// The maximum number of bytes allowed by the extension host
+    limit: u64,
+}
+
+impl ResourceExhausted {
+    /// Creates a report of `requested` bytes of the resource exceeding its
+    /// `limit`
+    pub fn new(resource: ExhaustedResource, requested: usize, limit: usize) -> Self {
+        Self {
+            resource,
+            requested: requested as u64,
+            limit: limit as u64,
+        }
+    }
+}
+
+impl fmt::Display for ResourceExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "requested {} bytes of {}, limited to {} bytes",
+            self.requested, self.resource, self.limit
+        )
+    }
+}
@@ -0,0 +1,165 @@
+//! Log records forwarded from the extension guest to the extension host
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use etheryal_extension_derive::ExtensionMessage;
+use etheryal_identifier::NamespacedIdentifier;
+use getset::Getters;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{event, info_span, Level};
+
+/// The level of a log record, in order of increasing verbosity
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[allow(missing_docs)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<Level> for LogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::ERROR => Self::Error,
+            Level::WARN => Self::Warn,
+            Level::INFO => Self::Info,
+            Level::DEBUG => Self::Debug,
+            Level::TRACE => Self::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Self::ERROR,
+            LogLevel::Warn => Self::WARN,
+            LogLevel::Info => Self::INFO,
+            LogLevel::Debug => Self::DEBUG,
+            LogLevel::Trace => Self::TRACE,
+        }
+    }
+}
+
+/// A span the event of a log record was recorded in
+#[derive(Serialize, Deserialize, Clone, Debug, Getters, JsonSchema)]
+#[getset(get = "pub")]
+pub struct LogSpan {
+    /// The name of the span
+    name: String,
+    /// The fields recorded on the span, formatted with `Debug`
+    fields: BTreeMap<String, String>,
+}
+
+impl LogSpan {
+    /// Creates a new span
+    pub fn new(name: String, fields: BTreeMap<String, String>) -> Self {
+        Self { name, fields }
+    }
+}
+
+/// A message sent from the extension guest to the extension host
+/// when the extension guest records a `tracing` event
+#[derive(Serialize, Deserialize, Clone, Debug, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+#[getset(get = "pub")]
+pub struct LogRecord {
+    /// The level of the event
+    level: LogLevel,
+    /// The target of the event, usually the module it was recorded in
+    target: String,
+    /// The message of the event
+    message: String,
+    /// The other fields recorded on the event, formatted with `Debug`
+    fields: BTreeMap<String, String>,
+    /// The spans the event was recorded in, from the outermost one
+    spans: Vec<LogSpan>,
+}
+
+impl LogRecord {
+    /// Creates a new log record
+    pub fn new(
+        level: LogLevel, target: String, message: String, fields: BTreeMap<String, String>,
+        spans: Vec<LogSpan>,
+    ) -> Self {
+        Self {
+            level,
+            target,
+            message,
+            fields,
+            spans,
+        }
+    }
+
+    /// Records the event again in the extension host, in an `extension` span
+    /// tagged with the identifier of the extension that sent it
+    pub fn emit(&self, extension: &NamespacedIdentifier) {
+        let span = info_span!("extension", id = %extension);
+        let _entered = span.enter();
+
+        let mut spans = String::new();
+        for span in &self.spans {
+            if !spans.is_empty() {
+                spans.push(':');
+            }
+            spans.push_str(&span.name);
+            if !span.fields.is_empty() {
+                let _ = write!(spans, "{{{}}}", format_fields(&span.fields));
+            }
+        }
+        let fields = format_fields(&self.fields);
+
+        macro_rules! emit {
+            ($level:expr) => {
+                event!(
+                    target: "etheryal_extension::guest",
+                    $level,
+                    target = %self.target,
+                    spans = %spans,
+                    fields = %fields,
+                    "{}",
+                    self.message
+                )
+            };
+        }
+        match self.level {
+            LogLevel::Error => emit!(Level::ERROR),
+            LogLevel::Warn => emit!(Level::WARN),
+            LogLevel::Info => emit!(Level::INFO),
+            LogLevel::Debug => emit!(Level::DEBUG),
+            LogLevel::Trace => emit!(Level::TRACE),
+        }
+    }
+}
+
+/// A message sent from the extension host to the extension guest
+/// to change the most verbose level of the log records forwarded to the host
+#[derive(Serialize, Deserialize, Clone, Debug, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+#[getset(get = "pub")]
+pub struct SetLogLevel {
+    /// The most verbose level forwarded to the host
+    level: LogLevel,
+}
+
+impl SetLogLevel {
+    /// Creates a new message setting the log level
+    pub fn new(level: LogLevel) -> Self {
+        Self { level }
+    }
+}
+
+fn format_fields(fields: &BTreeMap<String, String>) -> String {
+    let mut formatted = String::new();
+    for (name, value) in fields {
+        if !formatted.is_empty() {
+            formatted.push(' ');
+        }
+        let _ = write!(formatted, "{name}={value}");
+    }
+    formatted
+}
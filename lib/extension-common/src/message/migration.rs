@@ -0,0 +1,225 @@
+//! Upgrades messages sent by an extension host or guest built against an older
+//! version of a message, declared with `#[extension_message(migrate(...))]`
+use std::any::{type_name, TypeId};
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Mutex;
+
+use serde::de::DeserializeOwned;
+use tracing::info;
+
+use crate::message::BoxedMessage;
+
+/// An older version of a message that can be upgraded to the current version,
+/// submitted by `#[derive(ExtensionMessage)]`
+///
+/// Older versions are decoded from the same encoded map as the current
+/// version, so they must not deny unknown fields.
+pub struct MessageMigration {
+    tag: &'static str,
+    from_version: u32,
+    to_version: u32,
+    type_name: fn() -> &'static str,
+    type_id: fn() -> TypeId,
+    migrate: fn(&[u8]) -> Result<BoxedMessage, rmp_serde::decode::Error>,
+}
+
+impl MessageMigration {
+    /// Creates the migration of the message `Old`, the version `from_version`
+    /// of the message tagged `tag`, to the message `New`, the version
+    /// `to_version`
+    pub const fn of<Old, New>(tag: &'static str, from_version: u32, to_version: u32) -> Self
+    where
+        Old: DeserializeOwned + Into<New>,
+        New: Send + Sync + 'static, {
+        Self {
+            tag,
+            from_version,
+            to_version,
+            type_name: type_name::<New>,
+            type_id: TypeId::of::<New>,
+            migrate: migrate::<Old, New>,
+        }
+    }
+
+    /// Returns the tag of the message in the encoded message
+    pub fn tag(&self) -> &'static str {
+        self.tag
+    }
+
+    /// Returns the version of the message this migration upgrades from
+    pub fn from_version(&self) -> u32 {
+        self.from_version
+    }
+
+    /// Returns the version of the message this migration upgrades to
+    pub fn to_version(&self) -> u32 {
+        self.to_version
+    }
+
+    /// Returns the name of the type of the upgraded message
+    pub fn type_name(&self) -> &'static str {
+        (self.type_name)()
+    }
+
+    /// Returns the type id of the upgraded message
+    pub fn type_id(&self) -> TypeId {
+        (self.type_id)()
+    }
+
+    /// Decodes the older version of the message and upgrades it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not an encoded older version of the
+    /// message
+    pub fn migrate(&self, encoded: &[u8]) -> Result<BoxedMessage, rmp_serde::decode::Error> {
+        (self.migrate)(encoded)
+    }
+}
+
+impl fmt::Debug for MessageMigration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageMigration")
+            .field("type_name", &self.type_name())
+            .field("from_version", &self.from_version)
+            .field("to_version", &self.to_version)
+            .finish()
+    }
+}
+
+inventory::collect!(MessageMigration);
+
+/// Upgrades an older version of the message `type_id` tagged `tag`, or returns
+/// `None` if no migration could decode it
+pub(crate) fn migrate_message(tag: &str, type_id: TypeId, encoded: &[u8]) -> Option<BoxedMessage> {
+    // Try the most recent versions first, as they are the most likely to be sent
+    let mut migrations: Vec<_> = inventory::iter::<MessageMigration>
+        .into_iter()
+        .filter(|migration| migration.tag == tag && migration.type_id() == type_id)
+        .collect();
+    migrations.sort_by_key(|migration| Reverse(migration.from_version));
+
+    migrations.into_iter().find_map(|migration| {
+        let message = migration.migrate(encoded).ok()?;
+        log_migration(migration);
+        Some(message)
+    })
+}
+
+fn migrate<Old, New>(encoded: &[u8]) -> Result<BoxedMessage, rmp_serde::decode::Error>
+where
+    Old: DeserializeOwned + Into<New>,
+    New: Send + Sync + 'static, {
+    let old = rmp_serde::from_slice::<Old>(encoded)?;
+    Ok(Box::new(old.into()))
+}
+
+/// Logs the first time each migration is used, so an outdated peer doesn't
+/// flood the logs
+fn log_migration(migration: &MessageMigration) {
+    static LOGGED: Mutex<BTreeSet<(&str, u32)>> = Mutex::new(BTreeSet::new());
+
+    let mut logged = LOGGED.lock().unwrap_or_else(|err| err.into_inner());
+    if logged.insert((migration.tag, migration.from_version)) {
+        info!(
+            "Upgraded message '{}' from version {} to version {}",
+            migration.type_name(),
+            migration.from_version,
+            migration.to_version
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use etheryal_extension_derive::ExtensionMessage;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use crate::message::registry::{decode_host_message, host_message};
+    use crate::message::{encode_message, MessageTag};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, JsonSchema, ExtensionMessage)]
+    #[extension_message(host, version = 2, migrate(from = GreetingV1, version = 1))]
+    struct Greeting {
+        text: String,
+        channel: String,
+    }
+
+    #[derive(Deserialize)]
+    struct GreetingV1 {
+        text: String,
+    }
+
+    impl From<GreetingV1> for Greeting {
+        fn from(greeting: GreetingV1) -> Self {
+            Self {
+                text: greeting.text,
+                channel: "general".into(),
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Tagged<T> {
+        #[serde(rename = "type")]
+        tag: &'static str,
+        #[serde(flatten)]
+        message: T,
+    }
+
+    #[derive(Serialize)]
+    struct EncodedGreetingV1 {
+        text: &'static str,
+    }
+
+    #[test]
+    fn test_message_version() {
+        let registration = host_message(Greeting::TAG).unwrap();
+        assert_eq!(registration.version(), 2);
+        let migration = inventory::iter::<super::MessageMigration>
+            .into_iter()
+            .find(|migration| migration.tag() == Greeting::TAG)
+            .unwrap();
+        assert_eq!(migration.from_version(), 1);
+        assert_eq!(migration.to_version(), 2);
+    }
+
+    #[test]
+    fn test_decode_current_version() {
+        let greeting = Greeting {
+            text: "Hello".into(),
+            channel: "news".into(),
+        };
+        let encoded = encode_message(&greeting).unwrap();
+        let (_, decoded) = decode_host_message(&encoded).unwrap();
+        assert_eq!(*decoded.downcast::<Greeting>().unwrap(), greeting);
+    }
+
+    #[test]
+    fn test_decode_migrated_version() {
+        // The older version is sent with the same tag as the current version
+        let encoded = rmp_serde::to_vec_named(&Tagged {
+            tag: Greeting::TAG,
+            message: EncodedGreetingV1 { text: "Hello" },
+        })
+        .unwrap();
+        let (_, decoded) = decode_host_message(&encoded).unwrap();
+        assert_eq!(*decoded.downcast::<Greeting>().unwrap(), Greeting {
+            text: "Hello".into(),
+            channel: "general".into(),
+        });
+    }
+
+    #[test]
+    fn test_decode_unknown_message() {
+        let encoded = rmp_serde::to_vec_named(&Tagged {
+            tag: "unknown",
+            message: EncodedGreetingV1 { text: "Hello" },
+        })
+        .unwrap();
+        assert!(decode_host_message(&encoded).is_err());
+    }
+}
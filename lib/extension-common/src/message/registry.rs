@@ -0,0 +1,421 @@
+//! A link-time registry of every message deriving `ExtensionMessage`, with
+//! the name, tag and schema of each message type
+//!
+//! The registrations don't depend on the message enums, so messages can be
+//! derived outside of this crate. The extension plugins listen for, decode and
+//! encode every registered message.
+use std::any::{type_name, Any, TypeId};
+use std::fmt;
+
+#[cfg(feature = "bevy")]
+use bevy_app::App;
+#[cfg(feature = "bevy")]
+use bevy_ecs::entity::Entity;
+#[cfg(feature = "bevy")]
+use bevy_ecs::world::World;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::ser::Error as _;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "bevy")]
+use crate::message::event::{GuestMessageEvents, HostMessageEvents};
+use crate::message::{
+    decode_message, encode_message, migration, BoxedMessage, GuestMessage, HostMessage, MessageTag,
+};
+
+/// A message sent *to* the extension guest, submitted by
+/// `#[derive(ExtensionMessage)]`
+pub struct GuestMessageRegistration {
+    type_name: fn() -> &'static str,
+    type_id: fn() -> TypeId,
+    tag: &'static str,
+    version: u32,
+    schema: fn(&mut SchemaGenerator) -> Schema,
+    encode: fn(&dyn Any) -> Result<Vec<u8>, rmp_serde::encode::Error>,
+    decode: fn(&[u8]) -> Result<BoxedMessage, rmp_serde::decode::Error>,
+    #[cfg(feature = "bevy")]
+    events: GuestMessageEvents,
+}
+
+impl GuestMessageRegistration {
+    /// Creates the registration of the guest message `T`
+    pub const fn of<T>() -> Self
+    where
+        T: GuestMessage + MessageTag + JsonSchema + Serialize + DeserializeOwned, {
+        Self {
+            type_name: type_name::<T>,
+            type_id: TypeId::of::<T>,
+            tag: T::TAG,
+            version: 1,
+            schema: subschema_for::<T>,
+            encode: encode::<T>,
+            decode: decode::<T>,
+            #[cfg(feature = "bevy")]
+            events: GuestMessageEvents::of::<T>(),
+        }
+    }
+
+    /// Sets the version of this message, set with
+    /// `#[extension_message(version = N)]`
+    pub const fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Returns the name of the type of this message
+    pub fn type_name(&self) -> &'static str {
+        (self.type_name)()
+    }
+
+    /// Returns the type id of this message
+    pub fn type_id(&self) -> TypeId {
+        (self.type_id)()
+    }
+
+    /// Returns the tag of this message in the encoded message
+    pub fn tag(&self) -> &'static str {
+        self.tag
+    }
+
+    /// Returns the version of this message
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the schema of this message, adding its definitions to the
+    /// generator
+    pub fn schema(&self, gen: &mut SchemaGenerator) -> Schema {
+        (self.schema)(gen)
+    }
+
+    /// Encodes a message of this type with [encode_message]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not of this type, or can't be
+    /// encoded
+    pub fn encode(&self, message: &dyn Any) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        (self.encode)(message)
+    }
+
+    /// Decodes a message of this type, upgrading it if it was encoded with an
+    /// older version of the message
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of decoding the current version of the message if no
+    /// migration could decode it either
+    pub fn decode(&self, encoded: &[u8]) -> Result<BoxedMessage, rmp_serde::decode::Error> {
+        (self.decode)(encoded)
+            .or_else(|err| migration::migrate_message(self.tag, self.type_id(), encoded).ok_or(err))
+    }
+}
+
+impl fmt::Debug for GuestMessageRegistration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GuestMessageRegistration")
+            .field("type_name", &self.type_name())
+            .field("tag", &self.tag)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl GuestMessageRegistration {
+    /// Adds the [ExtensionEvent](crate::message::event::ExtensionEvent) of
+    /// this message to the app
+    pub fn add_event(&self, app: &mut App) {
+        (self.events.add_event)(app)
+    }
+
+    /// Sends a decoded message of this type as an
+    /// [ExtensionEvent](crate::message::event::ExtensionEvent)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not of this type
+    pub fn send_event(&self, world: &mut World, message: BoxedMessage) -> Result<(), &'static str> {
+        (self.events.send_event)(world, message)
+    }
+}
+
+/// A message sent *to* the extension host, submitted by
+/// `#[derive(ExtensionMessage)]`
+pub struct HostMessageRegistration {
+    type_name: fn() -> &'static str,
+    type_id: fn() -> TypeId,
+    tag: &'static str,
+    version: u32,
+    schema: fn(&mut SchemaGenerator) -> Schema,
+    encode: fn(&dyn Any) -> Result<Vec<u8>, rmp_serde::encode::Error>,
+    decode: fn(&[u8]) -> Result<BoxedMessage, rmp_serde::decode::Error>,
+    #[cfg(feature = "bevy")]
+    events: HostMessageEvents,
+}
+
+impl HostMessageRegistration {
+    /// Creates the registration of the host message `T`
+    pub const fn of<T>() -> Self
+    where
+        T: HostMessage + MessageTag + JsonSchema + Serialize + DeserializeOwned, {
+        Self {
+            type_name: type_name::<T>,
+            type_id: TypeId::of::<T>,
+            tag: T::TAG,
+            version: 1,
+            schema: subschema_for::<T>,
+            encode: encode::<T>,
+            decode: decode::<T>,
+            #[cfg(feature = "bevy")]
+            events: HostMessageEvents::of::<T>(),
+        }
+    }
+
+    /// Sets the version of this message, set with
+    /// `#[extension_message(version = N)]`
+    pub const fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Returns the name of the type of this message
+    pub fn type_name(&self) -> &'static str {
+        (self.type_name)()
+    }
+
+    /// Returns the type id of this message
+    pub fn type_id(&self) -> TypeId {
+        (self.type_id)()
+    }
+
+    /// Returns the tag of this message in the encoded message
+    pub fn tag(&self) -> &'static str {
+        self.tag
+    }
+
+    /// Returns the version of this message
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the schema of this message, adding its definitions to the
+    /// generator
+    pub fn schema(&self, gen: &mut SchemaGenerator) -> Schema {
+        (self.schema)(gen)
+    }
+
+    /// Encodes a message of this type with [encode_message]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not of this type, or can't be
+    /// encoded
+    pub fn encode(&self, message: &dyn Any) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        (self.encode)(message)
+    }
+
+    /// Decodes a message of this type, upgrading it if it was encoded with an
+    /// older version of the message
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of decoding the current version of the message if no
+    /// migration could decode it either
+    pub fn decode(&self, encoded: &[u8]) -> Result<BoxedMessage, rmp_serde::decode::Error> {
+        (self.decode)(encoded)
+            .or_else(|err| migration::migrate_message(self.tag, self.type_id(), encoded).ok_or(err))
+    }
+}
+
+impl fmt::Debug for HostMessageRegistration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostMessageRegistration")
+            .field("type_name", &self.type_name())
+            .field("tag", &self.tag)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl HostMessageRegistration {
+    /// Adds the [ToHost](crate::message::event::ToHost) event of this message
+    /// to the app of the extension guest
+    pub fn add_to_host_event(&self, app: &mut App) {
+        (self.events.add_to_host_event)(app)
+    }
+
+    /// Drains the [ToHost](crate::message::event::ToHost) events of this
+    /// message, returning the messages to send to the extension host
+    pub fn drain_to_host_events(&self, world: &mut World) -> Vec<BoxedMessage> {
+        (self.events.drain_to_host_events)(world)
+    }
+
+    /// Adds the [HostMessageEvent](crate::message::event::HostMessageEvent) of
+    /// this message to the app of the extension host
+    pub fn add_host_event(&self, app: &mut App) {
+        (self.events.add_host_event)(app)
+    }
+
+    /// Sends a decoded message of this type, sent by the extension instance
+    /// `extension`, as a
+    /// [HostMessageEvent](crate::message::event::HostMessageEvent)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not of this type
+    pub fn send_host_event(
+        &self, world: &mut World, extension: Entity, message: BoxedMessage,
+    ) -> Result<(), &'static str> {
+        (self.events.send_host_event)(world, extension, message)
+    }
+}
+
+inventory::collect!(GuestMessageRegistration);
+inventory::collect!(HostMessageRegistration);
+
+/// Returns every message that can be sent to the extension guest
+pub fn guest_messages() -> impl Iterator<Item = &'static GuestMessageRegistration> {
+    inventory::iter::<GuestMessageRegistration>.into_iter()
+}
+
+/// Returns every message that can be sent to the extension host
+pub fn host_messages() -> impl Iterator<Item = &'static HostMessageRegistration> {
+    inventory::iter::<HostMessageRegistration>.into_iter()
+}
+
+/// Finds the registration of the guest message with the given tag
+pub fn guest_message(tag: &str) -> Option<&'static GuestMessageRegistration> {
+    guest_messages().find(|registration| registration.tag == tag)
+}
+
+/// Finds the registration of the host message with the given tag
+pub fn host_message(tag: &str) -> Option<&'static HostMessageRegistration> {
+    host_messages().find(|registration| registration.tag == tag)
+}
+
+/// Decodes a message sent *to* the extension guest with the registration of
+/// its tag, upgrading it if it was encoded by a host built against an older
+/// version of the message
+///
+/// # Errors
+///
+/// Returns an error if the tag of the message is not registered, or if the
+/// message can't be decoded
+pub fn decode_guest_message(
+    encoded: &[u8],
+) -> Result<(&'static GuestMessageRegistration, BoxedMessage), rmp_serde::decode::Error> {
+    let tag = decode_tag(encoded)?;
+    let registration = guest_message(&tag).ok_or_else(|| unknown_tag(&tag))?;
+    Ok((registration, registration.decode(encoded)?))
+}
+
+/// Decodes a message sent *to* the extension host with the registration of
+/// its tag, upgrading it if it was encoded by a guest built against an older
+/// version of the message
+///
+/// # Errors
+///
+/// Returns an error if the tag of the message is not registered, or if the
+/// message can't be decoded
+pub fn decode_host_message(
+    encoded: &[u8],
+) -> Result<(&'static HostMessageRegistration, BoxedMessage), rmp_serde::decode::Error> {
+    let tag = decode_tag(encoded)?;
+    let registration = host_message(&tag).ok_or_else(|| unknown_tag(&tag))?;
+    Ok((registration, registration.decode(encoded)?))
+}
+
+/// The tag of an encoded message, used to find its registration
+#[derive(Deserialize)]
+struct EncodedTag {
+    #[serde(rename = "type")]
+    tag: String,
+}
+
+fn decode_tag(encoded: &[u8]) -> Result<String, rmp_serde::decode::Error> {
+    rmp_serde::from_slice::<EncodedTag>(encoded).map(|encoded| encoded.tag)
+}
+
+fn unknown_tag(tag: &str) -> rmp_serde::decode::Error {
+    rmp_serde::decode::Error::custom(format!("unknown message tag '{tag}'"))
+}
+
+fn encode<T>(message: &dyn Any) -> Result<Vec<u8>, rmp_serde::encode::Error>
+where
+    T: MessageTag + Serialize + 'static, {
+    let message = message.downcast_ref::<T>().ok_or_else(|| {
+        rmp_serde::encode::Error::custom(format!("the message is not a '{}'", type_name::<T>()))
+    })?;
+    encode_message(message)
+}
+
+fn decode<T>(encoded: &[u8]) -> Result<BoxedMessage, rmp_serde::decode::Error>
+where
+    T: DeserializeOwned + Send + Sync + 'static, {
+    Ok(Box::new(decode_message::<T>(encoded)?))
+}
+
+fn subschema_for<T>(gen: &mut SchemaGenerator) -> Schema
+where
+    T: JsonSchema, {
+    gen.subschema_for::<T>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::debug::{Ping, Pong};
+    use crate::message::events::{ShutdownGuest, ShutdownHost};
+    use crate::message::{GuestMessageEnum, HostMessageEnum};
+
+    #[test]
+    fn test_message_tags() {
+        let encoded = serde_json::to_value(GuestMessageEnum::from(Pong)).unwrap();
+        assert_eq!(encoded["type"], Pong::TAG);
+        let encoded = serde_json::to_value(HostMessageEnum::from(ShutdownHost)).unwrap();
+        assert_eq!(encoded["type"], ShutdownHost::TAG);
+        assert_eq!(ShutdownHost::TAG, "shutdown_host");
+    }
+
+    #[test]
+    fn test_guest_messages_registered() {
+        let registered: Vec<_> = guest_messages()
+            .map(GuestMessageRegistration::type_id)
+            .collect();
+        assert!(registered.contains(&TypeId::of::<Pong>()));
+        assert!(registered.contains(&TypeId::of::<ShutdownGuest>()));
+        assert!(!registered.contains(&TypeId::of::<Ping>()));
+    }
+
+    #[test]
+    fn test_host_messages_registered() {
+        assert!(host_message(Ping::TAG).is_some());
+        assert!(host_message(ShutdownHost::TAG).is_some());
+        assert!(host_message(Pong::TAG).is_none());
+    }
+
+    #[test]
+    fn test_decode_message_enum() {
+        // The messages encoded as variants of the message enums are decoded with
+        // their registration
+        let encoded = rmp_serde::to_vec_named(&GuestMessageEnum::from(Pong)).unwrap();
+        let (registration, message) = decode_guest_message(&encoded).unwrap();
+        assert_eq!(registration.tag(), Pong::TAG);
+        assert!(message.downcast::<Pong>().is_ok());
+    }
+
+    #[test]
+    fn test_encode_message_enum() {
+        // The messages encoded with their registration are decoded as variants
+        // of the message enums
+        let encoded = host_message(Ping::TAG).unwrap().encode(&Ping).unwrap();
+        let decoded: HostMessageEnum = rmp_serde::from_slice(&encoded).unwrap();
+        assert!(matches!(decoded, HostMessageEnum::Ping(Ping)));
+        assert!(host_message(Ping::TAG).unwrap().encode(&Pong).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Snapshots of the state persisted by an extension guest, kept by the
+//! extension host while the extension is reloaded
+use std::collections::BTreeMap;
+
+use etheryal_extension_derive::ExtensionMessage;
+use getset::Getters;
+use schemars::JsonSchema;
+use semver::Version;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+/// The values persisted by an extension guest, encoded by key
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct PersistedValues(
+    #[schemars(with = "BTreeMap<String, crate::schema::Binary>")] BTreeMap<String, ByteBuf>,
+);
+
+impl PersistedValues {
+    /// Decodes the value persisted with the key, if any
+    pub fn get<T>(&self, key: &str) -> Option<Result<T, rmp_serde::decode::Error>>
+    where
+        T: DeserializeOwned, {
+        self.0
+            .get(key)
+            .map(|encoded| rmp_serde::from_slice(encoded))
+    }
+
+    /// Encodes the value, replacing the value persisted with the key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value could not be encoded
+    pub fn insert<T>(
+        &mut self, key: impl Into<String>, value: &T,
+    ) -> Result<(), rmp_serde::encode::Error>
+    where
+        T: Serialize + ?Sized, {
+        let encoded = rmp_serde::to_vec_named(value)?;
+        self.0.insert(key.into(), ByteBuf::from(encoded));
+        Ok(())
+    }
+
+    /// Removes the value persisted with the key, returning whether there was
+    /// one
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.0.remove(key).is_some()
+    }
+
+    /// Returns whether a value is persisted with the key
+    pub fn contains(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Returns whether no value is persisted
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The state persisted by an extension guest
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Getters, JsonSchema)]
+#[getset(get = "pub")]
+pub struct Snapshot {
+    /// The version of the extension which took the snapshot
+    #[schemars(with = "String")]
+    version: Version,
+    /// The persisted resources
+    resources: PersistedValues,
+    /// The persisted components of each entity
+    entities: Vec<PersistedValues>,
+}
+
+impl Snapshot {
+    /// Creates an empty snapshot taken by the version `version` of the
+    /// extension
+    pub fn new(version: Version) -> Self {
+        Self {
+            version,
+            resources: PersistedValues::default(),
+            entities: Vec::new(),
+        }
+    }
+
+    /// Returns the persisted resources, to migrate them
+    pub fn resources_mut(&mut self) -> &mut PersistedValues {
+        &mut self.resources
+    }
+
+    /// Returns the persisted components of each entity, to migrate them
+    pub fn entities_mut(&mut self) -> &mut Vec<PersistedValues> {
+        &mut self.entities
+    }
+}
+
+/// A message sent from the extension host to the extension guest
+/// before it is unloaded to be reloaded, requesting a snapshot of its
+/// persisted state
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+pub struct SnapshotRequest;
+
+/// A message sent from the extension guest to the extension host
+/// in reply to a `SnapshotRequest` message, if the extension persists any
+/// state
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+pub struct GuestSnapshot {
+    /// The snapshot of the persisted state
+    #[getset(get = "pub")]
+    snapshot: Snapshot,
+}
+
+impl GuestSnapshot {
+    /// Creates a message carrying the snapshot
+    pub fn new(snapshot: Snapshot) -> Self {
+        Self { snapshot }
+    }
+
+    /// Returns the snapshot of the persisted state
+    pub fn into_snapshot(self) -> Snapshot {
+        self.snapshot
+    }
+}
+
+/// A message sent from the extension host to the extension guest
+/// reloaded after its previous instance sent a `GuestSnapshot` message, before
+/// its registration is confirmed
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+pub struct RestoreSnapshot {
+    /// The snapshot of the state persisted by the previous instance
+    #[getset(get = "pub")]
+    snapshot: Snapshot,
+}
+
+impl RestoreSnapshot {
+    /// Creates a message restoring the snapshot
+    pub fn new(snapshot: Snapshot) -> Self {
+        Self { snapshot }
+    }
+
+    /// Returns the snapshot of the state persisted by the previous instance
+    pub fn into_snapshot(self) -> Snapshot {
+        self.snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::registry::decode_guest_message;
+    use crate::message::GuestMessageEnum;
+
+    #[test]
+    fn test_restore_snapshot() {
+        let mut snapshot = Snapshot::new(Version::new(1, 2, 0));
+        snapshot.resources_mut().insert("score", &42u32).unwrap();
+        let mut entity = PersistedValues::default();
+        entity.insert("name", "etheryal").unwrap();
+        snapshot.entities_mut().push(entity);
+
+        let message = GuestMessageEnum::from(RestoreSnapshot::new(snapshot.clone()));
+        let encoded = rmp_serde::to_vec_named(&message).unwrap();
+        let (_, decoded) = decode_guest_message(&encoded).unwrap();
+        let restore = decoded.downcast::<RestoreSnapshot>().unwrap();
+        assert_eq!(restore.snapshot(), &snapshot);
+        assert_eq!(
+            restore
+                .snapshot()
+                .resources()
+                .get::<u32>("score")
+                .unwrap()
+                .unwrap(),
+            42
+        );
+        assert!(restore
+            .snapshot()
+            .resources()
+            .get::<u32>("missing")
+            .is_none());
+        assert_eq!(
+            restore.snapshot().entities()[0]
+                .get::<String>("name")
+                .unwrap()
+                .unwrap(),
+            "etheryal"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Statistics about the messages exchanged between the extension host and the
+//! extension guest
+use etheryal_extension_derive::ExtensionMessage;
+use getset::{CopyGetters, Getters};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A message sent from the extension guest to the extension host
+/// periodically, with the statistics of the messages exchanged since the
+/// extension started
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Debug,
+    Default,
+    Getters,
+    CopyGetters,
+    JsonSchema,
+    ExtensionMessage,
+)]
+#[extension_message(host)]
+pub struct GuestStats {
+    /// The statistics of each message type
+    #[getset(get = "pub")]
+    messages: Vec<MessageTypeStats>,
+    /// The time spent encoding messages, in nanoseconds
+    #[getset(get_copy = "pub")]
+    encode_time_nanos: u64,
+    /// The time spent decoding messages, in nanoseconds
+    #[getset(get_copy = "pub")]
+    decode_time_nanos: u64,
+}
+
+impl GuestStats {
+    /// Creates new statistics
+    pub fn new(
+        messages: Vec<MessageTypeStats>, encode_time_nanos: u64, decode_time_nanos: u64,
+    ) -> Self {
+        Self {
+            messages,
+            encode_time_nanos,
+            decode_time_nanos,
+        }
+    }
+
+    /// Returns the statistics of the message type with the given name
+    pub fn message(&self, type_name: &str) -> Option<&MessageTypeStats> {
+        self.messages
+            .iter()
+            .find(|stats| stats.type_name == type_name)
+    }
+}
+
+/// The statistics of a single message type
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Getters, CopyGetters, JsonSchema)]
+pub struct MessageTypeStats {
+    /// The name of the type of the message
+    #[getset(get = "pub")]
+    type_name: String,
+    /// The number of messages sent
+    #[getset(get_copy = "pub")]
+    sent: u64,
+    /// The number of bytes of the encoded messages sent
+    #[getset(get_copy = "pub")]
+    sent_bytes: u64,
+    /// The number of messages received
+    #[getset(get_copy = "pub")]
+    received: u64,
+    /// The number of bytes of the encoded messages received
+    #[getset(get_copy = "pub")]
+    received_bytes: u64,
+    /// The most received messages waiting to be dispatched during the last
+    /// update
+    #[getset(get_copy = "pub")]
+    queue_depth: u64,
+    /// The number of received messages dropped because the queue was full
+    #[getset(get_copy = "pub")]
+    dropped: u64,
+}
+
+impl MessageTypeStats {
+    /// Creates empty statistics for the message type with the given name
+    pub fn new(type_name: String) -> Self {
+        Self {
+            type_name,
+            ..Default::default()
+        }
+    }
+
+    /// Records a sent message of `bytes` bytes
+    pub fn record_sent(&mut self, bytes: usize) {
+        self.sent += 1;
+        self.sent_bytes += bytes as u64;
+    }
+
+    /// Records a received message of `bytes` bytes
+    pub fn record_received(&mut self, bytes: usize) {
+        self.received += 1;
+        self.received_bytes += bytes as u64;
+    }
+
+    /// Records `count` received messages dropped because the queue was full
+    pub fn record_dropped(&mut self, count: u64) {
+        self.dropped += count;
+    }
+
+    /// Sets the most received messages waiting to be dispatched during the
+    /// last update
+    pub fn set_queue_depth(&mut self, queue_depth: usize) {
+        self.queue_depth = queue_depth as u64;
+    }
+}
+
+/// A message sent from the extension guest to the extension host
+/// when messages sent to the guest were dropped because the queue of their
+/// type was full, so the host can slow down
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Getters, CopyGetters, JsonSchema, ExtensionMessage,
+)]
+#[extension_message(host)]
+pub struct MessagesDropped {
+    /// The tag of the dropped messages, as in the `type` field of the encoded
+    /// messages
+    #[getset(get = "pub")]
+    tag: String,
+    /// The number of messages dropped since the last report
+    #[getset(get_copy = "pub")]
+    count: u64,
+    /// The capacity of the queue of the message type
+    #[getset(get_copy = "pub")]
+    capacity: u64,
+}
+
+impl MessagesDropped {
+    /// Creates a new report of dropped messages
+    pub fn new(tag: String, count: u64, capacity: usize) -> Self {
+        Self {
+            tag,
+            count,
+            capacity: capacity as u64,
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Messages published on topics, fanned out by the extension host to the
+//! extensions subscribed to them
+use std::fmt;
+
+use etheryal_extension_derive::ExtensionMessage;
+use etheryal_identifier::{Identifier, IdentifierError, NamespacedIdentifier};
+use getset::Getters;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+/// The topics an extension subscribes to: a single topic such as
+/// `example:scores`, every topic of a namespace such as `example:*`, or every
+/// topic with `*`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub enum TopicPattern {
+    /// Every topic
+    All,
+    /// Every topic of the namespace
+    Namespace(Identifier),
+    /// A single topic
+    Topic(NamespacedIdentifier),
+}
+
+impl TopicPattern {
+    /// Returns whether the topic matches the pattern
+    pub fn matches(&self, topic: &NamespacedIdentifier) -> bool {
+        match self {
+            Self::All => true,
+            Self::Namespace(namespace) => topic.namespace() == namespace,
+            Self::Topic(pattern) => pattern == topic,
+        }
+    }
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => f.write_str("*"),
+            Self::Namespace(namespace) => write!(f, "{namespace}:*"),
+            Self::Topic(topic) => write!(f, "{topic}"),
+        }
+    }
+}
+
+impl TryFrom<&str> for TopicPattern {
+    type Error = IdentifierError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if value == "*" {
+            return Ok(Self::All);
+        }
+        match value.strip_suffix(":*") {
+            Some(namespace) => Ok(Self::Namespace(Identifier::try_from(namespace)?)),
+            None => Ok(Self::Topic(NamespacedIdentifier::try_from(value)?)),
+        }
+    }
+}
+
+impl TryFrom<String> for TopicPattern {
+    type Error = IdentifierError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::try_from(value.as_str())
+    }
+}
+
+impl From<TopicPattern> for String {
+    fn from(value: TopicPattern) -> Self {
+        value.to_string()
+    }
+}
+
+impl From<NamespacedIdentifier> for TopicPattern {
+    fn from(value: NamespacedIdentifier) -> Self {
+        Self::Topic(value)
+    }
+}
+
+impl JsonSchema for TopicPattern {
+    fn schema_name() -> String {
+        "TopicPattern".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(
+                    r"^(\*|[a-z_][a-z0-9_]*:(\*|[a-z_][a-z0-9_]*)|[a-z_][a-z0-9_]*)$".into(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// A message sent from the extension guest to the extension host
+/// to receive the messages published on the topics matching the pattern
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+#[getset(get = "pub")]
+pub struct Subscribe {
+    /// The topics subscribed to
+    pattern: TopicPattern,
+}
+
+impl Subscribe {
+    /// Creates a subscription to the topics matching the pattern
+    pub fn new(pattern: TopicPattern) -> Self {
+        Self { pattern }
+    }
+}
+
+/// A message sent from the extension guest to the extension host
+/// to stop receiving the messages published on the topics of a previous
+/// `Subscribe` message
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+#[getset(get = "pub")]
+pub struct Unsubscribe {
+    /// The pattern of the previous subscription
+    pattern: TopicPattern,
+}
+
+impl Unsubscribe {
+    /// Removes the subscription to the topics matching the pattern
+    pub fn new(pattern: TopicPattern) -> Self {
+        Self { pattern }
+    }
+}
+
+/// A message sent from the extension guest to the extension host
+/// to publish a message on a topic, sent to every extension subscribed to it
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+pub struct Publish {
+    /// The topic the message is published on
+    #[getset(get = "pub")]
+    topic: NamespacedIdentifier,
+    /// The encoded message
+    #[schemars(with = "crate::schema::Binary")]
+    payload: ByteBuf,
+}
+
+impl Publish {
+    /// Encodes the message published on the topic
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message could not be encoded
+    pub fn new<T>(
+        topic: NamespacedIdentifier, message: &T,
+    ) -> Result<Self, rmp_serde::encode::Error>
+    where
+        T: Serialize + ?Sized, {
+        Ok(Self {
+            topic,
+            payload: ByteBuf::from(rmp_serde::to_vec_named(message)?),
+        })
+    }
+
+    /// Returns the encoded message
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// A message sent from the extension host to the extension guest
+/// carrying a message published on a topic the extension subscribed to
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+pub struct TopicMessage {
+    /// The topic the message was published on
+    #[getset(get = "pub")]
+    topic: NamespacedIdentifier,
+    /// The identifier of the extension which published the message, or none
+    /// if the extension host published it
+    #[getset(get = "pub")]
+    publisher: Option<NamespacedIdentifier>,
+    /// The encoded message
+    #[schemars(with = "crate::schema::Binary")]
+    payload: ByteBuf,
+}
+
+impl TopicMessage {
+    /// Creates a message carrying the encoded message published on the topic
+    pub fn new(
+        topic: NamespacedIdentifier, publisher: Option<NamespacedIdentifier>, payload: Vec<u8>,
+    ) -> Self {
+        Self {
+            topic,
+            publisher,
+            payload: ByteBuf::from(payload),
+        }
+    }
+
+    /// Returns the encoded message
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Decodes the published message
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is not a `T`
+    pub fn decode<T>(&self) -> Result<T, rmp_serde::decode::Error>
+    where
+        T: DeserializeOwned, {
+        rmp_serde::from_slice(&self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(topic: &str) -> NamespacedIdentifier {
+        NamespacedIdentifier::try_from(topic).unwrap()
+    }
+
+    #[test]
+    fn test_topic_patterns() {
+        let all = TopicPattern::try_from("*").unwrap();
+        let namespace = TopicPattern::try_from("example:*").unwrap();
+        let single = TopicPattern::try_from("example:scores").unwrap();
+        assert_eq!(
+            namespace,
+            TopicPattern::Namespace(Identifier::try_from("example").unwrap())
+        );
+        assert!(TopicPattern::try_from("Example:*").is_err());
+        assert!(TopicPattern::try_from("example:sc*").is_err());
+
+        assert!(all.matches(&topic("other:scores")));
+        assert!(namespace.matches(&topic("example:players")));
+        assert!(!namespace.matches(&topic("other:scores")));
+        assert!(single.matches(&topic("example:scores")));
+        assert!(!single.matches(&topic("example:players")));
+
+        for pattern in [all, namespace, single] {
+            let encoded = rmp_serde::to_vec_named(&Subscribe::new(pattern.clone())).unwrap();
+            let decoded: Subscribe = rmp_serde::from_slice(&encoded).unwrap();
+            assert_eq!(decoded.pattern(), &pattern);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! A machine-readable description of the protocol between the extension host
+//! and the extension guest, as a JSON Schema
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{
+    InstanceType, Metadata, ObjectValidation, RootSchema, Schema, SchemaObject,
+};
+use schemars::JsonSchema;
+
+use crate::message::{registry, GuestMessageEnum, HostMessageEnum};
+use crate::ExtensionModuleInfo;
+
+/// Returns the schema of the whole extension protocol.
+///
+/// The root schema has an `extension_info` property, which is the first
+/// message sent by the guest, and `host_message` and `guest_message`
+/// properties describing every message that can be sent *to* the host and
+/// *to* the guest. Every message registered by `#[derive(ExtensionMessage)]`
+/// is included in the definitions.
+pub fn protocol_schema() -> RootSchema {
+    let mut gen = SchemaGenerator::new(SchemaSettings::draft07());
+
+    let mut object = ObjectValidation::default();
+    object.properties.insert(
+        "extension_info".into(),
+        gen.subschema_for::<ExtensionModuleInfo>(),
+    );
+    object.properties.insert(
+        "host_message".into(),
+        gen.subschema_for::<HostMessageEnum>(),
+    );
+    object.properties.insert(
+        "guest_message".into(),
+        gen.subschema_for::<GuestMessageEnum>(),
+    );
+
+    for registration in registry::host_messages() {
+        registration.schema(&mut gen);
+    }
+    for registration in registry::guest_messages() {
+        registration.schema(&mut gen);
+    }
+
+    let schema = SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            title: Some("etheryal extension protocol".into()),
+            description: Some(
+                "Messages exchanged between the etheryal extension host and guest, encoded as \
+                 MessagePack maps"
+                    .into(),
+            ),
+            ..Default::default()
+        })),
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(object)),
+        ..Default::default()
+    };
+
+    RootSchema {
+        meta_schema: gen.settings().meta_schema.clone(),
+        schema,
+        definitions: gen.take_definitions(),
+    }
+}
+
+/// The schema of the byte strings, encoded as MessagePack `bin` values instead
+/// of arrays of integers
+pub(crate) struct Binary;
+
+impl JsonSchema for Binary {
+    fn schema_name() -> String {
+        "Binary".into()
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            format: Some("binary".into()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
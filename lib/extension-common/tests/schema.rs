@@ -0,0 +1,24 @@
+//! Keeps the checked-in protocol schema in sync with the protocol, so changes
+//! to the messages show up in review. Run with `UPDATE_SCHEMA=1` to update it.
+use std::fs;
+use std::path::Path;
+
+use etheryal_extension_common::schema::protocol_schema;
+
+#[test]
+fn test_protocol_schema_up_to_date() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema/protocol.json");
+    let schema =
+        serde_json::to_string_pretty(&protocol_schema()).expect("schema serialized") + "\n";
+
+    if std::env::var_os("UPDATE_SCHEMA").is_some() {
+        fs::write(&path, schema).expect("schema written");
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).expect("schema read");
+    assert_eq!(
+        expected, schema,
+        "the protocol schema changed, run the tests with `UPDATE_SCHEMA=1` to update it"
+    );
+}
@@ -0,0 +1,318 @@
+use darling::ast::NestedMeta;
+use darling::FromMeta;
+use etheryal_identifier::NamespacedIdentifier;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use syn::spanned::Spanned;
+use syn::{FnArg, ItemFn, LitStr, ReturnType};
+
+/// The arguments of the `#[etheryal_extension::main]` attribute.
+#[derive(FromMeta)]
+struct MainArgs {
+    /// The namespaced identifier of the extension module.
+    id: LitStr,
+
+    /// The human readable name of the extension, defaults to the crate name.
+    #[darling(default)]
+    name: Option<LitStr>,
+
+    /// The description of the extension, defaults to the crate description.
+    #[darling(default)]
+    description: Option<LitStr>,
+
+    /// The dependencies of the extension module.
+    #[darling(multiple, rename = "dependency")]
+    dependencies: Vec<DependencyArgs>,
+
+    /// The number of updates run after the host requests a shutdown.
+    #[darling(default)]
+    shutdown_grace_ticks: Option<u32>,
+
+    /// Whether the app is run by the host through the `etheryal_tick` export.
+    #[darling(default)]
+    host_tick: bool,
+}
+
+/// A single `dependency(...)` entry of the `#[etheryal_extension::main]`
+/// attribute.
+#[derive(FromMeta)]
+struct DependencyArgs {
+    /// The namespaced identifier of the dependency.
+    id: LitStr,
+
+    /// The required version of the dependency.
+    version: LitStr,
+
+    /// Whether the dependency is optional.
+    #[darling(default)]
+    optional: bool,
+}
+
+pub fn expand(args: TokenStream, item: ItemFn, etheryal_extension: TokenStream) -> TokenStream {
+    let args = match NestedMeta::parse_meta_list(args) {
+        Ok(args) => args,
+        Err(err) => return darling::Error::from(err).write_errors(),
+    };
+    let args = match MainArgs::from_list(&args) {
+        Ok(args) => args,
+        Err(err) => return err.write_errors(),
+    };
+
+    match expand_main(args, item, etheryal_extension) {
+        Ok(tokens) => tokens,
+        Err(err) => err.to_compile_error(),
+    }
+}
+
+/// The name of the custom section the extension info is embedded in, read by
+/// the extension host before instantiating the module. This must match
+/// `etheryal_extension_common::EXTENSION_INFO_SECTION`.
+const EXTENSION_INFO_SECTION: &str = "etheryal_extension_info";
+
+/// The extension info embedded in the module, encoded like the
+/// `ExtensionModuleInfo` sent by the extension guest.
+#[derive(Serialize)]
+struct EmbeddedInfo {
+    name: String,
+    identifier: NamespacedIdentifier,
+    version: Version,
+    dependencies: Vec<EmbeddedDependency>,
+    description: Option<String>,
+}
+
+/// A dependency in the [EmbeddedInfo], encoded like the
+/// `ExtensionModuleDependency`.
+#[derive(Serialize)]
+struct EmbeddedDependency {
+    identifier: NamespacedIdentifier,
+    version: VersionReq,
+    optional: bool,
+}
+
+fn expand_main(
+    args: MainArgs, item: ItemFn, etheryal_extension: TokenStream,
+) -> syn::Result<TokenStream> {
+    validate_signature(&item)?;
+    let identifier = validate_identifier(&args.id)?;
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    let main = &sig.ident;
+    let setup = format_ident!("__etheryal_extension_setup");
+    let inputs = &sig.inputs;
+
+    let id = &args.id;
+    let name = match &args.name {
+        Some(name) => quote! { #name },
+        None => quote! { env!("CARGO_PKG_NAME") },
+    };
+    let description = match &args.description {
+        Some(description) => quote! { Some(#description.into()) },
+        None => quote! {
+            Some(env!("CARGO_PKG_DESCRIPTION"))
+                .filter(|description| !description.is_empty())
+                .map(Into::into)
+        },
+    };
+
+    let mut plugin = quote! {
+        #etheryal_extension::plugin::EtheryalExtensionPlugin::new(extension_info)
+    };
+    if let Some(ticks) = args.shutdown_grace_ticks {
+        plugin.extend(quote! { .with_shutdown_grace_ticks(#ticks) });
+    }
+
+    // The host runs each update through the export instead of the schedule runner
+    let (plugins, tick_export) = if args.host_tick {
+        plugin.extend(quote! { .with_host_tick() });
+        let tick_export = quote! {
+            #[no_mangle]
+            pub extern "C" fn etheryal_tick(delta_nanos: u64) {
+                #etheryal_extension::plugin::tick(delta_nanos);
+            }
+        };
+        (plugin, tick_export)
+    } else {
+        let plugins = quote! {
+            (
+                #etheryal_extension::bevy_app::ScheduleRunnerPlugin::default(),
+                #plugin,
+            )
+        };
+        (plugins, TokenStream::new())
+    };
+
+    let mut dependencies = Vec::with_capacity(args.dependencies.len());
+    let mut embedded_dependencies = Vec::with_capacity(args.dependencies.len());
+    for dependency in &args.dependencies {
+        let identifier = validate_identifier(&dependency.id)?;
+        let version = VersionReq::parse(&dependency.version.value()).map_err(|err| {
+            syn::Error::new(
+                dependency.version.span(),
+                format!("invalid dependency version requirement: {err}"),
+            )
+        })?;
+        embedded_dependencies.push(EmbeddedDependency {
+            identifier,
+            version,
+            optional: dependency.optional,
+        });
+
+        let DependencyArgs {
+            id,
+            version,
+            optional,
+        } = dependency;
+        dependencies.push(quote! {
+            #etheryal_extension::common::ExtensionModuleDependency::builder()
+                .identifier(
+                    #etheryal_extension::identifier::NamespacedIdentifier::try_from(#id)
+                        .expect("dependency identifier should be valid"),
+                )
+                .version(
+                    #etheryal_extension::semver::VersionReq::parse(#version)
+                        .expect("dependency version requirement should be valid"),
+                )
+                .optional(#optional)
+                .build()
+        });
+    }
+
+    let embedded_info = embed_info(&args, identifier, embedded_dependencies)?;
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis fn #main() {
+            fn #setup(#inputs) #block
+
+            let extension_info = #etheryal_extension::common::ExtensionModuleInfo::builder()
+                .name(#name.into())
+                .identifier(
+                    #etheryal_extension::identifier::NamespacedIdentifier::try_from(#id)
+                        .expect("extension identifier should be valid"),
+                )
+                .version(
+                    #etheryal_extension::semver::Version::parse(env!("CARGO_PKG_VERSION"))
+                        .expect("crate version should be valid semver"),
+                )
+                .dependencies(vec![#(#dependencies),*])
+                .description(#description)
+                .build();
+
+            let mut app = #etheryal_extension::bevy_app::App::new();
+            app.add_plugins(#plugins);
+            #setup(&mut app);
+            app.run();
+        }
+
+        #tick_export
+        #embedded_info
+    })
+}
+
+/// Embeds the extension info in a custom section of the WebAssembly module, so
+/// the extension host can resolve the dependencies of the extension without
+/// running it.
+fn embed_info(
+    args: &MainArgs, identifier: NamespacedIdentifier, dependencies: Vec<EmbeddedDependency>,
+) -> syn::Result<TokenStream> {
+    let package_env = |name: &str| {
+        std::env::var(name).map_err(|_| {
+            syn::Error::new(
+                args.id.span(),
+                format!("`{name}` is not set, the extension must be built with cargo"),
+            )
+        })
+    };
+
+    let name = match &args.name {
+        Some(name) => name.value(),
+        None => package_env("CARGO_PKG_NAME")?,
+    };
+    let description = match &args.description {
+        Some(description) => Some(description.value()),
+        None => Some(package_env("CARGO_PKG_DESCRIPTION")?)
+            .filter(|description| !description.is_empty()),
+    };
+    let version = Version::parse(&package_env("CARGO_PKG_VERSION")?).map_err(|err| {
+        syn::Error::new(
+            args.id.span(),
+            format!("the crate version is not valid semver: {err}"),
+        )
+    })?;
+
+    let encoded = rmp_serde::to_vec_named(&EmbeddedInfo {
+        name,
+        identifier,
+        version,
+        dependencies,
+        description,
+    })
+    .map_err(|err| {
+        syn::Error::new(
+            args.id.span(),
+            format!("failed to encode the extension info: {err}"),
+        )
+    })?;
+    let len = encoded.len();
+
+    Ok(quote! {
+        #[cfg(target_arch = "wasm32")]
+        #[doc(hidden)]
+        #[link_section = #EXTENSION_INFO_SECTION]
+        #[used]
+        static __ETHERYAL_EXTENSION_INFO: [u8; #len] = [#(#encoded),*];
+    })
+}
+
+/// Checks that the annotated function looks like `fn main(app: &mut App)`.
+fn validate_signature(item: &ItemFn) -> syn::Result<()> {
+    let sig = &item.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "the extension entry point cannot be async",
+        ));
+    }
+    if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "the extension entry point cannot be generic",
+        ));
+    }
+    if let ReturnType::Type(_, ty) = &sig.output {
+        return Err(syn::Error::new(
+            ty.span(),
+            "the extension entry point cannot return a value",
+        ));
+    }
+
+    let mut inputs = sig.inputs.iter();
+    match (inputs.next(), inputs.next()) {
+        (Some(FnArg::Typed(_)), None) => Ok(()),
+        (Some(FnArg::Receiver(receiver)), _) => Err(syn::Error::new(
+            receiver.span(),
+            "the extension entry point cannot take `self`",
+        )),
+        (_, Some(extra)) => Err(syn::Error::new(
+            extra.span(),
+            "the extension entry point must take a single `&mut App` argument",
+        )),
+        (None, _) => Err(syn::Error::new(
+            sig.paren_token.span.join(),
+            "the extension entry point must take a single `&mut App` argument",
+        )),
+    }
+}
+
+/// Checks at compile time that a string literal is a valid namespaced
+/// identifier.
+fn validate_identifier(id: &LitStr) -> syn::Result<NamespacedIdentifier> {
+    NamespacedIdentifier::try_from(id.value()).map_err(|err| syn::Error::new(id.span(), err))
+}
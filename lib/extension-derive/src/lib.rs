@@ -1,59 +1,129 @@
 //! Derive macros for the `etheryal-extension` crate.
 #![deny(missing_docs)]
-use darling::FromDeriveInput;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use proc_macro_crate::{crate_name, FoundCrate};
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput, Ident};
+use syn::{parse_macro_input, DeriveInput, Ident, ItemFn};
 
-#[derive(FromDeriveInput)]
-#[darling(attributes(extension_message))]
-/// Sets the destination of an extension message.
-struct MacroArgs {
-    /// A message that is sent from the host to the guest.
-    guest: Option<()>,
-
-    /// A message that is sent from the guest to the host.
-    host: Option<()>,
-}
+mod entry;
+mod message;
 
 /// Derives the `ExtensionMessage` trait for the given type.
+///
+/// The message direction is set with `#[extension_message(guest)]`,
+/// `#[extension_message(host)]` or both. The type must implement
+/// `Serialize`, `Deserialize`, `JsonSchema` and be `Send + Sync + 'static`.
+///
+/// Every derived message is also submitted to the message registry, which
+/// lists its name, tag and schema, including the messages declared outside of
+/// `etheryal-extension-common`. The extension plugins listen for, decode and
+/// encode every registered message.
+///
+/// When the shape of a message changes, its version is incremented with
+/// `#[extension_message(version = N)]`, and the older versions are kept as
+/// separate types converted into the message with `From`, listed with
+/// `migrate(from = Old, version = N)`. Messages encoded by an older host or
+/// guest are then upgraded when decoded, trying the most recent versions first.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+/// #[extension_message(host, version = 2, migrate(from = ChatMessageV1, version = 1))]
+/// pub struct ChatMessage {
+///     text: String,
+///     channel: String,
+/// }
+///
+/// #[derive(Deserialize)]
+/// pub struct ChatMessageV1 {
+///     text: String,
+/// }
+///
+/// impl From<ChatMessageV1> for ChatMessage {
+///     fn from(message: ChatMessageV1) -> Self {
+///         Self { text: message.text, channel: "general".into() }
+///     }
+/// }
+/// ```
 #[proc_macro_derive(ExtensionMessage, attributes(extension_message))]
 pub fn derive_extension_message(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let message = match message_path() {
+        Ok(message) => message,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    message::expand(ast, message).into()
+}
+
+/// Generates the `main` function of an extension module.
+///
+/// The annotated function receives the [`App`] after the etheryal extension
+/// plugins have been added, so it only has to register its own systems. The
+/// extension name, version and description default to the values in the
+/// crate's `Cargo.toml`. The number of updates run after the host requests a
+/// shutdown is set with `shutdown_grace_ticks = N`.
+///
+/// With `host_tick`, the extension exports an `etheryal_tick(delta_nanos)`
+/// function and runs a single update each time the host calls it, instead of
+/// running on its own clock. The `etheryal-extension-host` crate only loads
+/// extensions with `host_tick`.
+///
+/// ```ignore
+/// #[etheryal_extension::main(
+///     id = "example:extension_module",
+///     dependency(id = "etheryal:etheryal", version = ">=0.1.0-nightly"),
+///     host_tick,
+/// )]
+/// fn main(app: &mut App) {
+///     app.add_systems(Startup, setup);
+/// }
+/// ```
+///
+/// [`App`]: https://docs.rs/bevy_app/latest/bevy_app/struct.App.html
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemFn);
     let etheryal_extension = match crate_name("etheryal-extension") {
-        Ok(found_crate) => {
-            let etheryal_extension = match found_crate {
-                FoundCrate::Itself => Ident::new("etheryal_extension", Span::call_site()),
-                FoundCrate::Name(name) => Ident::new(&name, Span::call_site()),
-            };
-            quote! { #etheryal_extension }
+        Ok(FoundCrate::Itself) => quote! { etheryal_extension },
+        Ok(FoundCrate::Name(name)) => {
+            let name = Ident::new(&name, Span::call_site());
+            quote! { #name }
         },
-        _ => {
-            let found_crate = crate_name("etheryal-extension-common")
-                .expect("etheryal-extension-common crate should be present");
-            let etheryal_extension = match found_crate {
-                FoundCrate::Itself => Ident::new("crate", Span::call_site()),
-                FoundCrate::Name(name) => Ident::new(&name, Span::call_site()),
-            };
-            quote! { #etheryal_extension::message }
+        Err(_) => {
+            return syn::Error::new(
+                Span::call_site(),
+                "`#[etheryal_extension::main]` requires the `etheryal-extension` crate",
+            )
+            .to_compile_error()
+            .into();
         },
     };
 
-    let ast = parse_macro_input!(input as DeriveInput);
-    let name = &ast.ident;
-    let attr: MacroArgs = FromDeriveInput::from_derive_input(&ast).expect("attribute parsed");
+    entry::expand(args.into(), item, etheryal_extension).into()
+}
 
-    let mut tokens = proc_macro2::TokenStream::new();
-    if attr.guest.is_some() {
-        tokens.extend(quote! {
-            impl #etheryal_extension::GuestMessage for #name {}
-        });
+/// Resolves the path to the `message` module of `etheryal-extension-common`,
+/// either through the `etheryal-extension` crate or directly.
+fn message_path() -> syn::Result<proc_macro2::TokenStream> {
+    if let Ok(found_crate) = crate_name("etheryal-extension") {
+        let etheryal_extension = match found_crate {
+            FoundCrate::Itself => Ident::new("etheryal_extension", Span::call_site()),
+            FoundCrate::Name(name) => Ident::new(&name, Span::call_site()),
+        };
+        return Ok(quote! { #etheryal_extension::common::message });
     }
-    if attr.host.is_some() {
-        tokens.extend(quote! {
-            impl #etheryal_extension::HostMessage for #name {}
-        });
-    }
-    tokens.into()
+
+    let found_crate = crate_name("etheryal-extension-common").map_err(|_| {
+        syn::Error::new(
+            Span::call_site(),
+            "`ExtensionMessage` requires the `etheryal-extension` or `etheryal-extension-common` \
+             crate",
+        )
+    })?;
+    let etheryal_extension = match found_crate {
+        FoundCrate::Itself => Ident::new("crate", Span::call_site()),
+        FoundCrate::Name(name) => Ident::new(&name, Span::call_site()),
+    };
+    Ok(quote! { #etheryal_extension::message })
 }
@@ -0,0 +1,171 @@
+use darling::{FromDeriveInput, FromMeta};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{DeriveInput, Path};
+
+#[derive(FromDeriveInput)]
+#[darling(attributes(extension_message))]
+/// Sets the destination of an extension message.
+struct MacroArgs {
+    /// A message that is sent from the host to the guest.
+    guest: Option<()>,
+
+    /// A message that is sent from the guest to the host.
+    host: Option<()>,
+
+    /// The version of the message, incremented when its shape changes.
+    #[darling(default = "default_version")]
+    version: u32,
+
+    /// The older versions of the message that can be upgraded to this one.
+    #[darling(multiple, rename = "migrate")]
+    migrations: Vec<MigrationArgs>,
+}
+
+/// A single `migrate(...)` entry of the `#[extension_message]` attribute.
+#[derive(FromMeta)]
+struct MigrationArgs {
+    /// The type of the older version of the message, which must implement
+    /// `Deserialize` and `Into` the message.
+    from: Path,
+
+    /// The version of the older message, the most recent versions are tried
+    /// first.
+    version: u32,
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+pub fn expand(ast: DeriveInput, message: TokenStream) -> TokenStream {
+    match expand_message(&ast, &message) {
+        Ok(tokens) => tokens,
+        Err(err) => err.write_errors(),
+    }
+}
+
+fn expand_message(ast: &DeriveInput, message: &TokenStream) -> darling::Result<TokenStream> {
+    let mut errors = darling::Error::accumulator();
+    let attr = errors.handle(MacroArgs::from_derive_input(ast));
+
+    if !ast.generics.params.is_empty() {
+        errors.push(
+            darling::Error::custom("extension messages cannot have generic parameters")
+                .with_span(&ast.generics),
+        );
+    }
+    if let Some(attr) = &attr {
+        if attr.guest.is_none() && attr.host.is_none() {
+            errors.push(
+                darling::Error::custom(
+                    "missing message direction, add `#[extension_message(guest)]`, \
+                     `#[extension_message(host)]` or both",
+                )
+                .with_span(&ast.ident),
+            );
+        }
+        if attr.version == 0 {
+            errors
+                .push(darling::Error::custom("message versions start at 1").with_span(&ast.ident));
+        }
+        for (index, migration) in attr.migrations.iter().enumerate() {
+            if migration.version == 0 || migration.version >= attr.version {
+                errors.push(
+                    darling::Error::custom(format!(
+                        "migrations must be from a version lower than {}, the version of this \
+                         message",
+                        attr.version
+                    ))
+                    .with_span(&migration.from),
+                );
+            } else if attr.migrations[..index]
+                .iter()
+                .any(|previous| previous.version == migration.version)
+            {
+                errors.push(
+                    darling::Error::custom(format!(
+                        "duplicate migration from version {}",
+                        migration.version
+                    ))
+                    .with_span(&migration.from),
+                );
+            }
+        }
+    }
+    errors.finish()?;
+    let attr = attr.expect("attribute errors should have been reported");
+
+    let name = &ast.ident;
+    let tag = message_tag(&name.to_string());
+    let version = attr.version;
+    // The registrations require the message to be encodable and decodable, so
+    // their bounds are reported on the type name
+    let mut tokens = quote! {
+        impl #message::MessageTag for #name {
+            const TAG: &'static str = #tag;
+        }
+    };
+    if attr.guest.is_some() {
+        tokens.extend(quote! {
+            impl #message::GuestMessage for #name {}
+
+            #message::__private::inventory::submit! {
+                #message::registry::GuestMessageRegistration::of::<#name>()
+                    .with_version(#version)
+            }
+        });
+    }
+    if attr.host.is_some() {
+        tokens.extend(quote! {
+            impl #message::HostMessage for #name {}
+
+            #message::__private::inventory::submit! {
+                #message::registry::HostMessageRegistration::of::<#name>()
+                    .with_version(#version)
+            }
+        });
+    }
+    tokens.extend(submit_migrations(ast, &attr, message));
+    Ok(tokens)
+}
+
+/// Submits the migrations of the message, spanned on the older message type so
+/// a missing `From` implementation is reported there.
+fn submit_migrations(ast: &DeriveInput, attr: &MacroArgs, message: &TokenStream) -> TokenStream {
+    let name = &ast.ident;
+    let tag = message_tag(&ast.ident.to_string());
+    let version = attr.version;
+
+    attr.migrations
+        .iter()
+        .map(|migration| {
+            let from = &migration.from;
+            let from_version = migration.version;
+            quote_spanned! {from.span()=>
+                #message::__private::inventory::submit! {
+                    #message::migration::MessageMigration::of::<#from, #name>(
+                        #tag,
+                        #from_version,
+                        #version,
+                    )
+                }
+            }
+        })
+        .collect()
+}
+
+/// Returns the tag of the message, the name of its type in snake case like the
+/// variants of the message enums, which are `#[serde(rename_all =
+/// "snake_case")]`.
+fn message_tag(name: &str) -> String {
+    let mut tag = String::with_capacity(name.len());
+    for (index, char) in name.char_indices() {
+        if index > 0 && char.is_uppercase() {
+            tag.push('_');
+        }
+        tag.push(char.to_ascii_lowercase());
+    }
+    tag
+}
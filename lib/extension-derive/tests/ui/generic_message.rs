@@ -0,0 +1,11 @@
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+pub struct Message<'a> {
+    text: &'a str,
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct MessageV1;
+
+#[derive(Deserialize)]
+pub struct MessageV2;
+
+#[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+#[extension_message(
+    host,
+    version = 2,
+    migrate(from = MessageV1, version = 1),
+    migrate(from = MessageV1, version = 1),
+    migrate(from = MessageV2, version = 2)
+)]
+pub struct Message;
+
+fn main() {}
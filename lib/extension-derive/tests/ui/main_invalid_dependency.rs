@@ -0,0 +1,7 @@
+#[etheryal_extension::main(
+    id = "example:extension_module",
+    dependency(id = "etheryal:etheryal", version = "latest"),
+)]
+fn setup(_app: &mut bevy_app::App) {}
+
+fn main() {}
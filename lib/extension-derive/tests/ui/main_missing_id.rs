@@ -0,0 +1,4 @@
+#[etheryal_extension::main(dependency(id = "etheryal:etheryal", version = ">=0.1.0"))]
+fn setup(_app: &mut bevy_app::App) {}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[etheryal_extension::main(id = "example:extension_module")]
+struct Extension;
+
+fn main() {}
@@ -0,0 +1,8 @@
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+pub struct Message;
+
+fn main() {}
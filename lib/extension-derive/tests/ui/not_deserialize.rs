@@ -0,0 +1,9 @@
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[derive(Serialize, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+pub struct Message;
+
+fn main() {}
@@ -0,0 +1,8 @@
+use etheryal_extension_derive::ExtensionMessage;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, ExtensionMessage)]
+#[extension_message(host)]
+pub struct Message;
+
+fn main() {}
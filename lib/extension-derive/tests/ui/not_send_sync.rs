@@ -0,0 +1,14 @@
+use std::rc::Rc;
+
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+pub struct Message {
+    #[serde(skip)]
+    counter: Rc<u32>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+pub struct Message;
+
+fn main() {}
@@ -0,0 +1,17 @@
+//! Stands in for the extension host, so the extensions can run natively
+
+#[no_mangle]
+pub extern "C" fn extension_info(_len: usize, _ptr: *const u8) {}
+
+#[no_mangle]
+pub extern "C" fn send_message(_len: usize, _ptr: *const u8) {}
+
+#[no_mangle]
+pub extern "C" fn recv_message() -> usize {
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn read_message_buf(_len: usize, _ptr: *mut u8) -> usize {
+    0
+}
@@ -0,0 +1,12 @@
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A guest message declared outside of the protocol crate
+#[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+#[extension_message(guest)]
+pub struct ChatMessage {
+    text: String,
+}
+
+fn main() {}
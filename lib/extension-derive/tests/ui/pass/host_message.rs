@@ -0,0 +1,17 @@
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A host message declared outside of the protocol crate
+#[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+#[extension_message(host)]
+pub struct Heartbeat;
+
+/// A message sent in both directions
+#[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+#[extension_message(guest, host)]
+pub struct Echo {
+    text: String,
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+use bevy_app::{App, AppExit, Update};
+use bevy_ecs::event::EventWriter;
+
+mod common;
+
+#[etheryal_extension::main(
+    id = "example:extension_module",
+    name = "Example Extension",
+    description = "An example extension",
+    dependency(id = "etheryal:etheryal", version = ">=0.1.0-nightly"),
+    dependency(id = "example:optional", version = "^1", optional = true),
+    shutdown_grace_ticks = 2,
+)]
+fn main(app: &mut App) {
+    app.add_systems(Update, |mut exit: EventWriter<AppExit>| exit.send(AppExit));
+}
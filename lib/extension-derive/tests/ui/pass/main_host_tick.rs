@@ -0,0 +1,6 @@
+use bevy_app::App;
+
+mod common;
+
+#[etheryal_extension::main(id = "example:extension_module", host_tick)]
+fn main(_app: &mut App) {}
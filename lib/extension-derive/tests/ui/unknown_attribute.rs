@@ -0,0 +1,9 @@
+use etheryal_extension_derive::ExtensionMessage;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema, ExtensionMessage)]
+#[extension_message(guest, server)]
+pub struct Message;
+
+fn main() {}
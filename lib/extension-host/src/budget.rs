@@ -0,0 +1,139 @@
+//! Limits how long the update of each extension guest runs, with the fuel
+//! consumed by the WebAssembly instructions
+use std::collections::HashMap;
+
+use bevy_ecs::prelude::*;
+use etheryal_identifier::NamespacedIdentifier;
+use getset::CopyGetters;
+use serde::Deserialize;
+
+/// The fuel an extension guest can consume during each update, and what
+/// happens when it runs out
+///
+/// Set in the `[budget]` table of the manifest of an extension, or by the
+/// extension host with
+/// [ExtensionHostPlugin::with_default_budget](crate::ExtensionHostPlugin::with_default_budget)
+/// and
+/// [ExtensionHostPlugin::with_extension_budget](crate::ExtensionHostPlugin::with_extension_budget).
+/// The budget also limits the entry point of the extensions loaded from an
+/// [ExtensionCatalog](crate::ExtensionCatalog), which fail to load if they run
+/// out of fuel.
+///
+/// ```toml
+/// [budget]
+/// fuel = 10_000_000
+/// on_overrun = { throttle = { ticks = 10 } }
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, CopyGetters)]
+#[getset(get_copy = "pub")]
+#[serde(deny_unknown_fields)]
+pub struct ExtensionBudget {
+    /// The fuel the extension guest can consume during each update, roughly
+    /// one unit per WebAssembly instruction
+    fuel: u64,
+    /// What happens when the extension guest runs out of fuel
+    #[serde(default)]
+    on_overrun: OverrunPolicy,
+}
+
+impl ExtensionBudget {
+    /// Creates a budget of `fuel` per update, resuming the updates which run
+    /// out of fuel during the next update of the extension host
+    pub fn new(fuel: u64) -> Self {
+        Self {
+            fuel,
+            on_overrun: OverrunPolicy::default(),
+        }
+    }
+
+    /// Sets what happens when the extension guest runs out of fuel
+    pub fn with_overrun_policy(mut self, policy: OverrunPolicy) -> Self {
+        self.on_overrun = policy;
+        self
+    }
+}
+
+/// What happens when an extension guest runs out of fuel during an update
+///
+/// The update is suspended where the extension guest ran out of fuel, and
+/// resumed from there once the extension guest runs again, so its state is
+/// never left half updated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum OverrunPolicy {
+    /// The rest of the update is skipped until the next update of the
+    /// extension host, which resumes it
+    #[default]
+    Skip,
+    /// The rest of the update is skipped, and the extension guest doesn't run
+    /// during the next updates of the extension host, after which the update
+    /// is resumed
+    Throttle {
+        /// The number of updates of the extension host the extension guest
+        /// doesn't run
+        ticks: u32,
+    },
+    /// The extension is unloaded like with
+    /// [UnloadExtension](crate::UnloadExtension), its update is resumed until
+    /// it acknowledges the shutdown or its grace period is over
+    Unload,
+}
+
+/// An event sent when an extension guest ran out of fuel during an update
+#[derive(Event, Clone, Debug)]
+pub struct BudgetExceeded {
+    /// The entity of the extension instance
+    pub extension: Entity,
+    /// The identifier of the extension, once it is known
+    pub identifier: Option<NamespacedIdentifier>,
+    /// The budget the extension guest exceeded, with the policy applied
+    pub budget: ExtensionBudget,
+}
+
+/// Marks an extension instance which doesn't run its updates after running
+/// out of fuel, with [OverrunPolicy::Throttle]
+#[derive(Component, Debug)]
+pub struct Throttled {
+    remaining_ticks: u32,
+}
+
+impl Throttled {
+    pub(crate) fn new(ticks: u32) -> Self {
+        Self {
+            remaining_ticks: ticks,
+        }
+    }
+
+    /// Counts down an update, returning whether the extension guest doesn't
+    /// run it
+    pub(crate) fn skip_update(&mut self) -> bool {
+        match self.remaining_ticks.checked_sub(1) {
+            Some(remaining_ticks) => {
+                self.remaining_ticks = remaining_ticks;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// The budgets set by the extension host, which take precedence over the
+/// budgets of the manifests for the extensions with their own budget
+#[derive(Resource, Default)]
+pub(crate) struct BudgetConfig {
+    pub(crate) default: Option<ExtensionBudget>,
+    pub(crate) extensions: HashMap<NamespacedIdentifier, ExtensionBudget>,
+}
+
+impl BudgetConfig {
+    /// Returns the budget of an extension, from the extension host, then its
+    /// manifest, then the default budget of the extension host
+    pub(crate) fn budget_of(
+        &self, identifier: Option<&NamespacedIdentifier>, manifest: Option<ExtensionBudget>,
+    ) -> Option<ExtensionBudget> {
+        identifier
+            .and_then(|identifier| self.extensions.get(identifier).copied())
+            .or(manifest)
+            .or(self.default)
+    }
+}
@@ -0,0 +1,590 @@
+//! Scans the extensions directory, where each extension has its own directory
+//! with an `etheryal.toml` manifest and a WebAssembly module, and loads the
+//! extensions after their dependencies
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy_ecs::component::Component;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::Event;
+use bevy_ecs::query::Without;
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::{Mut, World};
+use etheryal_extension_common::{ExtensionModuleInfo, EXTENSION_INFO_SECTION};
+use etheryal_identifier::NamespacedIdentifier;
+use getset::Getters;
+use semver::Version;
+use tracing::{error, info, warn};
+use wasmparser::{Parser, Payload};
+
+use crate::budget::BudgetConfig;
+use crate::error::{CatalogError, SkipReason};
+use crate::instance::ExtensionInstance;
+use crate::lifecycle::{self, Unloading};
+use crate::manifest::{ExtensionManifest, MANIFEST_FILE};
+use crate::runtime::ExtensionRuntime;
+
+/// An extension found in the extensions directory, kept on the entity of its
+/// extension instance once loaded
+#[derive(Component, Clone, Debug, Getters)]
+#[getset(get = "pub")]
+pub struct CatalogEntry {
+    /// The directory of the extension
+    directory: PathBuf,
+    /// The path of the WebAssembly module of the extension
+    module_path: PathBuf,
+    /// The manifest of the extension
+    manifest: ExtensionManifest,
+    /// The extension info embedded in the WebAssembly module
+    info: ExtensionModuleInfo,
+}
+
+/// The extensions found in the extensions directory
+///
+/// ```ignore
+/// let report = ExtensionCatalog::scan("extensions")?
+///     .with_provided(NamespacedIdentifier::try_from("etheryal:etheryal")?, version)
+///     .load(world);
+/// ```
+#[derive(Debug)]
+pub struct ExtensionCatalog {
+    entries: Vec<CatalogEntry>,
+    failed: Vec<FailedExtension>,
+    provided: BTreeMap<NamespacedIdentifier, Version>,
+}
+
+impl ExtensionCatalog {
+    /// Reads the manifest and the extension info of every extension in the
+    /// subdirectories of `directory`, in the order of their paths
+    ///
+    /// The extensions that could not be read are reported by
+    /// [failed](ExtensionCatalog::failed).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory could not be read
+    pub fn scan(directory: impl AsRef<Path>) -> Result<Self, CatalogError> {
+        let directory = directory.as_ref();
+        let mut directories = Vec::new();
+        for entry in fs::read_dir(directory).map_err(io_error(directory))? {
+            let path = entry.map_err(io_error(directory))?.path();
+            if path.is_dir() {
+                directories.push(path);
+            }
+        }
+        directories.sort();
+        Ok(Self::from_directories(directories))
+    }
+
+    /// Reads the manifest and the extension info of the extension of each
+    /// directory
+    pub(crate) fn from_directories(directories: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut catalog = Self {
+            entries: Vec::new(),
+            failed: Vec::new(),
+            provided: BTreeMap::new(),
+        };
+        for directory in directories {
+            match read_entry(&directory) {
+                Ok(entry) => catalog.entries.push(entry),
+                Err(error) => catalog.failed.push(FailedExtension {
+                    path: directory,
+                    error,
+                }),
+            }
+        }
+        catalog
+    }
+
+    /// Adds a dependency provided by the extension host itself, such as
+    /// `etheryal:etheryal`, which the extensions can depend on
+    pub fn with_provided(mut self, identifier: NamespacedIdentifier, version: Version) -> Self {
+        self.provided.insert(identifier, version);
+        self
+    }
+
+    /// Returns the extensions that were read successfully
+    pub fn entries(&self) -> &[CatalogEntry] {
+        &self.entries
+    }
+
+    /// Returns the extensions that could not be read
+    pub fn failed(&self) -> &[FailedExtension] {
+        &self.failed
+    }
+
+    /// Instantiates the extensions after their dependencies, spawning an entity
+    /// with the [ExtensionInstance] and the [CatalogEntry] of each extension
+    ///
+    /// The extensions already running in the world are dependencies of the
+    /// new extensions, and aren't loaded twice. The world must have the
+    /// [ExtensionRuntime] resource added by the
+    /// [ExtensionHostPlugin](crate::ExtensionHostPlugin).
+    pub fn load(mut self, world: &mut World) -> LoadReport {
+        let running = running_extensions(world);
+        let mut skipped = Vec::new();
+        self.entries
+            .retain(|entry| match running.get(entry.info.identifier()) {
+                Some(&(_, extension)) => {
+                    skipped.push(SkippedExtension::new(
+                        entry,
+                        SkipReason::AlreadyLoaded(extension),
+                    ));
+                    false
+                },
+                None => true,
+            });
+        for (identifier, (version, _)) in running {
+            self.provided.entry(identifier).or_insert(version);
+        }
+
+        let (order, resolved_skipped) = self.resolve();
+        skipped.extend(resolved_skipped);
+        let mut report = LoadReport {
+            loaded: Vec::new(),
+            skipped,
+            failed: Vec::new(),
+        };
+
+        world.resource_scope(|world, runtime: Mut<ExtensionRuntime>| {
+            let mut loaded = BTreeSet::new();
+            for index in order {
+                let entry = &self.entries[index];
+
+                // A dependency may have failed to instantiate
+                let not_loaded = entry.info.dependencies().iter().find(|dependency| {
+                    let identifier = dependency.identifier();
+                    !dependency.optional()
+                        && !self.provided.contains_key(identifier)
+                        && !loaded.contains(identifier)
+                });
+                if let Some(dependency) = not_loaded {
+                    report.skipped.push(SkippedExtension::new(
+                        entry,
+                        SkipReason::DependencyNotLoaded(dependency.identifier().clone()),
+                    ));
+                    continue;
+                }
+
+                let instance = fs::read(&entry.module_path)
+                    .map_err(io_error(&entry.module_path))
+                    .and_then(|wasm| {
+                        let identifier = entry.info.identifier();
+                        let limits = runtime.memory_limits_of(identifier, *entry.manifest.memory());
+                        let budget = world
+                            .get_resource::<BudgetConfig>()
+                            .map_or(*entry.manifest.budget(), |budgets| {
+                                budgets.budget_of(Some(identifier), *entry.manifest.budget())
+                            });
+                        runtime
+                            .load_with_budget(&wasm, limits, budget)
+                            .map_err(|source| CatalogError::Load {
+                                path: entry.module_path.clone(),
+                                source,
+                            })
+                    });
+                match instance {
+                    Ok(instance) => {
+                        let entity = world.spawn((instance, entry.clone())).id();
+                        loaded.insert(entry.info.identifier().clone());
+                        report.loaded.push(LoadedExtension {
+                            identifier: entry.info.identifier().clone(),
+                            path: entry.directory.clone(),
+                            entity,
+                        });
+                    },
+                    Err(error) => report.failed.push(FailedExtension {
+                        path: entry.directory.clone(),
+                        error,
+                    }),
+                }
+            }
+        });
+
+        report.failed.splice(0..0, self.failed);
+        report
+    }
+
+    /// Returns the indices of the extensions in the order they can be loaded,
+    /// each after its dependencies, and the extensions that can't be loaded
+    fn resolve(&self) -> (Vec<usize>, Vec<SkippedExtension>) {
+        let mut skipped = Vec::new();
+
+        // The first extension with an identifier is loaded, the others are skipped
+        let mut identifiers = BTreeMap::new();
+        let mut candidates = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            match identifiers.entry(entry.info.identifier()) {
+                Entry::Vacant(vacant) => {
+                    vacant.insert(index);
+                    candidates.push(index);
+                },
+                Entry::Occupied(occupied) => {
+                    skipped.push(SkippedExtension::new(entry, SkipReason::Duplicate {
+                        first: self.entries[*occupied.get()].directory.clone(),
+                    }))
+                },
+            }
+        }
+
+        let mut remaining = BTreeMap::new();
+        for index in candidates {
+            let entry = &self.entries[index];
+            match self.dependencies_of(entry, &identifiers) {
+                Ok(dependencies) => {
+                    remaining.insert(index, dependencies);
+                },
+                Err(reason) => skipped.push(SkippedExtension::new(entry, reason)),
+            }
+        }
+
+        let mut order = Vec::new();
+        let mut ordered = BTreeSet::new();
+        while !remaining.is_empty() {
+            // An extension is skipped when one of its required dependencies is
+            let unresolvable = remaining.iter().find_map(|(&index, dependencies)| {
+                dependencies
+                    .iter()
+                    .find(|dependency| {
+                        !dependency.optional
+                            && !ordered.contains(&dependency.index)
+                            && !remaining.contains_key(&dependency.index)
+                    })
+                    .map(|dependency| (index, dependency.index))
+            });
+            if let Some((index, dependency)) = unresolvable {
+                remaining.remove(&index);
+                skipped.push(SkippedExtension::new(
+                    &self.entries[index],
+                    SkipReason::DependencyNotLoaded(
+                        self.entries[dependency].info.identifier().clone(),
+                    ),
+                ));
+                continue;
+            }
+
+            let ready = remaining.iter().find_map(|(&index, dependencies)| {
+                dependencies
+                    .iter()
+                    .all(|dependency| !remaining.contains_key(&dependency.index))
+                    .then_some(index)
+            });
+            let Some(index) = ready else {
+                // Every remaining extension depends on another remaining extension
+                for &index in remaining.keys() {
+                    skipped.push(SkippedExtension::new(
+                        &self.entries[index],
+                        SkipReason::DependencyCycle,
+                    ));
+                }
+                break;
+            };
+            remaining.remove(&index);
+            ordered.insert(index);
+            order.push(index);
+        }
+
+        (order, skipped)
+    }
+
+    /// Returns the extensions of the catalog the extension depends on, after
+    /// checking that every dependency is satisfied
+    fn dependencies_of(
+        &self, entry: &CatalogEntry, identifiers: &BTreeMap<&NamespacedIdentifier, usize>,
+    ) -> Result<Vec<Dependency>, SkipReason> {
+        let mut dependencies = Vec::new();
+        for dependency in entry.info.dependencies() {
+            let identifier = dependency.identifier();
+            let (version, index) = if let Some(version) = self.provided.get(identifier) {
+                (version, None)
+            } else if let Some(&index) = identifiers.get(identifier) {
+                (self.entries[index].info.version(), Some(index))
+            } else if *dependency.optional() {
+                continue;
+            } else {
+                return Err(SkipReason::MissingDependency(identifier.clone()));
+            };
+
+            if !dependency.version().matches(version) {
+                return Err(SkipReason::UnsatisfiedDependency {
+                    identifier: identifier.clone(),
+                    required: dependency.version().clone(),
+                    found: version.clone(),
+                });
+            }
+            if let Some(index) = index {
+                dependencies.push(Dependency {
+                    index,
+                    optional: *dependency.optional(),
+                });
+            }
+        }
+        Ok(dependencies)
+    }
+}
+
+/// A dependency of an extension on another extension of the catalog
+struct Dependency {
+    index: usize,
+    optional: bool,
+}
+
+/// The result of loading extensions, kept in a resource once the extensions
+/// directory is loaded at startup, and sent as an event when extensions are
+/// loaded or reloaded later
+#[derive(Resource, Event, Default, Debug, Getters)]
+#[getset(get = "pub")]
+pub struct LoadReport {
+    /// The extensions that were loaded, in the order they were loaded
+    loaded: Vec<LoadedExtension>,
+    /// The extensions that were not loaded because of their dependencies
+    skipped: Vec<SkippedExtension>,
+    /// The extensions that could not be read or instantiated
+    failed: Vec<FailedExtension>,
+}
+
+impl LoadReport {
+    /// Logs the extensions that were loaded, skipped and failed
+    pub fn log(&self) {
+        for loaded in &self.loaded {
+            info!(
+                "Loaded extension '{}' from {}",
+                loaded.identifier,
+                loaded.path.display()
+            );
+        }
+        for skipped in &self.skipped {
+            warn!(
+                "Skipped extension '{}' from {}: {}",
+                skipped.identifier,
+                skipped.path.display(),
+                skipped.reason
+            );
+        }
+        for failed in &self.failed {
+            error!("{}", failed.error);
+        }
+    }
+
+    pub(crate) fn from_error(path: PathBuf, error: CatalogError) -> Self {
+        Self {
+            failed: vec![FailedExtension { path, error }],
+            ..Default::default()
+        }
+    }
+}
+
+/// An extension that was loaded
+#[derive(Debug)]
+pub struct LoadedExtension {
+    /// The identifier of the extension
+    pub identifier: NamespacedIdentifier,
+    /// The directory of the extension
+    pub path: PathBuf,
+    /// The entity of the extension instance
+    pub entity: Entity,
+}
+
+/// An extension that was not loaded because of its dependencies
+#[derive(Debug)]
+pub struct SkippedExtension {
+    /// The identifier of the extension
+    pub identifier: NamespacedIdentifier,
+    /// The directory of the extension
+    pub path: PathBuf,
+    /// The reason the extension was not loaded
+    pub reason: SkipReason,
+}
+
+impl SkippedExtension {
+    fn new(entry: &CatalogEntry, reason: SkipReason) -> Self {
+        Self {
+            identifier: entry.info.identifier().clone(),
+            path: entry.directory.clone(),
+            reason,
+        }
+    }
+}
+
+/// An extension that could not be read or instantiated
+#[derive(Debug)]
+pub struct FailedExtension {
+    /// The directory of the extension
+    pub path: PathBuf,
+    /// The reason the extension could not be loaded
+    pub error: CatalogError,
+}
+
+/// The extensions directory loaded at startup, set with
+/// [ExtensionHostPlugin::with_extensions_dir](crate::ExtensionHostPlugin::with_extensions_dir)
+#[derive(Resource)]
+pub(crate) struct ExtensionsDirectory {
+    pub(crate) path: PathBuf,
+}
+
+pub(crate) fn load_extensions_dir(world: &mut World) {
+    let Some(directory) = world.remove_resource::<ExtensionsDirectory>() else {
+        return;
+    };
+
+    let report = match ExtensionCatalog::scan(&directory.path) {
+        Ok(catalog) => lifecycle::load_catalog(world, catalog),
+        Err(error) => {
+            let report = LoadReport::from_error(directory.path, error);
+            report.log();
+            report
+        },
+    };
+    world.insert_resource(report);
+}
+
+/// Returns the version and the entity of every extension which is running and
+/// not unloading
+fn running_extensions(world: &mut World) -> BTreeMap<NamespacedIdentifier, (Version, Entity)> {
+    running_extension_infos(world)
+        .into_iter()
+        .map(|(extension, info)| {
+            (
+                info.identifier().clone(),
+                (info.version().clone(), extension),
+            )
+        })
+        .collect()
+}
+
+/// Returns the extension info of every extension which is running and not
+/// unloading
+pub(crate) fn running_extension_infos(world: &mut World) -> Vec<(Entity, ExtensionModuleInfo)> {
+    let mut extensions = world.query_filtered::<RunningExtension, Without<Unloading>>();
+    extensions
+        .iter(world)
+        .filter_map(|(extension, instance, entry)| {
+            Some((extension, extension_info(instance, entry)?.clone()))
+        })
+        .collect()
+}
+
+/// Returns the extension info of an extension, read from its module if it was
+/// loaded from an extensions directory, or sent once registered, which matches
+/// its module
+pub(crate) fn extension_info<'a>(
+    instance: &'a ExtensionInstance, entry: Option<&'a CatalogEntry>,
+) -> Option<&'a ExtensionModuleInfo> {
+    entry.map(CatalogEntry::info).or_else(|| instance.info())
+}
+
+type RunningExtension<'a> = (Entity, &'a ExtensionInstance, Option<&'a CatalogEntry>);
+
+/// Reads the extension info embedded in a WebAssembly module by
+/// `#[etheryal_extension::main]`
+///
+/// # Errors
+///
+/// Returns an error if the module is not valid or has no valid extension info
+pub fn read_embedded_info(
+    path: impl AsRef<Path>, wasm: &[u8],
+) -> Result<ExtensionModuleInfo, CatalogError> {
+    let path = path.as_ref();
+    for payload in Parser::new(0).parse_all(wasm) {
+        let payload = payload.map_err(|source| CatalogError::InvalidModule {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        if let Payload::CustomSection(section) = payload {
+            if section.name() == EXTENSION_INFO_SECTION {
+                return rmp_serde::from_slice(section.data()).map_err(|source| {
+                    CatalogError::InvalidInfo {
+                        path: path.to_path_buf(),
+                        source,
+                    }
+                });
+            }
+        }
+    }
+    Err(CatalogError::MissingInfo {
+        path: path.to_path_buf(),
+    })
+}
+
+pub(crate) fn read_entry(directory: &Path) -> Result<CatalogEntry, CatalogError> {
+    let manifest_path = directory.join(MANIFEST_FILE);
+    let manifest = fs::read_to_string(&manifest_path).map_err(io_error(&manifest_path))?;
+    let manifest: ExtensionManifest =
+        toml::from_str(&manifest).map_err(|source| CatalogError::Manifest {
+            path: manifest_path,
+            source,
+        })?;
+
+    let module_path = match manifest.module() {
+        Some(module) => confined_module(directory, module)?,
+        None => find_module(directory)?,
+    };
+    let wasm = fs::read(&module_path).map_err(io_error(&module_path))?;
+    let info = read_embedded_info(&module_path, &wasm)?;
+    // The manifest describes the extension embedded in the module, rather than
+    // naming another one
+    if *manifest.id() != info.identifier().to_string() || manifest.version() != info.version() {
+        return Err(CatalogError::InfoMismatch {
+            path: directory.join(MANIFEST_FILE),
+            manifest_id: manifest.id().clone(),
+            manifest_version: manifest.version().clone(),
+            info: Box::new(info),
+        });
+    }
+
+    Ok(CatalogEntry {
+        directory: directory.to_path_buf(),
+        module_path,
+        manifest,
+        info,
+    })
+}
+
+/// Resolves the module set in the manifest of an extension, which must be
+/// inside the directory of the extension once its links are followed
+fn confined_module(directory: &Path, module: &Path) -> Result<PathBuf, CatalogError> {
+    let path = directory.join(module);
+    let canonical_directory = directory.canonicalize().map_err(io_error(directory))?;
+    let canonical_path = path.canonicalize().map_err(io_error(&path))?;
+    if !canonical_path.starts_with(&canonical_directory) {
+        return Err(CatalogError::ModuleOutsideDirectory {
+            path,
+            directory: directory.to_path_buf(),
+        });
+    }
+    Ok(path)
+}
+
+/// Finds the only WebAssembly module in the directory of an extension
+fn find_module(directory: &Path) -> Result<PathBuf, CatalogError> {
+    let mut modules = Vec::new();
+    for entry in fs::read_dir(directory).map_err(io_error(directory))? {
+        let path = entry.map_err(io_error(directory))?.path();
+        if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|extension| extension == "wasm")
+        {
+            modules.push(path);
+        }
+    }
+
+    match modules.len() {
+        0 => Err(CatalogError::MissingModule {
+            directory: directory.to_path_buf(),
+        }),
+        1 => Ok(modules.remove(0)),
+        _ => Err(CatalogError::AmbiguousModule {
+            directory: directory.to_path_buf(),
+        }),
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> CatalogError + '_ {
+    move |source| CatalogError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
@@ -0,0 +1,114 @@
+use std::any::type_name;
+use std::sync::Arc;
+
+use bevy_ecs::entity::Entity;
+use bevy_ecs::system::Command;
+use bevy_ecs::world::World;
+use etheryal_extension_common::message::{encode_message, GuestMessage, MessageTag};
+use serde::Serialize;
+use tracing::{error, warn};
+
+use crate::instance::ExtensionInstance;
+
+/// The extension instances a [SendToGuest] command sends its message to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GuestTarget {
+    /// The extension instance of the entity
+    Extension(Entity),
+    /// Every loaded extension instance
+    Broadcast,
+}
+
+/// A command sending a message to one or every extension guest, received
+/// during their next update
+///
+/// ```ignore
+/// fn reply(mut commands: Commands, mut pings: EventReader<HostMessageEvent<Ping>>) {
+///     for ping in pings.iter() {
+///         commands.add(SendToGuest::new(ping.extension, Pong));
+///     }
+/// }
+/// ```
+///
+/// The message is encoded when the command is created, so any guest message can
+/// be sent, including the messages derived outside of
+/// `etheryal-extension-common`.
+#[derive(Clone, Debug)]
+pub struct SendToGuest {
+    target: GuestTarget,
+    type_name: &'static str,
+    encoded: Result<Vec<u8>, Arc<rmp_serde::encode::Error>>,
+}
+
+impl SendToGuest {
+    /// Creates a command sending the message to the extension instance of the
+    /// entity `extension`
+    pub fn new<G>(extension: Entity, message: G) -> Self
+    where
+        G: GuestMessage + MessageTag + Serialize, {
+        Self::with_target(GuestTarget::Extension(extension), &message)
+    }
+
+    /// Creates a command sending the message to every loaded extension instance
+    pub fn broadcast<G>(message: G) -> Self
+    where
+        G: GuestMessage + MessageTag + Serialize, {
+        Self::with_target(GuestTarget::Broadcast, &message)
+    }
+
+    fn with_target<G>(target: GuestTarget, message: &G) -> Self
+    where
+        G: GuestMessage + MessageTag + Serialize, {
+        Self {
+            target,
+            type_name: type_name::<G>(),
+            encoded: encode_message(message).map_err(Arc::new),
+        }
+    }
+
+    /// Returns the extension instances the message is sent to
+    pub fn target(&self) -> GuestTarget {
+        self.target
+    }
+
+    /// Returns the name of the type of the message sent to the extension
+    /// guests
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+impl Command for SendToGuest {
+    fn apply(self, world: &mut World) {
+        let type_name = self.type_name;
+        let encoded = match self.encoded {
+            Ok(encoded) => encoded,
+            Err(err) => {
+                error!("Failed to encode '{type_name}' for the extension guests: {err}");
+                return;
+            },
+        };
+
+        match self.target {
+            GuestTarget::Extension(extension) => {
+                let Some(mut instance) = world.get_mut::<ExtensionInstance>(extension) else {
+                    warn!(
+                        "Failed to send '{type_name}' to {extension:?}, which is not an extension"
+                    );
+                    return;
+                };
+                if let Err(err) = instance.send_encoded(encoded) {
+                    warn!("Failed to send '{type_name}' to {extension:?}: {err}");
+                }
+            },
+            GuestTarget::Broadcast => {
+                let mut instances = world.query::<(Entity, &mut ExtensionInstance)>();
+                for (extension, mut instance) in instances.iter_mut(world) {
+                    if let Err(err) = instance.send_encoded(encoded.clone()) {
+                        warn!("Failed to send '{type_name}' to {extension:?}: {err}");
+                    }
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::io;
+use std::path::PathBuf;
+
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::Event;
+use etheryal_extension_common::message::limits::ResourceExhausted;
+use etheryal_extension_common::ExtensionModuleInfo;
+use etheryal_identifier::NamespacedIdentifier;
+use semver::{Version, VersionReq};
+use thiserror::Error;
+
+/// An error that can occur when running an extension guest
+#[derive(Error, Debug)]
+pub enum ExtensionHostError {
+    /// The extension module could not be compiled or instantiated, or it
+    /// trapped while running
+    #[error("WebAssembly error: {0}")]
+    Wasm(#[from] wasmtime::Error),
+
+    /// An error occurred while encoding a message
+    #[error("Failed to encode message: {0}")]
+    Encode(#[from] rmp_serde::encode::Error),
+
+    /// The extension module doesn't export a function required by the
+    /// extension host
+    #[error("The extension module doesn't export `{0}`")]
+    MissingExport(&'static str),
+
+    /// The extension module has no valid extension info embedded by
+    /// `#[etheryal_extension::main]`
+    #[error("Invalid extension module: {0}")]
+    InvalidModule(#[source] Box<CatalogError>),
+
+    /// The extension info sent by the extension guest doesn't match the
+    /// extension info embedded in its module
+    #[error(
+        "The extension guest sent the extension info of '{0}', which doesn't match its module"
+    )]
+    InfoMismatch(NamespacedIdentifier),
+
+    /// The extension guest consumed all the fuel of its budget before the end
+    /// of its update, which is suspended, or of its entry point
+    #[error("The extension guest ran out of its {0} fuel")]
+    OutOfFuel(u64),
+
+    /// The extension guest exceeded its memory limits, or a message exceeded
+    /// its maximum message size
+    #[error("The extension guest exhausted a resource: {0}")]
+    ResourceExhausted(ResourceExhausted),
+}
+
+impl ExtensionHostError {
+    /// Returns the error of the extension host behind a trap of the extension
+    /// guest, such as an exhausted resource
+    pub(crate) fn from_guest(error: wasmtime::Error) -> Self {
+        error.downcast::<Self>().unwrap_or_else(Self::Wasm)
+    }
+}
+
+/// An event sent when an extension guest trapped, after its entity was
+/// despawned and its restart was scheduled
+#[derive(Event, Debug)]
+pub struct ExtensionTrapped {
+    /// The entity of the extension instance that trapped
+    pub extension: Entity,
+    /// The identifier of the extension, once it is known
+    pub identifier: Option<NamespacedIdentifier>,
+    /// The reason the extension guest trapped
+    pub error: ExtensionHostError,
+}
+
+/// An error that makes an entry of the extensions directory unloadable
+#[derive(Error, Debug)]
+pub enum CatalogError {
+    /// A file or directory could not be read
+    #[error("Failed to read {}: {source}", path.display())]
+    Io {
+        /// The path that could not be read
+        path: PathBuf,
+        /// The reason it could not be read
+        source: io::Error,
+    },
+
+    /// The manifest is not valid
+    #[error("Invalid manifest {}: {source}", path.display())]
+    Manifest {
+        /// The path of the manifest
+        path: PathBuf,
+        /// The reason the manifest is not valid
+        source: toml::de::Error,
+    },
+
+    /// The directory of the extension has no WebAssembly module
+    #[error("No WebAssembly module in {}", directory.display())]
+    MissingModule {
+        /// The directory of the extension
+        directory: PathBuf,
+    },
+
+    /// The directory of the extension has several WebAssembly modules, and the
+    /// manifest doesn't tell which one to load
+    #[error(
+        "Several WebAssembly modules in {}, set `module` in the manifest",
+        directory.display()
+    )]
+    AmbiguousModule {
+        /// The directory of the extension
+        directory: PathBuf,
+    },
+
+    /// The module set in the manifest is not inside the directory of the
+    /// extension
+    #[error(
+        "The module {} is outside of the directory of its extension {}",
+        path.display(),
+        directory.display()
+    )]
+    ModuleOutsideDirectory {
+        /// The path of the module
+        path: PathBuf,
+        /// The directory of the extension
+        directory: PathBuf,
+    },
+
+    /// The identifier or version in the manifest doesn't match the extension
+    /// info embedded in the WebAssembly module
+    #[error(
+        "The manifest {} describes '{manifest_id}' version {manifest_version}, but its module is \
+         '{}' version {}",
+        path.display(),
+        info.identifier(),
+        info.version()
+    )]
+    InfoMismatch {
+        /// The path of the manifest
+        path: PathBuf,
+        /// The identifier in the manifest
+        manifest_id: String,
+        /// The version in the manifest
+        manifest_version: Version,
+        /// The extension info embedded in the module
+        info: Box<ExtensionModuleInfo>,
+    },
+
+    /// The WebAssembly module could not be parsed
+    #[error("Invalid WebAssembly module {}: {source}", path.display())]
+    InvalidModule {
+        /// The path of the module
+        path: PathBuf,
+        /// The reason the module could not be parsed
+        source: wasmparser::BinaryReaderError,
+    },
+
+    /// The WebAssembly module has no embedded extension info, it was not built
+    /// with `#[etheryal_extension::main]`
+    #[error("No extension info embedded in {}", path.display())]
+    MissingInfo {
+        /// The path of the module
+        path: PathBuf,
+    },
+
+    /// The extension info embedded in the WebAssembly module is not valid
+    #[error("Invalid extension info embedded in {}: {source}", path.display())]
+    InvalidInfo {
+        /// The path of the module
+        path: PathBuf,
+        /// The reason the extension info is not valid
+        source: rmp_serde::decode::Error,
+    },
+
+    /// The WebAssembly module could not be instantiated
+    #[error("Failed to load {}: {source}", path.display())]
+    Load {
+        /// The path of the module
+        path: PathBuf,
+        /// The reason the module could not be instantiated
+        source: ExtensionHostError,
+    },
+}
+
+/// The reason an extension of the extensions directory was not loaded
+#[derive(Error, Debug)]
+pub enum SkipReason {
+    /// Another extension with the same identifier was found first
+    #[error("Duplicate of the extension in {}", first.display())]
+    Duplicate {
+        /// The directory of the extension loaded instead
+        first: PathBuf,
+    },
+
+    /// A required dependency was not found
+    #[error("Missing dependency '{0}'")]
+    MissingDependency(NamespacedIdentifier),
+
+    /// The version of a dependency doesn't match the required version
+    #[error("Dependency '{identifier}' requires version {required}, found {found}")]
+    UnsatisfiedDependency {
+        /// The identifier of the dependency
+        identifier: NamespacedIdentifier,
+        /// The required version of the dependency
+        required: VersionReq,
+        /// The version of the dependency found
+        found: Version,
+    },
+
+    /// A required dependency was not loaded
+    #[error("Dependency '{0}' was not loaded")]
+    DependencyNotLoaded(NamespacedIdentifier),
+
+    /// The extension depends on itself through its dependencies
+    #[error("Dependency cycle")]
+    DependencyCycle,
+
+    /// An extension with the same identifier is already running
+    #[error("Already loaded as {0:?}")]
+    AlreadyLoaded(Entity),
+}
@@ -0,0 +1,232 @@
+//! The default handlers of the core messages sent by the extension guests,
+//! which can be disabled with
+//! [ExtensionHostPlugin::without_default_handler](crate::ExtensionHostPlugin::without_default_handler)
+use std::collections::BTreeSet;
+
+use bevy_app::AppExit;
+use bevy_ecs::prelude::*;
+use etheryal_extension_common::message::debug::{Ping, Pong};
+use etheryal_extension_common::message::direct::{DirectMessage, DirectMessageReceived};
+use etheryal_extension_common::message::events::{
+    GuestPanicked, ShutdownAcknowledged, ShutdownHost,
+};
+use etheryal_extension_common::message::log::LogRecord;
+use etheryal_extension_common::message::stats::{GuestStats, MessagesDropped};
+use etheryal_extension_common::ExtensionModuleInfo;
+use etheryal_identifier::NamespacedIdentifier;
+use tracing::{debug, error, info, warn};
+
+use crate::catalog::{self, CatalogEntry};
+use crate::command::SendToGuest;
+use crate::instance::ExtensionInstance;
+use crate::lifecycle::Unloading;
+use crate::HostMessageEvent;
+
+/// The extensions allowed to shut down the extension host with a
+/// [ShutdownHost] message, none by default
+#[derive(Resource, Clone, Default, Debug)]
+pub enum ShutdownPermission {
+    /// No extension can shut down the extension host
+    #[default]
+    Deny,
+    /// Every extension can shut down the extension host
+    AllowAll,
+    /// Only the extensions with these identifiers can shut down the extension
+    /// host
+    Allow(BTreeSet<NamespacedIdentifier>),
+}
+
+impl ShutdownPermission {
+    /// Returns whether the extension can shut down the extension host
+    pub fn allows(&self, extension: &ExtensionModuleInfo) -> bool {
+        match self {
+            Self::Deny => false,
+            Self::AllowAll => true,
+            Self::Allow(identifiers) => identifiers.contains(extension.identifier()),
+        }
+    }
+}
+
+/// The latest statistics sent by an extension guest, kept on the entity of its
+/// extension instance
+#[derive(Component, Clone, Debug)]
+pub struct LatestGuestStats(pub GuestStats);
+
+pub(crate) fn reply_to_ping(
+    mut commands: Commands, mut pings: EventReader<HostMessageEvent<Ping>>,
+) {
+    for ping in pings.iter() {
+        commands.add(SendToGuest::new(ping.extension, Pong));
+    }
+}
+
+pub(crate) fn shutdown_host(
+    mut requests: EventReader<HostMessageEvent<ShutdownHost>>, permission: Res<ShutdownPermission>,
+    instances: Query<&ExtensionInstance>, mut exit: EventWriter<AppExit>,
+) {
+    for request in requests.iter() {
+        let info = instances
+            .get(request.extension)
+            .ok()
+            .and_then(ExtensionInstance::info);
+        match info {
+            Some(info) if permission.allows(info) => {
+                info!(
+                    "The extension '{}' requested the extension host to shut down",
+                    info.identifier()
+                );
+                exit.send(AppExit);
+                return;
+            },
+            Some(info) => warn!(
+                "The extension '{}' is not allowed to shut down the extension host",
+                info.identifier()
+            ),
+            None => warn!(
+                "Ignored a shutdown request from the unregistered extension {:?}",
+                request.extension
+            ),
+        }
+    }
+}
+
+pub(crate) fn despawn_acknowledged(
+    mut commands: Commands,
+    mut acknowledgements: EventReader<HostMessageEvent<ShutdownAcknowledged>>,
+) {
+    for acknowledgement in acknowledgements.iter() {
+        info!(
+            "The extension {:?} finished shutting down",
+            acknowledgement.extension
+        );
+        if let Some(mut extension) = commands.get_entity(acknowledgement.extension) {
+            extension.despawn();
+        }
+    }
+}
+
+pub(crate) fn emit_log_records(
+    mut records: EventReader<HostMessageEvent<LogRecord>>, instances: Query<&ExtensionInstance>,
+) {
+    for record in records.iter() {
+        let info = instances
+            .get(record.extension)
+            .ok()
+            .and_then(ExtensionInstance::info);
+        if let Some(info) = info {
+            record.emit(info.identifier());
+        }
+    }
+}
+
+pub(crate) fn log_guest_panics(mut panics: EventReader<HostMessageEvent<GuestPanicked>>) {
+    for panic in panics.iter() {
+        match panic.location() {
+            Some(location) => error!(
+                "The extension {:?} panicked at {}:{}:{}: {}",
+                panic.extension,
+                location.file(),
+                location.line(),
+                location.column(),
+                panic.message()
+            ),
+            None => error!(
+                "The extension {:?} panicked: {}",
+                panic.extension,
+                panic.message()
+            ),
+        }
+        if let Some(backtrace) = panic.backtrace() {
+            debug!(
+                "Backtrace of the panic of {:?}:\n{backtrace}",
+                panic.extension
+            );
+        }
+    }
+}
+
+pub(crate) fn store_guest_stats(
+    mut commands: Commands, mut stats: EventReader<HostMessageEvent<GuestStats>>,
+) {
+    for stats in stats.iter() {
+        if let Some(mut extension) = commands.get_entity(stats.extension) {
+            extension.insert(LatestGuestStats(stats.message.clone()));
+        }
+    }
+}
+
+pub(crate) fn route_direct_messages(
+    mut messages: EventReader<HostMessageEvent<DirectMessage>>,
+    mut instances: Query<(
+        &mut ExtensionInstance,
+        Option<&CatalogEntry>,
+        Option<&Unloading>,
+    )>,
+) {
+    for message in messages.iter() {
+        // The sender and the target are identified by the extension info of their
+        // modules, which they can't change at runtime
+        let Some(sender) = instances
+            .get(message.extension)
+            .ok()
+            .and_then(|(instance, entry, _)| catalog::extension_info(instance, entry).cloned())
+        else {
+            warn!(
+                "Ignored a direct message from the unregistered extension {:?}",
+                message.extension
+            );
+            continue;
+        };
+        let target = instances.iter_mut().find(|(instance, entry, unloading)| {
+            unloading.is_none()
+                && catalog::extension_info(instance, *entry)
+                    .is_some_and(|info| info.identifier() == message.to())
+        });
+        let Some((mut target, entry, _)) = target else {
+            warn!(
+                "The extension '{}' sent a message to '{}', which is not running",
+                sender.identifier(),
+                message.to()
+            );
+            continue;
+        };
+
+        // The sender must depend on the target, or the target accept its messages
+        let depends = sender
+            .dependencies()
+            .iter()
+            .any(|dependency| dependency.identifier() == message.to());
+        let accepted =
+            entry.is_some_and(|entry| entry.manifest().accepts_messages_from(sender.identifier()));
+        if !depends && !accepted {
+            warn!(
+                "The extension '{}' is not allowed to send messages to '{}'",
+                sender.identifier(),
+                message.to()
+            );
+            continue;
+        }
+
+        let received =
+            DirectMessageReceived::new(sender.identifier().clone(), message.payload().to_vec());
+        if let Err(err) = target.send_message(received) {
+            warn!(
+                "Failed to route a message from '{}' to '{}': {err}",
+                sender.identifier(),
+                message.to()
+            );
+        }
+    }
+}
+
+pub(crate) fn log_dropped_messages(mut reports: EventReader<HostMessageEvent<MessagesDropped>>) {
+    for report in reports.iter() {
+        warn!(
+            "The extension {:?} dropped {} '{}' messages, its queue of {} messages is full",
+            report.extension,
+            report.count(),
+            report.tag(),
+            report.capacity()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+use bevy_ecs::component::Component;
+use etheryal_extension_common::message::{GuestMessage, MessageTag};
+use etheryal_extension_common::ExtensionModuleInfo;
+use serde::Serialize;
+use wasmtime::{Store, TypedFunc};
+
+use crate::error::ExtensionHostError;
+use crate::limits::MemoryLimits;
+use crate::runtime::{GuestContext, GuestState};
+
+/// An update of an extension guest, which owns its store until it returns
+type UpdateFuture =
+    Pin<Box<dyn Future<Output = (Store<GuestContext>, wasmtime::Result<()>)> + Send>>;
+
+/// Where an extension instance is in its updates
+enum UpdateState {
+    /// The last update returned, the next one starts from the beginning
+    Idle(Store<GuestContext>),
+    /// The last update ran out of its `fuel`, and is resumed from where it was
+    /// suspended
+    Suspended {
+        update: Mutex<UpdateFuture>,
+        fuel: u64,
+    },
+    /// An update panicked along with its store
+    Poisoned,
+}
+
+/// A loaded extension module, created by
+/// [ExtensionRuntime::load](crate::ExtensionRuntime::load)
+///
+/// The [ExtensionHostPlugin](crate::ExtensionHostPlugin) runs an update of
+/// every entity with this component once per update of the host.
+#[derive(Component)]
+pub struct ExtensionInstance {
+    update: UpdateState,
+    state: Arc<GuestState>,
+    tick: TypedFunc<u64, ()>,
+    last_tick: Option<Instant>,
+}
+
+impl ExtensionInstance {
+    pub(crate) fn new(
+        store: Store<GuestContext>, tick: TypedFunc<u64, ()>, state: Arc<GuestState>,
+    ) -> Self {
+        Self {
+            update: UpdateState::Idle(store),
+            state,
+            tick,
+            last_tick: None,
+        }
+    }
+
+    /// Returns the information sent by the extension guest, once it has
+    /// registered during its first update
+    ///
+    /// Its identifier, version and dependencies match the information embedded
+    /// in the module, otherwise the extension guest trapped while registering.
+    pub fn info(&self) -> Option<&ExtensionModuleInfo> {
+        self.state.info.get()
+    }
+
+    /// Queues a message for the extension guest, received during its next
+    /// update
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message could not be encoded, or if it exceeds
+    /// the maximum message size of the extension, in which case the extension
+    /// guest is sent a `ResourceExhausted` message instead
+    pub fn send_message<G>(&mut self, message: G) -> Result<(), ExtensionHostError>
+    where
+        G: GuestMessage + MessageTag + Serialize, {
+        self.state.queue_message(&message)
+    }
+
+    /// Queues an encoded message for the extension guest
+    pub(crate) fn send_encoded(&mut self, encoded: Vec<u8>) -> Result<(), ExtensionHostError> {
+        self.state.queue_encoded(encoded)
+    }
+
+    /// Returns the memory limits of the extension guest
+    pub fn memory_limits(&self) -> MemoryLimits {
+        self.state.limits
+    }
+
+    /// Returns the size of the linear memory of the extension guest, in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.state.usage.memory.load(Ordering::Relaxed)
+    }
+
+    /// Returns the largest size the linear memory of the extension guest
+    /// reached, in bytes
+    pub fn peak_memory_usage(&self) -> usize {
+        self.state.usage.peak_memory.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the last update of the extension guest ran out of fuel,
+    /// and is resumed by its next update
+    pub fn is_suspended(&self) -> bool {
+        matches!(self.update, UpdateState::Suspended { .. })
+    }
+
+    /// Runs an update of the extension guest, which can consume `fuel`, or
+    /// resumes the suspended update with the fuel it started with
+    ///
+    /// Returns [ExtensionHostError::OutOfFuel] if the update ran out of fuel,
+    /// in which case it is suspended until the next call.
+    pub(crate) fn tick(&mut self, now: Instant, fuel: u64) -> Result<(), ExtensionHostError> {
+        let (mut update, fuel) = match std::mem::replace(&mut self.update, UpdateState::Poisoned) {
+            UpdateState::Idle(mut store) => {
+                let delta = self
+                    .last_tick
+                    .map_or(0, |last_tick| (now - last_tick).as_nanos());
+                self.last_tick = Some(now);
+
+                store.fuel_async_yield_interval(yield_interval(fuel))?;
+                store.set_fuel(u64::MAX)?;
+                let tick = self.tick.clone();
+                let delta = u64::try_from(delta).unwrap_or(u64::MAX);
+                let update: UpdateFuture = Box::pin(async move {
+                    let result = tick.call_async(&mut store, delta).await;
+                    (store, result)
+                });
+                (update, fuel)
+            },
+            UpdateState::Suspended { update, fuel } => (
+                update.into_inner().unwrap_or_else(PoisonError::into_inner),
+                fuel,
+            ),
+            UpdateState::Poisoned => {
+                return Err(ExtensionHostError::Wasm(wasmtime::Error::msg(
+                    "a previous update of the extension guest panicked",
+                )));
+            },
+        };
+
+        match poll_once(update.as_mut()) {
+            Poll::Ready((store, result)) => {
+                self.update = UpdateState::Idle(store);
+                result.map_err(ExtensionHostError::from_guest)
+            },
+            Poll::Pending => {
+                self.update = UpdateState::Suspended {
+                    update: Mutex::new(update),
+                    fuel,
+                };
+                Err(ExtensionHostError::OutOfFuel(fuel))
+            },
+        }
+    }
+
+    /// Takes the encoded messages sent by the extension guest since the last
+    /// call
+    pub(crate) fn take_messages(&mut self) -> Vec<Vec<u8>> {
+        self.state.take_sent()
+    }
+}
+
+/// Returns how often an extension guest with `fuel` per update is suspended,
+/// or `None` if its updates are never suspended
+pub(crate) fn yield_interval(fuel: u64) -> Option<u64> {
+    (fuel < u64::MAX).then_some(fuel.max(1))
+}
+
+/// Polls a future once, the extension guests suspend themselves when they run
+/// out of fuel and don't wait for anything else
+pub(crate) fn poll_once<F>(future: Pin<&mut F>) -> Poll<F::Output>
+where
+    F: Future + ?Sized, {
+    future.poll(&mut Context::from_waker(Waker::noop()))
+}
@@ -0,0 +1,343 @@
+//! A Bevy plugin that loads etheryal WebAssembly extensions and exchanges
+//! messages with them, the extension host counterpart of the
+//! `EtheryalExtensionPlugin`.
+//!
+//! Every loaded extension is an entity with an [ExtensionInstance]. The
+//! messages sent by the extension guests are received as
+//! [HostMessageEvent]s, and messages are sent to them with the [SendToGuest]
+//! command. The extensions of the extensions directory are loaded at startup
+//! with [ExtensionHostPlugin::with_extensions_dir], and can be loaded,
+//! unloaded and reloaded later with the [LoadExtension], [UnloadExtension]
+//! and [ReloadExtension] commands. Messages published on topics are sent to the
+//! extensions in [TopicSubscriptions]. The fuel each extension can consume
+//! during an update is limited by its [ExtensionBudget], and its memory by its
+//! [MemoryLimits]. An update which runs out of fuel is suspended and handled
+//! according to its [OverrunPolicy]. An extension which traps is despawned,
+//! and restarted according to its [RestartPolicy].
+#![deny(missing_docs, clippy::missing_safety_doc)]
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use bevy_app::{App, Plugin, PreUpdate, Startup};
+use bevy_ecs::schedule::{BoxedScheduleLabel, IntoSystemConfigs, ScheduleLabel};
+use budget::BudgetConfig;
+pub use budget::{BudgetExceeded, ExtensionBudget, OverrunPolicy, Throttled};
+use catalog::ExtensionsDirectory;
+pub use catalog::{
+    read_embedded_info, CatalogEntry, ExtensionCatalog, FailedExtension, LoadReport,
+    LoadedExtension, SkippedExtension,
+};
+pub use command::{GuestTarget, SendToGuest};
+pub use error::{CatalogError, ExtensionHostError, ExtensionTrapped, SkipReason};
+use etheryal_extension_common::message::debug::Ping;
+use etheryal_extension_common::message::direct::DirectMessage;
+pub use etheryal_extension_common::message::event::HostMessageEvent;
+use etheryal_extension_common::message::events::{
+    GuestPanicked, ShutdownAcknowledged, ShutdownHost,
+};
+use etheryal_extension_common::message::log::LogRecord;
+use etheryal_extension_common::message::stats::{GuestStats, MessagesDropped};
+use etheryal_extension_common::message::{registry, HostMessage};
+use etheryal_identifier::NamespacedIdentifier;
+pub use handlers::{LatestGuestStats, ShutdownPermission};
+pub use instance::ExtensionInstance;
+use lifecycle::ExtensionLifecycle;
+pub use lifecycle::{
+    LoadExtension, ReloadExtension, UnloadExtension, Unloading, DEFAULT_UNLOAD_GRACE_TICKS,
+};
+pub use limits::{MemoryLimits, DEFAULT_MAX_MEMORY, DEFAULT_MAX_MESSAGE_SIZE};
+pub use manifest::{ExtensionManifest, MANIFEST_FILE};
+use restart::RestartConfig;
+pub use restart::{RestartPolicy, DEFAULT_RESTART_RESET_TICKS};
+pub use runtime::ExtensionRuntime;
+use semver::Version;
+pub use topics::{PublishTopic, TopicSubscriptions};
+use tracing::error;
+use watcher::ModuleWatcher;
+
+mod budget;
+mod catalog;
+mod command;
+mod error;
+mod handlers;
+mod instance;
+mod lifecycle;
+mod limits;
+mod manifest;
+mod restart;
+mod runtime;
+mod systems;
+mod topics;
+mod watcher;
+
+/// A Bevy plugin that runs the loaded etheryal extensions.
+pub struct ExtensionHostPlugin {
+    schedule: BoxedScheduleLabel,
+    shutdown_permission: ShutdownPermission,
+    disabled_handlers: HashSet<TypeId>,
+    extensions_dir: Option<PathBuf>,
+    provided: Vec<(NamespacedIdentifier, Version)>,
+    hot_reload: bool,
+    unload_grace_ticks: u32,
+    default_budget: Option<ExtensionBudget>,
+    extension_budgets: HashMap<NamespacedIdentifier, ExtensionBudget>,
+    memory_limits: MemoryLimits,
+    extension_memory_limits: HashMap<NamespacedIdentifier, MemoryLimits>,
+    default_restart_policy: RestartPolicy,
+    extension_restart_policies: HashMap<NamespacedIdentifier, RestartPolicy>,
+    restart_reset_ticks: u32,
+}
+
+impl ExtensionHostPlugin {
+    /// Create a new plugin running the extensions in `PreUpdate`
+    pub fn new() -> Self {
+        Self {
+            schedule: Box::new(PreUpdate),
+            shutdown_permission: ShutdownPermission::default(),
+            disabled_handlers: HashSet::new(),
+            extensions_dir: None,
+            provided: Vec::new(),
+            hot_reload: false,
+            unload_grace_ticks: DEFAULT_UNLOAD_GRACE_TICKS,
+            default_budget: None,
+            extension_budgets: HashMap::new(),
+            memory_limits: MemoryLimits::default(),
+            extension_memory_limits: HashMap::new(),
+            default_restart_policy: RestartPolicy::default(),
+            extension_restart_policies: HashMap::new(),
+            restart_reset_ticks: DEFAULT_RESTART_RESET_TICKS,
+        }
+    }
+
+    /// Loads the extensions of the directory at startup, with the
+    /// [LoadReport] in a resource
+    ///
+    /// Each extension has its own subdirectory, with an `etheryal.toml`
+    /// manifest and a WebAssembly module. See [ExtensionCatalog].
+    pub fn with_extensions_dir(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.extensions_dir = Some(directory.into());
+        self
+    }
+
+    /// Reloads the extensions of the extensions directory when their
+    /// WebAssembly module changes, with the extensions depending on them
+    pub fn with_hot_reload(mut self) -> Self {
+        self.hot_reload = true;
+        self
+    }
+
+    /// Sets the number of updates an unloading extension has to acknowledge
+    /// its shutdown before it is despawned anyway,
+    /// [DEFAULT_UNLOAD_GRACE_TICKS] by default
+    pub fn with_unload_grace_ticks(mut self, ticks: u32) -> Self {
+        self.unload_grace_ticks = ticks;
+        self
+    }
+
+    /// Sets the budget of the extensions without a budget in their manifest,
+    /// which are not limited by default
+    pub fn with_default_budget(mut self, budget: ExtensionBudget) -> Self {
+        self.default_budget = Some(budget);
+        self
+    }
+
+    /// Sets the budget of the extension with the identifier, replacing the
+    /// budget of its manifest
+    pub fn with_extension_budget(
+        mut self, identifier: NamespacedIdentifier, budget: ExtensionBudget,
+    ) -> Self {
+        self.extension_budgets.insert(identifier, budget);
+        self
+    }
+
+    /// Sets the memory limits of the extensions without limits in their
+    /// manifest, the [MemoryLimits::default] by default
+    pub fn with_memory_limits(mut self, limits: MemoryLimits) -> Self {
+        self.memory_limits = limits;
+        self
+    }
+
+    /// Sets the memory limits of the extension with the identifier, replacing
+    /// the limits of its manifest
+    pub fn with_extension_memory_limits(
+        mut self, identifier: NamespacedIdentifier, limits: MemoryLimits,
+    ) -> Self {
+        self.extension_memory_limits.insert(identifier, limits);
+        self
+    }
+
+    /// Sets whether the extensions without a restart policy in their manifest
+    /// are restarted when they trap, [RestartPolicy::Never] by default
+    pub fn with_default_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.default_restart_policy = policy;
+        self
+    }
+
+    /// Sets whether the extension with the identifier is restarted when it
+    /// traps, replacing the policy of its manifest
+    pub fn with_extension_restart_policy(
+        mut self, identifier: NamespacedIdentifier, policy: RestartPolicy,
+    ) -> Self {
+        self.extension_restart_policies.insert(identifier, policy);
+        self
+    }
+
+    /// Sets the number of updates an extension has to run without trapping
+    /// before the count of its restarts is reset,
+    /// [DEFAULT_RESTART_RESET_TICKS] by default
+    pub fn with_restart_reset_ticks(mut self, ticks: u32) -> Self {
+        self.restart_reset_ticks = ticks;
+        self
+    }
+
+    /// Adds a dependency provided by the extension host itself, such as
+    /// `etheryal:etheryal`, which the loaded extensions can depend on
+    pub fn with_provided_dependency(
+        mut self, identifier: NamespacedIdentifier, version: Version,
+    ) -> Self {
+        self.provided.push((identifier, version));
+        self
+    }
+
+    /// Sets the extensions allowed to shut down the extension host with a
+    /// `ShutdownHost` message, none by default
+    pub fn with_shutdown_permission(mut self, permission: ShutdownPermission) -> Self {
+        self.shutdown_permission = permission;
+        self
+    }
+
+    /// Doesn't add the default handler of the core host message `T`, so the
+    /// app can handle its [HostMessageEvent]s itself
+    ///
+    /// By default, the extension host replies to `Ping` with `Pong`, exits on
+    /// a permitted `ShutdownHost`, despawns the extensions on
+    /// `ShutdownAcknowledged`, logs `LogRecord`, `GuestPanicked` and
+    /// `MessagesDropped`, keeps the latest `GuestStats` in
+    /// [LatestGuestStats], and routes the permitted `DirectMessage`s to their
+    /// target.
+    pub fn without_default_handler<T>(mut self) -> Self
+    where
+        T: HostMessage, {
+        self.disabled_handlers.insert(TypeId::of::<T>());
+        self
+    }
+
+    /// Sets the schedule the extensions are updated in, and their messages
+    /// sent as [HostMessageEvent]s, `PreUpdate` by default
+    pub fn with_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.schedule = Box::new(schedule);
+        self
+    }
+
+    fn add_default_handler<T, M>(&self, app: &mut App, handler: impl IntoSystemConfigs<M>)
+    where
+        T: HostMessage, {
+        if self.disabled_handlers.contains(&TypeId::of::<T>()) {
+            return;
+        }
+        app.add_systems(
+            self.schedule.clone(),
+            handler.after(systems::run_extensions),
+        );
+    }
+}
+
+impl Default for ExtensionHostPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for ExtensionHostPlugin {
+    fn build(&self, app: &mut App) {
+        let mut runtime = ExtensionRuntime::new()
+            .expect("host functions should be defined once")
+            .with_memory_limits(self.memory_limits);
+        runtime.extension_memory_limits = self.extension_memory_limits.clone();
+
+        // Receive every message the extension guests can send, including the
+        // messages derived outside of `etheryal-extension-common`
+        let mut tags = HashMap::new();
+        for registration in registry::host_messages() {
+            if let Some(other) = tags.insert(registration.tag(), registration.type_name()) {
+                panic!(
+                    "The host messages '{other}' and '{}' have the same tag '{}'",
+                    registration.type_name(),
+                    registration.tag()
+                );
+            }
+            registration.add_host_event(app);
+        }
+
+        app.insert_resource(runtime)
+            .insert_resource(ExtensionLifecycle::new(
+                self.provided.clone(),
+                self.unload_grace_ticks,
+            ))
+            .insert_resource(BudgetConfig {
+                default: self.default_budget,
+                extensions: self.extension_budgets.clone(),
+            })
+            .insert_resource(RestartConfig::new(
+                self.default_restart_policy,
+                self.extension_restart_policies.clone(),
+                self.restart_reset_ticks,
+            ))
+            .add_event::<ExtensionTrapped>()
+            .add_event::<BudgetExceeded>()
+            .add_event::<LoadReport>()
+            .add_systems(
+                self.schedule.clone(),
+                (
+                    systems::run_extensions,
+                    restart::reset_stable_restarts.after(systems::run_extensions),
+                    lifecycle::store_snapshots.after(systems::run_extensions),
+                    lifecycle::finish_unloading
+                        .after(lifecycle::store_snapshots)
+                        .after(handlers::despawn_acknowledged),
+                ),
+            );
+
+        // Fan out the messages published on topics to their subscribers
+        app.init_resource::<TopicSubscriptions>().add_systems(
+            self.schedule.clone(),
+            (topics::update_subscriptions, topics::publish_guest_messages)
+                .chain()
+                .after(systems::run_extensions),
+        );
+
+        if let Some(directory) = &self.extensions_dir {
+            app.insert_resource(ExtensionsDirectory {
+                path: directory.clone(),
+            })
+            .add_systems(Startup, catalog::load_extensions_dir);
+
+            if self.hot_reload {
+                match ModuleWatcher::new(directory) {
+                    Ok(watcher) => {
+                        app.insert_resource(watcher).add_systems(
+                            self.schedule.clone(),
+                            watcher::reload_changed_modules.before(systems::run_extensions),
+                        );
+                    },
+                    Err(err) => error!(
+                        "Failed to watch the extensions directory {}: {err}",
+                        directory.display()
+                    ),
+                }
+            }
+        }
+
+        // Handle the core messages, unless the app handles them itself
+        app.insert_resource(self.shutdown_permission.clone());
+        self.add_default_handler::<Ping, _>(app, handlers::reply_to_ping);
+        self.add_default_handler::<ShutdownHost, _>(app, handlers::shutdown_host);
+        self.add_default_handler::<ShutdownAcknowledged, _>(app, handlers::despawn_acknowledged);
+        self.add_default_handler::<LogRecord, _>(app, handlers::emit_log_records);
+        self.add_default_handler::<GuestPanicked, _>(app, handlers::log_guest_panics);
+        self.add_default_handler::<GuestStats, _>(app, handlers::store_guest_stats);
+        self.add_default_handler::<MessagesDropped, _>(app, handlers::log_dropped_messages);
+        self.add_default_handler::<DirectMessage, _>(app, handlers::route_direct_messages);
+    }
+}
@@ -0,0 +1,383 @@
+//! Loads, unloads and reloads extensions while the extension host is running
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::EventReader;
+use bevy_ecs::prelude::Component;
+use bevy_ecs::system::{Command, ResMut, Resource};
+use bevy_ecs::world::World;
+use etheryal_extension_common::message::events::ShutdownGuest;
+use etheryal_extension_common::message::snapshot::{
+    GuestSnapshot, RestoreSnapshot, Snapshot, SnapshotRequest,
+};
+use etheryal_identifier::NamespacedIdentifier;
+use semver::Version;
+use tracing::{debug, error, info, warn};
+
+use crate::catalog::{self, CatalogEntry, ExtensionCatalog, LoadReport};
+use crate::instance::ExtensionInstance;
+use crate::HostMessageEvent;
+
+/// The number of updates of the extension host an unloading extension has to
+/// acknowledge its shutdown, before it is despawned anyway
+pub const DEFAULT_UNLOAD_GRACE_TICKS: u32 = 60;
+
+/// Marks an extension instance which was sent `ShutdownGuest`, until it
+/// acknowledges the shutdown or its grace period is over
+#[derive(Component, Debug)]
+pub struct Unloading {
+    remaining_ticks: u32,
+}
+
+/// The settings of the extensions loaded while the extension host is running,
+/// and the reloads waiting for extensions to unload
+#[derive(Resource)]
+pub(crate) struct ExtensionLifecycle {
+    pub(crate) provided: Vec<(NamespacedIdentifier, Version)>,
+    pub(crate) unload_grace_ticks: u32,
+    reloads: Vec<PendingReload>,
+}
+
+impl ExtensionLifecycle {
+    pub(crate) fn new(
+        provided: Vec<(NamespacedIdentifier, Version)>, unload_grace_ticks: u32,
+    ) -> Self {
+        Self {
+            provided,
+            unload_grace_ticks,
+            reloads: Vec::new(),
+        }
+    }
+}
+
+/// Extensions loaded again from their directories once the previous
+/// instances are despawned
+struct PendingReload {
+    unloading: Vec<Entity>,
+    directories: Vec<PathBuf>,
+    /// The identifiers of the reloaded instances
+    identifiers: HashMap<Entity, NamespacedIdentifier>,
+    /// The snapshots sent by the previous instances, restored in the new ones
+    snapshots: HashMap<NamespacedIdentifier, Snapshot>,
+    /// The number of updates left before the extensions are loaded again
+    delay_ticks: u32,
+}
+
+/// A command loading the extension of a directory with an `etheryal.toml`
+/// manifest, which can depend on the extensions already running
+///
+/// The [LoadReport] is sent as an event.
+#[derive(Clone, Debug)]
+pub struct LoadExtension {
+    directory: PathBuf,
+}
+
+impl LoadExtension {
+    /// Creates a command loading the extension of the directory
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl Command for LoadExtension {
+    fn apply(self, world: &mut World) {
+        let catalog = ExtensionCatalog::from_directories([self.directory]);
+        let report = load_catalog(world, catalog);
+        world.send_event(report);
+    }
+}
+
+/// A command unloading an extension and every extension depending on it
+///
+/// The extensions are sent `ShutdownGuest`, and are despawned once they
+/// acknowledge the shutdown, or once their grace period set with
+/// [ExtensionHostPlugin::with_unload_grace_ticks](crate::ExtensionHostPlugin::with_unload_grace_ticks)
+/// is over.
+#[derive(Clone, Copy, Debug)]
+pub struct UnloadExtension {
+    extension: Entity,
+}
+
+impl UnloadExtension {
+    /// Creates a command unloading the extension instance of the entity
+    pub fn new(extension: Entity) -> Self {
+        Self { extension }
+    }
+}
+
+impl Command for UnloadExtension {
+    fn apply(self, world: &mut World) {
+        if world.get::<Unloading>(self.extension).is_some() {
+            return;
+        }
+        if world.get::<ExtensionInstance>(self.extension).is_none() {
+            warn!(
+                "Failed to unload {:?}, which is not an extension",
+                self.extension
+            );
+            return;
+        }
+        let extensions = with_dependents(world, self.extension);
+        unload(world, &extensions);
+    }
+}
+
+/// A command reloading an extension from its directory, with every extension
+/// depending on it, once the running instances are unloaded
+///
+/// The extensions are sent `SnapshotRequest` before `ShutdownGuest`, and the
+/// snapshot of their persisted state is sent back to their new instance in a
+/// `RestoreSnapshot` message. The extension must have been loaded from its
+/// directory, and keeps running if its directory can't be read anymore. The
+/// dependents which weren't loaded from a directory are only unloaded. The
+/// [LoadReport] is sent as an event.
+#[derive(Clone, Copy, Debug)]
+pub struct ReloadExtension {
+    extension: Entity,
+}
+
+impl ReloadExtension {
+    /// Creates a command reloading the extension instance of the entity
+    pub fn new(extension: Entity) -> Self {
+        Self { extension }
+    }
+}
+
+impl Command for ReloadExtension {
+    fn apply(self, world: &mut World) {
+        if world.get::<Unloading>(self.extension).is_some() {
+            debug!("The extension {:?} is already unloading", self.extension);
+            return;
+        }
+        let Some(entry) = world.get::<CatalogEntry>(self.extension) else {
+            warn!(
+                "Failed to reload {:?}, which was not loaded from a directory",
+                self.extension
+            );
+            return;
+        };
+        if let Err(err) = catalog::read_entry(entry.directory()) {
+            error!(
+                "Failed to reload the extension '{}': {err}",
+                entry.info().identifier()
+            );
+            return;
+        }
+
+        let extensions = with_dependents(world, self.extension);
+        reload(world, extensions, 0);
+    }
+}
+
+/// Restarts an extension which trapped, with every extension depending on it,
+/// after `delay_ticks` updates of the extension host
+///
+/// The extension is despawned right away, its dependents are unloaded like
+/// with [ReloadExtension].
+pub(crate) fn restart(world: &mut World, extension: Entity, delay_ticks: u32) {
+    let extensions = with_dependents(world, extension);
+    reload(world, extensions, delay_ticks);
+    world.despawn(extension);
+}
+
+/// Loads the extensions of the catalog with the dependencies provided by the
+/// extension host, and logs the [LoadReport]
+pub(crate) fn load_catalog(world: &mut World, catalog: ExtensionCatalog) -> LoadReport {
+    let provided = world.resource::<ExtensionLifecycle>().provided.clone();
+    let report = provided
+        .into_iter()
+        .fold(catalog, |catalog, (identifier, version)| {
+            catalog.with_provided(identifier, version)
+        })
+        .load(world);
+    report.log();
+    report
+}
+
+/// Returns the extension followed by the extensions depending on it, directly
+/// or through other dependents
+pub(crate) fn with_dependents(world: &mut World, extension: Entity) -> Vec<Entity> {
+    let infos = catalog::running_extension_infos(world);
+    let mut dependents = vec![extension];
+    let mut identifiers: BTreeSet<_> = infos
+        .iter()
+        .filter(|(entity, _)| *entity == extension)
+        .map(|(_, info)| info.identifier())
+        .collect();
+    loop {
+        let found: Vec<_> = infos
+            .iter()
+            .filter(|(entity, info)| {
+                !dependents.contains(entity)
+                    && info
+                        .dependencies()
+                        .iter()
+                        .any(|dependency| identifiers.contains(dependency.identifier()))
+            })
+            .collect();
+        if found.is_empty() {
+            return dependents;
+        }
+        for (entity, info) in found {
+            dependents.push(*entity);
+            identifiers.insert(info.identifier());
+        }
+    }
+}
+
+/// Unloads the extensions, and loads the extensions loaded from a directory
+/// again once they are despawned and `delay_ticks` updates passed, restoring
+/// their snapshots
+///
+/// The extensions already reloading, such as the dependents of another
+/// reloaded extension, are reloaded once along with the other extensions.
+fn reload(world: &mut World, extensions: Vec<Entity>, delay_ticks: u32) {
+    let mut lifecycle = world.resource_mut::<ExtensionLifecycle>();
+    let (merged, others): (Vec<_>, Vec<_>) = std::mem::take(&mut lifecycle.reloads)
+        .into_iter()
+        .partition(|reload| {
+            reload
+                .unloading
+                .iter()
+                .any(|extension| extensions.contains(extension))
+        });
+    lifecycle.reloads = others;
+    let mut reload = merged.into_iter().fold(
+        PendingReload {
+            unloading: Vec::new(),
+            directories: Vec::new(),
+            identifiers: HashMap::new(),
+            snapshots: HashMap::new(),
+            delay_ticks,
+        },
+        |mut reload, merged| {
+            reload.unloading.extend(merged.unloading);
+            reload.directories.extend(merged.directories);
+            reload.identifiers.extend(merged.identifiers);
+            reload.snapshots.extend(merged.snapshots);
+            reload.delay_ticks = reload.delay_ticks.max(merged.delay_ticks);
+            reload
+        },
+    );
+
+    let extensions: Vec<_> = extensions
+        .into_iter()
+        .filter(|extension| !reload.unloading.contains(extension))
+        .collect();
+    for &extension in &extensions {
+        let Some(entry) = world.get::<CatalogEntry>(extension) else {
+            continue;
+        };
+        reload.directories.push(entry.directory().clone());
+        reload
+            .identifiers
+            .insert(extension, entry.info().identifier().clone());
+        if let Some(mut instance) = world.get_mut::<ExtensionInstance>(extension) {
+            if let Err(err) = instance.send_message(SnapshotRequest) {
+                error!("Failed to send 'SnapshotRequest' to {extension:?}: {err}");
+            }
+        }
+    }
+    unload(world, &extensions);
+    reload.unloading.extend(extensions);
+    world
+        .resource_mut::<ExtensionLifecycle>()
+        .reloads
+        .push(reload);
+}
+
+/// Sends `ShutdownGuest` to the extensions, and marks them as unloading
+fn unload(world: &mut World, extensions: &[Entity]) {
+    let grace_ticks = world.resource::<ExtensionLifecycle>().unload_grace_ticks;
+    for &extension in extensions {
+        let Some(mut entity) = world.get_entity_mut(extension) else {
+            continue;
+        };
+        if let Some(mut instance) = entity.get_mut::<ExtensionInstance>() {
+            if let Err(err) = instance.send_message(ShutdownGuest) {
+                error!("Failed to send 'ShutdownGuest' to {extension:?}: {err}");
+            }
+        }
+        entity.insert(Unloading {
+            remaining_ticks: grace_ticks,
+        });
+        info!("Unloading the extension {extension:?}");
+    }
+}
+
+/// Despawns the unloading extensions whose grace period is over, and loads
+/// the reloaded extensions once their previous instances are despawned
+pub(crate) fn finish_unloading(world: &mut World) {
+    let mut expired = Vec::new();
+    let mut unloading = world.query::<(Entity, &mut Unloading)>();
+    for (extension, mut unloading) in unloading.iter_mut(world) {
+        match unloading.remaining_ticks.checked_sub(1) {
+            Some(remaining_ticks) => unloading.remaining_ticks = remaining_ticks,
+            None => expired.push(extension),
+        }
+    }
+    for extension in expired {
+        warn!("The extension {extension:?} didn't acknowledge its shutdown in time");
+        world.despawn(extension);
+    }
+
+    let mut reloads = std::mem::take(&mut world.resource_mut::<ExtensionLifecycle>().reloads);
+    for reload in &mut reloads {
+        reload.delay_ticks = reload.delay_ticks.saturating_sub(1);
+    }
+    let (ready, pending): (Vec<_>, Vec<_>) = reloads.into_iter().partition(|reload| {
+        reload.delay_ticks == 0
+            && reload
+                .unloading
+                .iter()
+                .all(|&extension| world.get_entity(extension).is_none())
+    });
+    world.resource_mut::<ExtensionLifecycle>().reloads = pending;
+    for mut reload in ready {
+        let catalog = ExtensionCatalog::from_directories(reload.directories);
+        let report = load_catalog(world, catalog);
+        for loaded in report.loaded() {
+            let Some(snapshot) = reload.snapshots.remove(&loaded.identifier) else {
+                continue;
+            };
+            if let Some(mut instance) = world.get_mut::<ExtensionInstance>(loaded.entity) {
+                if let Err(err) = instance.send_message(RestoreSnapshot::new(snapshot)) {
+                    error!(
+                        "Failed to restore the snapshot of the extension '{}': {err}",
+                        loaded.identifier
+                    );
+                }
+            }
+        }
+        world.send_event(report);
+    }
+}
+
+/// Keeps the snapshots sent by the reloaded extensions until their new
+/// instances are loaded
+pub(crate) fn store_snapshots(
+    mut snapshots: EventReader<HostMessageEvent<GuestSnapshot>>,
+    mut lifecycle: ResMut<ExtensionLifecycle>,
+) {
+    for snapshot in snapshots.iter() {
+        let reload = lifecycle.reloads.iter_mut().find_map(|reload| {
+            let identifier = reload.identifiers.get(&snapshot.extension)?.clone();
+            Some((reload, identifier))
+        });
+        match reload {
+            Some((reload, identifier)) => {
+                debug!("Received the snapshot of the extension '{identifier}'");
+                reload
+                    .snapshots
+                    .insert(identifier, snapshot.snapshot().clone());
+            },
+            None => warn!(
+                "Ignored a snapshot from {:?}, which is not reloading",
+                snapshot.extension
+            ),
+        }
+    }
+}
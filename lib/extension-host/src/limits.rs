@@ -0,0 +1,130 @@
+//! Limits the memory of each extension guest, and the size of the messages it
+//! exchanges with the extension host
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use etheryal_extension_common::message::limits::{ExhaustedResource, ResourceExhausted};
+use getset::CopyGetters;
+use serde::Deserialize;
+use wasmtime::ResourceLimiter;
+
+use crate::error::ExtensionHostError;
+
+/// The default maximum size of the linear memory of an extension guest
+pub const DEFAULT_MAX_MEMORY: usize = 256 * 1024 * 1024;
+
+/// The default maximum size of an encoded message exchanged with an extension
+/// guest
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// The memory an extension guest can use, and the size of the messages it can
+/// exchange with the extension host
+///
+/// Set in the `[memory]` table of the manifest of an extension, or by the
+/// extension host with
+/// [ExtensionHostPlugin::with_memory_limits](crate::ExtensionHostPlugin::with_memory_limits)
+/// and
+/// [ExtensionHostPlugin::with_extension_memory_limits](crate::ExtensionHostPlugin::with_extension_memory_limits).
+/// The sizes are in bytes.
+///
+/// ```toml
+/// [memory]
+/// max_memory = 67_108_864
+/// max_message_size = 1_048_576
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, CopyGetters)]
+#[getset(get_copy = "pub")]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryLimits {
+    /// The maximum size of the linear memory of the extension guest,
+    /// [DEFAULT_MAX_MEMORY] by default
+    max_memory: usize,
+    /// The maximum size of an encoded message exchanged with the extension
+    /// guest, [DEFAULT_MAX_MESSAGE_SIZE] by default
+    max_message_size: usize,
+}
+
+impl MemoryLimits {
+    /// Sets the maximum size of the linear memory of the extension guest
+    pub fn with_max_memory(mut self, bytes: usize) -> Self {
+        self.max_memory = bytes;
+        self
+    }
+
+    /// Sets the maximum size of an encoded message exchanged with the extension
+    /// guest
+    pub fn with_max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Returns an error if an encoded message of `len` bytes is too large
+    pub(crate) fn check_message_size(&self, len: usize) -> Result<(), ResourceExhausted> {
+        if len > self.max_message_size {
+            return Err(ResourceExhausted::new(
+                ExhaustedResource::MessageSize,
+                len,
+                self.max_message_size,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            max_memory: DEFAULT_MAX_MEMORY,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+}
+
+/// The size of the linear memory of an extension instance, shared with the
+/// extension host while an update of the instance is suspended
+#[derive(Default)]
+pub(crate) struct MemoryUsage {
+    pub(crate) memory: AtomicUsize,
+    pub(crate) peak_memory: AtomicUsize,
+}
+
+/// Enforces the [MemoryLimits] of an extension instance, and records the peak
+/// size of its linear memory
+pub(crate) struct MemoryLimiter {
+    pub(crate) limits: MemoryLimits,
+    pub(crate) usage: Arc<MemoryUsage>,
+}
+
+impl MemoryLimiter {
+    pub(crate) fn new(limits: MemoryLimits, usage: Arc<MemoryUsage>) -> Self {
+        Self { limits, usage }
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self, _current: usize, desired: usize, _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        // Trap with the exhausted limit, rather than letting the allocator of the
+        // extension guest abort
+        if desired > self.limits.max_memory {
+            return Err(
+                ExtensionHostError::ResourceExhausted(ResourceExhausted::new(
+                    ExhaustedResource::Memory,
+                    desired,
+                    self.limits.max_memory,
+                ))
+                .into(),
+            );
+        }
+        self.usage.memory.store(desired, Ordering::Relaxed);
+        self.usage.peak_memory.fetch_max(desired, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self, _current: usize, desired: usize, maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        Ok(maximum.is_none_or(|maximum| desired <= maximum))
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use etheryal_identifier::NamespacedIdentifier;
+use getset::Getters;
+use semver::Version;
+use serde::Deserialize;
+
+use crate::budget::ExtensionBudget;
+use crate::limits::MemoryLimits;
+use crate::restart::RestartPolicy;
+
+/// The name of the manifest in the directory of each extension
+pub const MANIFEST_FILE: &str = "etheryal.toml";
+
+/// The `etheryal.toml` manifest describing an extension in the extensions
+/// directory
+#[derive(Clone, Debug, Deserialize, Getters)]
+#[getset(get = "pub")]
+#[serde(deny_unknown_fields)]
+pub struct ExtensionManifest {
+    /// The human readable name of the extension
+    name: String,
+    /// The identifier of the extension in the extensions directory, which must
+    /// match the extension info embedded in the module
+    id: String,
+    /// The description of the extension
+    #[serde(default)]
+    description: Option<String>,
+    /// The author of the extension
+    #[serde(default)]
+    author: Option<String>,
+    /// The version of the extension, which must match the extension info
+    /// embedded in the module
+    version: Version,
+    /// The license of the extension
+    #[serde(default)]
+    license: Option<String>,
+    /// The path of the WebAssembly module, relative to the directory of the
+    /// extension and inside it, defaults to the only `.wasm` file of the
+    /// directory
+    #[serde(default)]
+    module: Option<PathBuf>,
+    /// The fuel the extension can consume during each update, unless the
+    /// extension host sets its own budget for the extension
+    #[serde(default)]
+    budget: Option<ExtensionBudget>,
+    /// The memory limits of the extension, unless the extension host sets its
+    /// own limits for the extension
+    #[serde(default)]
+    memory: Option<MemoryLimits>,
+    /// Whether the extension is restarted when it traps, unless the extension
+    /// host sets its own policy for the extension
+    #[serde(default)]
+    restart: Option<RestartPolicy>,
+    /// The identifiers of the extensions which can send messages to the
+    /// extension without depending on it, or `"*"` for every extension
+    #[serde(default)]
+    accept_messages_from: Vec<String>,
+}
+
+impl ExtensionManifest {
+    /// Returns whether the extension accepts the messages of the extension
+    /// `sender`, which doesn't depend on it
+    pub fn accepts_messages_from(&self, sender: &NamespacedIdentifier) -> bool {
+        self.accept_messages_from
+            .iter()
+            .any(|accepted| accepted == "*" || *accepted == sender.to_string())
+    }
+}
@@ -0,0 +1,213 @@
+//! Restarts the extensions which trapped, with the extensions depending on
+//! them
+use std::collections::HashMap;
+
+use bevy_ecs::prelude::*;
+use etheryal_identifier::NamespacedIdentifier;
+use serde::Deserialize;
+use tracing::{error, info, warn};
+
+use crate::catalog::CatalogEntry;
+use crate::error::ExtensionTrapped;
+use crate::instance::ExtensionInstance;
+use crate::lifecycle::{self, Unloading};
+
+/// The number of updates an extension has to run without trapping before the
+/// count of its restarts is reset
+pub const DEFAULT_RESTART_RESET_TICKS: u32 = 600;
+
+/// Whether an extension which trapped is restarted, with every extension
+/// depending on it
+///
+/// Set with `restart` in the manifest of an extension, or by the extension
+/// host with
+/// [ExtensionHostPlugin::with_default_restart_policy](crate::ExtensionHostPlugin::with_default_restart_policy)
+/// and
+/// [ExtensionHostPlugin::with_extension_restart_policy](crate::ExtensionHostPlugin::with_extension_restart_policy).
+/// Only the extensions loaded from a directory can be restarted.
+///
+/// ```toml
+/// restart = { backoff = { initial_ticks = 10, max_restarts = 5 } }
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum RestartPolicy {
+    /// The extension stays unloaded
+    #[default]
+    Never,
+    /// The extension is restarted during the next update
+    Always,
+    /// The extension is restarted after a delay doubling with each restart,
+    /// until it was restarted `max_restarts` times
+    Backoff {
+        /// The number of updates of the extension host before the first
+        /// restart
+        initial_ticks: u32,
+        /// The number of restarts after which the extension stays unloaded
+        max_restarts: u32,
+    },
+}
+
+impl RestartPolicy {
+    /// Returns the number of updates of the extension host before an extension
+    /// which was already restarted `restarts` times is restarted again, if it
+    /// is
+    pub fn restart_delay(&self, restarts: u32) -> Option<u32> {
+        match *self {
+            Self::Never => None,
+            Self::Always => Some(0),
+            Self::Backoff {
+                initial_ticks,
+                max_restarts,
+            } => (restarts < max_restarts)
+                .then(|| initial_ticks.saturating_mul(2u32.saturating_pow(restarts))),
+        }
+    }
+}
+
+/// The restart policies set by the extension host, and the number of times
+/// each extension was restarted since it last ran `reset_ticks` updates without
+/// trapping
+#[derive(Resource)]
+pub(crate) struct RestartConfig {
+    default: RestartPolicy,
+    extensions: HashMap<NamespacedIdentifier, RestartPolicy>,
+    reset_ticks: u32,
+    restarts: HashMap<NamespacedIdentifier, Restarts>,
+}
+
+/// The restarts of an extension, and the updates it ran since its last restart
+#[derive(Default)]
+struct Restarts {
+    count: u32,
+    stable_ticks: u32,
+}
+
+impl RestartConfig {
+    pub(crate) fn new(
+        default: RestartPolicy, extensions: HashMap<NamespacedIdentifier, RestartPolicy>,
+        reset_ticks: u32,
+    ) -> Self {
+        Self {
+            default,
+            extensions,
+            reset_ticks,
+            restarts: HashMap::new(),
+        }
+    }
+
+    /// Returns the restart policy of an extension, from the extension host,
+    /// then its manifest, then the default policy of the extension host
+    fn policy_of(
+        &self, identifier: &NamespacedIdentifier, manifest: Option<RestartPolicy>,
+    ) -> RestartPolicy {
+        self.extensions
+            .get(identifier)
+            .copied()
+            .or(manifest)
+            .unwrap_or(self.default)
+    }
+}
+
+/// Handles the extensions which trapped during the same update, the
+/// dependencies first, so the dependents which trapped along with them are
+/// reloaded once with them instead of on their own
+pub(crate) fn handle_traps(world: &mut World, events: Vec<ExtensionTrapped>) {
+    let dependents: Vec<_> = events
+        .iter()
+        .map(|event| lifecycle::with_dependents(world, event.extension))
+        .collect();
+    // An extension follows the trapped extensions it depends on, directly or
+    // through other dependents
+    let mut events: Vec<_> = events
+        .into_iter()
+        .map(|event| {
+            let dependencies = dependents
+                .iter()
+                .filter(|dependents| dependents[1..].contains(&event.extension))
+                .count();
+            (dependencies, event)
+        })
+        .collect();
+    events.sort_by_key(|(dependencies, _)| *dependencies);
+    for (_, event) in events {
+        handle_trap(world, event);
+    }
+}
+
+/// Despawns an extension which trapped, restarts it if its policy allows it,
+/// and sends the [ExtensionTrapped] event
+fn handle_trap(world: &mut World, event: ExtensionTrapped) {
+    let extension = event.extension;
+    match &event.identifier {
+        Some(identifier) => error!(
+            "The extension '{identifier}' ({extension:?}) trapped: {}",
+            event.error
+        ),
+        None => error!("The extension {extension:?} trapped: {}", event.error),
+    }
+
+    // An unloading extension is not restarted
+    let entry = world
+        .get::<CatalogEntry>(extension)
+        .filter(|_| world.get::<Unloading>(extension).is_none())
+        .map(|entry| {
+            (
+                entry.info().identifier().clone(),
+                *entry.manifest().restart(),
+            )
+        });
+    let restart = entry.and_then(|(identifier, manifest)| {
+        let mut config = world.resource_mut::<RestartConfig>();
+        let policy = config.policy_of(&identifier, manifest);
+        let restarts = config.restarts.entry(identifier.clone()).or_default();
+        restarts.stable_ticks = 0;
+        let delay = policy.restart_delay(restarts.count);
+        match delay {
+            Some(_) => restarts.count += 1,
+            None if policy != RestartPolicy::Never => {
+                warn!("The extension '{identifier}' was restarted too many times");
+            },
+            None => {},
+        }
+        delay.map(|delay| (identifier, restarts.count, delay))
+    });
+
+    match restart {
+        Some((identifier, restarts, delay)) => {
+            info!("Restarting the extension '{identifier}' in {delay} updates, restart {restarts}");
+            lifecycle::restart(world, extension, delay);
+        },
+        None => {
+            world.despawn(extension);
+        },
+    }
+    world.send_event(event);
+}
+
+/// Resets the count of restarts of the extensions which ran long enough
+/// without trapping since their last restart
+pub(crate) fn reset_stable_restarts(
+    mut config: ResMut<RestartConfig>,
+    extensions: Query<&CatalogEntry, (With<ExtensionInstance>, Without<Unloading>)>,
+) {
+    if config.restarts.is_empty() {
+        return;
+    }
+    let reset_ticks = config.reset_ticks;
+    for entry in &extensions {
+        let identifier = entry.info().identifier();
+        let Some(restarts) = config.restarts.get_mut(identifier) else {
+            continue;
+        };
+        restarts.stable_ticks += 1;
+        if restarts.stable_ticks >= reset_ticks {
+            info!(
+                "The extension '{identifier}' ran {reset_ticks} updates without trapping, \
+                 resetting its {} restarts",
+                restarts.count
+            );
+            config.restarts.remove(identifier);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! The WebAssembly runtime the extension guests run in, and the host functions
+//! imported by `etheryal-extension-sys`
+use std::collections::{HashMap, VecDeque};
+use std::pin::pin;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::task::Poll;
+
+use bevy_ecs::system::Resource;
+use etheryal_extension_common::message::events::ExtensionRegistered;
+use etheryal_extension_common::message::{encode_message, MessageTag};
+use etheryal_extension_common::ExtensionModuleInfo;
+use etheryal_identifier::NamespacedIdentifier;
+use serde::Serialize;
+use tracing::{info, trace, warn};
+use wasi_common::sync::WasiCtxBuilder;
+use wasi_common::WasiCtx;
+use wasmtime::{Caller, Config, Engine, Extern, Linker, Module, Store};
+
+use crate::budget::ExtensionBudget;
+use crate::catalog;
+use crate::error::ExtensionHostError;
+use crate::instance::{self, ExtensionInstance};
+use crate::limits::{MemoryLimiter, MemoryLimits, MemoryUsage};
+
+/// The name of the module the host functions are imported from
+const HOST_MODULE: &str = "host";
+
+/// A Bevy resource that compiles and instantiates extension modules
+#[derive(Resource)]
+pub struct ExtensionRuntime {
+    engine: Engine,
+    linker: Linker<GuestContext>,
+    memory_limits: MemoryLimits,
+    /// The limits set by the extension host for each extension, which take
+    /// precedence over the limits of their manifests
+    pub(crate) extension_memory_limits: HashMap<NamespacedIdentifier, MemoryLimits>,
+}
+
+impl ExtensionRuntime {
+    /// Creates a runtime providing the host functions and WASI to the
+    /// extension modules, which consume fuel to limit how long their updates
+    /// run, and run asynchronously so an update which runs out of fuel can be
+    /// suspended
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the host functions could not be defined
+    pub fn new() -> Result<Self, ExtensionHostError> {
+        let mut config = Config::new();
+        config.consume_fuel(true).async_support(true);
+        let engine = Engine::new(&config)?;
+        let mut linker = Linker::new(&engine);
+        wasi_common::sync::add_to_linker(&mut linker, |context: &mut GuestContext| {
+            &mut context.wasi
+        })?;
+        linker
+            .func_wrap(HOST_MODULE, "extension_info", extension_info)?
+            .func_wrap(HOST_MODULE, "send_message", send_message)?
+            .func_wrap(HOST_MODULE, "recv_message", recv_message)?
+            .func_wrap(HOST_MODULE, "read_message_buf", read_message_buf)?;
+
+        Ok(Self {
+            engine,
+            linker,
+            memory_limits: MemoryLimits::default(),
+            extension_memory_limits: HashMap::new(),
+        })
+    }
+
+    /// Sets the memory limits of the extension modules loaded with
+    /// [load](ExtensionRuntime::load), and of the extensions without limits in
+    /// their manifest
+    pub fn with_memory_limits(mut self, limits: MemoryLimits) -> Self {
+        self.memory_limits = limits;
+        self
+    }
+
+    /// Returns the memory limits of the extension modules loaded with
+    /// [load](ExtensionRuntime::load)
+    pub fn memory_limits(&self) -> MemoryLimits {
+        self.memory_limits
+    }
+
+    /// Returns the memory limits of an extension, from the extension host,
+    /// then its manifest, then the default limits of the runtime
+    pub(crate) fn memory_limits_of(
+        &self, identifier: &NamespacedIdentifier, manifest: Option<MemoryLimits>,
+    ) -> MemoryLimits {
+        self.extension_memory_limits
+            .get(identifier)
+            .copied()
+            .or(manifest)
+            .unwrap_or(self.memory_limits)
+    }
+
+    /// Returns the engine the extension modules are compiled with
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Compiles and instantiates an extension module with the default memory
+    /// limits of the runtime, then runs its entry point
+    ///
+    /// The extension module must be built with
+    /// `#[etheryal_extension::main(host_tick)]`, so the extension host can run
+    /// its updates. The returned instance should be spawned as an entity.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module is invalid, if it has no embedded
+    /// extension info, if it doesn't export `etheryal_tick` or if its entry
+    /// point traps
+    pub fn load(&self, wasm: &[u8]) -> Result<ExtensionInstance, ExtensionHostError> {
+        self.load_with_limits(wasm, self.memory_limits)
+    }
+
+    /// Compiles and instantiates an extension module with the memory limits,
+    /// then runs its entry point
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module is invalid, if it has no embedded
+    /// extension info, if it doesn't export `etheryal_tick`, if its entry point
+    /// traps, or if it exceeds the memory limits
+    pub fn load_with_limits(
+        &self, wasm: &[u8], limits: MemoryLimits,
+    ) -> Result<ExtensionInstance, ExtensionHostError> {
+        self.load_with_budget(wasm, limits, None)
+    }
+
+    /// Compiles and instantiates an extension module with the memory limits,
+    /// then runs its entry point, which can consume the fuel of one update of
+    /// the budget
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module is invalid, if it has no embedded
+    /// extension info, if it doesn't export `etheryal_tick`, if its entry point
+    /// traps or runs out of fuel, or if it exceeds the memory limits
+    pub fn load_with_budget(
+        &self, wasm: &[u8], limits: MemoryLimits, budget: Option<ExtensionBudget>,
+    ) -> Result<ExtensionInstance, ExtensionHostError> {
+        let embedded_info = catalog::read_embedded_info("the extension module", wasm)
+            .map_err(|err| ExtensionHostError::InvalidModule(Box::new(err)))?;
+        let module = Module::new(&self.engine, wasm)?;
+        let state = Arc::new(GuestState::new(limits));
+        let context = GuestContext::new(
+            WasiCtxBuilder::new().inherit_stdio().build(),
+            embedded_info,
+            Arc::clone(&state),
+        );
+        let mut store = Store::new(&self.engine, context);
+        store.limiter(|context| &mut context.limiter);
+        // The entry point is suspended if it runs out of fuel, which can't be
+        // resumed as the extension isn't running yet
+        let fuel = budget.map_or(u64::MAX, |budget| budget.fuel());
+        store.fuel_async_yield_interval(instance::yield_interval(fuel))?;
+        store.set_fuel(u64::MAX)?;
+
+        let tick = {
+            let start = pin!(async {
+                let instance = self
+                    .linker
+                    .instantiate_async(&mut store, &module)
+                    .await
+                    .map_err(ExtensionHostError::from_guest)?;
+
+                // Check the export before running the entry point, which never returns if
+                // the extension runs on its own clock
+                let tick = instance
+                    .get_typed_func::<u64, ()>(&mut store, "etheryal_tick")
+                    .map_err(|_| ExtensionHostError::MissingExport("etheryal_tick"))?;
+                let start = instance
+                    .get_typed_func::<(), ()>(&mut store, "_start")
+                    .map_err(|_| ExtensionHostError::MissingExport("_start"))?;
+                start
+                    .call_async(&mut store, ())
+                    .await
+                    .map_err(ExtensionHostError::from_guest)?;
+                Ok::<_, ExtensionHostError>(tick)
+            });
+            match instance::poll_once(start) {
+                Poll::Ready(result) => result?,
+                Poll::Pending => return Err(ExtensionHostError::OutOfFuel(fuel)),
+            }
+        };
+
+        Ok(ExtensionInstance::new(store, tick, state))
+    }
+}
+
+/// The store data of an extension instance, accessed by the host functions
+pub(crate) struct GuestContext {
+    wasi: WasiCtx,
+    /// The extension info embedded in the module, which the extension info
+    /// sent by the extension guest must match
+    embedded_info: ExtensionModuleInfo,
+    state: Arc<GuestState>,
+    /// The message being read by the extension guest
+    message_buf: Vec<u8>,
+    read_pos: usize,
+    limiter: MemoryLimiter,
+}
+
+impl GuestContext {
+    fn new(wasi: WasiCtx, embedded_info: ExtensionModuleInfo, state: Arc<GuestState>) -> Self {
+        Self {
+            wasi,
+            embedded_info,
+            limiter: MemoryLimiter::new(state.limits, Arc::clone(&state.usage)),
+            state,
+            message_buf: Vec::new(),
+            read_pos: 0,
+        }
+    }
+}
+
+/// The state of an extension instance shared by its host functions and the
+/// extension host, which reaches it while an update owns the store
+pub(crate) struct GuestState {
+    pub(crate) info: OnceLock<ExtensionModuleInfo>,
+    /// The encoded messages waiting to be received by the extension guest
+    inbox: Mutex<VecDeque<Vec<u8>>>,
+    /// The encoded messages sent by the extension guest since the last tick
+    outbox: Mutex<Vec<Vec<u8>>>,
+    pub(crate) limits: MemoryLimits,
+    pub(crate) usage: Arc<MemoryUsage>,
+}
+
+impl GuestState {
+    fn new(limits: MemoryLimits) -> Self {
+        Self {
+            info: OnceLock::new(),
+            inbox: Mutex::new(VecDeque::new()),
+            outbox: Mutex::new(Vec::new()),
+            limits,
+            usage: Arc::default(),
+        }
+    }
+
+    /// Encodes a message and queues it for the extension guest
+    pub(crate) fn queue_message<G>(&self, message: &G) -> Result<(), ExtensionHostError>
+    where
+        G: MessageTag + Serialize, {
+        self.queue_encoded(encode_message(message)?)
+    }
+
+    /// Queues an encoded message for the extension guest, or a
+    /// `ResourceExhausted` message instead if it exceeds the maximum message
+    /// size of the extension
+    pub(crate) fn queue_encoded(&self, encoded: Vec<u8>) -> Result<(), ExtensionHostError> {
+        let mut inbox = self.inbox.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(exhausted) = self.limits.check_message_size(encoded.len()) {
+            warn!("Dropped a message for the extension guest: {exhausted}");
+            let notification = encode_message(&exhausted)?;
+            inbox.push_back(notification);
+            return Err(ExtensionHostError::ResourceExhausted(exhausted));
+        }
+        inbox.push_back(encoded);
+        Ok(())
+    }
+
+    /// Takes the encoded messages sent by the extension guest since the last
+    /// call
+    pub(crate) fn take_sent(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.outbox.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+fn extension_info(
+    mut caller: Caller<'_, GuestContext>, len: u32, ptr: u32,
+) -> wasmtime::Result<()> {
+    if caller.data().state.info.get().is_some() {
+        return Err(wasmtime::Error::msg(
+            "the extension guest sent its extension info more than once",
+        ));
+    }
+
+    let encoded = read_guest_buffer(&mut caller, len, ptr)?;
+    let info: ExtensionModuleInfo = rmp_serde::from_slice(&encoded)?;
+    // The extension host trusts the identity of the extension embedded in its
+    // module, the extension guest can't claim another one
+    let embedded_info = &caller.data().embedded_info;
+    if info.identifier() != embedded_info.identifier()
+        || info.version() != embedded_info.version()
+        || info.dependencies() != embedded_info.dependencies()
+    {
+        return Err(ExtensionHostError::InfoMismatch(info.identifier().clone()).into());
+    }
+    info!(
+        "Registered extension '{}' version {}",
+        info.identifier(),
+        info.version()
+    );
+
+    // Confirm the registration, the extension guest waits for it to run
+    let state = &caller.data().state;
+    let _ = state.info.set(info);
+    state.queue_message(&ExtensionRegistered)?;
+    Ok(())
+}
+
+fn send_message(mut caller: Caller<'_, GuestContext>, len: u32, ptr: u32) -> wasmtime::Result<()> {
+    ensure_registered(&caller)?;
+
+    let encoded = read_guest_buffer(&mut caller, len, ptr)?;
+    trace!("Received message of {len} bytes");
+    caller
+        .data()
+        .state
+        .outbox
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(encoded);
+    Ok(())
+}
+
+fn recv_message(mut caller: Caller<'_, GuestContext>) -> wasmtime::Result<u32> {
+    ensure_registered(&caller)?;
+
+    let context = caller.data_mut();
+    context.message_buf = context
+        .state
+        .inbox
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .pop_front()
+        .unwrap_or_default();
+    context.read_pos = 0;
+    Ok(u32::try_from(context.message_buf.len())?)
+}
+
+fn read_message_buf(
+    mut caller: Caller<'_, GuestContext>, len: u32, ptr: u32,
+) -> wasmtime::Result<u32> {
+    ensure_registered(&caller)?;
+
+    let memory = guest_memory(&mut caller)?;
+    let (data, context) = memory.data_and_store_mut(&mut caller);
+    let remaining = &context.message_buf[context.read_pos..];
+    let read = remaining.len().min(len as usize);
+    let ptr = ptr as usize;
+    data.get_mut(ptr..ptr + read)
+        .ok_or_else(|| wasmtime::Error::msg("the message buffer is out of bounds"))?
+        .copy_from_slice(&remaining[..read]);
+
+    context.read_pos += read;
+    Ok(u32::try_from(read)?)
+}
+
+fn ensure_registered(caller: &Caller<'_, GuestContext>) -> wasmtime::Result<()> {
+    if caller.data().state.info.get().is_none() {
+        return Err(wasmtime::Error::msg(
+            "the extension guest must send its extension info first",
+        ));
+    }
+    Ok(())
+}
+
+/// Copies a buffer out of the memory of the extension guest
+fn read_guest_buffer(
+    caller: &mut Caller<'_, GuestContext>, len: u32, ptr: u32,
+) -> wasmtime::Result<Vec<u8>> {
+    caller
+        .data()
+        .state
+        .limits
+        .check_message_size(len as usize)
+        .map_err(ExtensionHostError::ResourceExhausted)?;
+    let memory = guest_memory(caller)?;
+    let mut buffer = vec![0; len as usize];
+    memory.read(&*caller, ptr as usize, &mut buffer)?;
+    Ok(buffer)
+}
+
+fn guest_memory(caller: &mut Caller<'_, GuestContext>) -> wasmtime::Result<wasmtime::Memory> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmtime::Error::msg("the extension module doesn't export its memory"))
+}
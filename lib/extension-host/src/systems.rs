@@ -0,0 +1,120 @@
+use std::time::Instant;
+
+use bevy_ecs::entity::Entity;
+use bevy_ecs::system::Command;
+use bevy_ecs::world::{Mut, World};
+use etheryal_extension_common::message::registry::decode_host_message;
+use tracing::{debug, error, warn};
+
+use crate::budget::{BudgetConfig, BudgetExceeded, OverrunPolicy, Throttled};
+use crate::catalog::CatalogEntry;
+use crate::error::{ExtensionHostError, ExtensionTrapped};
+use crate::instance::ExtensionInstance;
+use crate::lifecycle::UnloadExtension;
+use crate::restart;
+
+type RunningInstance<'a> = (
+    Entity,
+    &'a mut ExtensionInstance,
+    Option<&'a CatalogEntry>,
+    Option<&'a mut Throttled>,
+);
+
+pub fn run_extensions(world: &mut World) {
+    let now = Instant::now();
+    let mut received = Vec::new();
+    let mut trapped = Vec::new();
+    let mut exceeded = Vec::new();
+    let mut unthrottled = Vec::new();
+
+    world.resource_scope(|world, budgets: Mut<BudgetConfig>| {
+        let mut instances = world.query::<RunningInstance>();
+        for (extension, mut instance, entry, throttled) in instances.iter_mut(world) {
+            if let Some(mut throttled) = throttled {
+                if throttled.skip_update() {
+                    continue;
+                }
+                unthrottled.push(extension);
+            }
+
+            let identifier = entry
+                .map(|entry| entry.info().identifier())
+                .or_else(|| instance.info().map(|info| info.identifier()))
+                .cloned();
+            let budget = budgets.budget_of(
+                identifier.as_ref(),
+                entry.and_then(|entry| *entry.manifest().budget()),
+            );
+            let fuel = budget.map_or(u64::MAX, |budget| budget.fuel());
+            match (instance.tick(now, fuel), budget) {
+                (Ok(()), _) => {},
+                // The update is suspended where the guest ran out of fuel, and the
+                // messages sent before are still received
+                (Err(ExtensionHostError::OutOfFuel(_)), Some(budget)) => {
+                    exceeded.push(BudgetExceeded {
+                        extension,
+                        identifier,
+                        budget,
+                    });
+                },
+                (Err(error), _) => {
+                    trapped.push(ExtensionTrapped {
+                        extension,
+                        identifier,
+                        error,
+                    });
+                    continue;
+                },
+            }
+            received.extend(
+                instance
+                    .take_messages()
+                    .into_iter()
+                    .map(|encoded| (extension, encoded)),
+            );
+        }
+    });
+
+    for extension in unthrottled {
+        world.entity_mut(extension).remove::<Throttled>();
+    }
+    for event in exceeded {
+        warn!(
+            "The extension {:?} ran out of its {} fuel, applying {:?}",
+            event.extension,
+            event.budget.fuel(),
+            event.budget.on_overrun()
+        );
+        match event.budget.on_overrun() {
+            OverrunPolicy::Skip => {},
+            OverrunPolicy::Throttle { ticks } => {
+                world
+                    .entity_mut(event.extension)
+                    .insert(Throttled::new(ticks));
+            },
+            OverrunPolicy::Unload => UnloadExtension::new(event.extension).apply(world),
+        }
+        world.send_event(event);
+    }
+    restart::handle_traps(world, trapped);
+
+    for (extension, encoded) in received {
+        let (registration, message) = match decode_host_message(&encoded) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                error!("Failed to deserialize message from {extension:?}: {err}");
+                continue;
+            },
+        };
+
+        let type_name = registration.type_name();
+        if registration
+            .send_host_event(world, extension, message)
+            .is_err()
+        {
+            warn!("Failed to downcast host message to '{type_name}'");
+            continue;
+        }
+        debug!("Received an extension host message from {extension:?}: {type_name}");
+    }
+}
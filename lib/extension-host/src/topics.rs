@@ -0,0 +1,163 @@
+//! Fans out the messages published on topics to the extensions subscribed to
+//! them
+use std::collections::{BTreeSet, HashMap};
+
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::Command;
+use etheryal_extension_common::message::encode_message;
+use etheryal_extension_common::message::topic::{
+    Publish, Subscribe, TopicMessage, TopicPattern, Unsubscribe,
+};
+use etheryal_identifier::NamespacedIdentifier;
+use serde::Serialize;
+use tracing::{debug, error, warn};
+
+use crate::catalog::{self, CatalogEntry};
+use crate::instance::ExtensionInstance;
+use crate::HostMessageEvent;
+
+/// A Bevy resource with the topic patterns each extension instance subscribed
+/// to with `Subscribe` messages
+///
+/// The extension host itself receives every `Publish` message as a
+/// [HostMessageEvent].
+#[derive(Resource, Default, Debug)]
+pub struct TopicSubscriptions {
+    patterns: HashMap<Entity, BTreeSet<TopicPattern>>,
+}
+
+impl TopicSubscriptions {
+    /// Returns the topic patterns the extension instance subscribed to
+    pub fn patterns(&self, extension: Entity) -> impl Iterator<Item = &TopicPattern> {
+        self.patterns.get(&extension).into_iter().flatten()
+    }
+
+    /// Returns the extension instances subscribed to the topic
+    pub fn subscribers<'a>(
+        &'a self, topic: &'a NamespacedIdentifier,
+    ) -> impl Iterator<Item = Entity> + 'a {
+        self.patterns
+            .iter()
+            .filter(|(_, patterns)| patterns.iter().any(|pattern| pattern.matches(topic)))
+            .map(|(&extension, _)| extension)
+    }
+}
+
+/// A command publishing a message on a topic from the extension host, sent to
+/// every extension subscribed to it during their next update
+#[derive(Clone, Debug)]
+pub struct PublishTopic {
+    topic: NamespacedIdentifier,
+    publisher: Option<NamespacedIdentifier>,
+    payload: Vec<u8>,
+}
+
+impl PublishTopic {
+    /// Creates a command publishing the message on the topic
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message could not be encoded
+    pub fn new<T>(
+        topic: NamespacedIdentifier, message: &T,
+    ) -> Result<Self, rmp_serde::encode::Error>
+    where
+        T: Serialize + ?Sized, {
+        Ok(Self {
+            topic,
+            publisher: None,
+            payload: rmp_serde::to_vec_named(message)?,
+        })
+    }
+
+    /// Returns the topic the message is published on
+    pub fn topic(&self) -> &NamespacedIdentifier {
+        &self.topic
+    }
+}
+
+impl Command for PublishTopic {
+    fn apply(self, world: &mut World) {
+        let topic = self.topic.clone();
+        let message = TopicMessage::new(self.topic, self.publisher, self.payload);
+        let encoded = match encode_message(&message) {
+            Ok(encoded) => encoded,
+            Err(err) => {
+                error!("Failed to encode a message published on '{topic}': {err}");
+                return;
+            },
+        };
+
+        let subscribers: Vec<_> = world
+            .resource::<TopicSubscriptions>()
+            .subscribers(&topic)
+            .collect();
+        let mut despawned = Vec::new();
+        for extension in subscribers {
+            let Some(mut instance) = world.get_mut::<ExtensionInstance>(extension) else {
+                despawned.push(extension);
+                continue;
+            };
+            if let Err(err) = instance.send_encoded(encoded.clone()) {
+                warn!("Failed to send a message published on '{topic}' to {extension:?}: {err}");
+            }
+        }
+
+        // The subscriptions of the despawned extensions are removed lazily
+        let mut subscriptions = world.resource_mut::<TopicSubscriptions>();
+        for extension in despawned {
+            subscriptions.patterns.remove(&extension);
+        }
+    }
+}
+
+pub(crate) fn update_subscriptions(
+    mut subscribes: EventReader<HostMessageEvent<Subscribe>>,
+    mut unsubscribes: EventReader<HostMessageEvent<Unsubscribe>>,
+    mut subscriptions: ResMut<TopicSubscriptions>,
+) {
+    for subscribe in subscribes.iter() {
+        debug!(
+            "The extension {:?} subscribed to '{}'",
+            subscribe.extension,
+            subscribe.pattern()
+        );
+        subscriptions
+            .patterns
+            .entry(subscribe.extension)
+            .or_default()
+            .insert(subscribe.pattern().clone());
+    }
+    for unsubscribe in unsubscribes.iter() {
+        if let Some(patterns) = subscriptions.patterns.get_mut(&unsubscribe.extension) {
+            patterns.remove(unsubscribe.pattern());
+        }
+    }
+}
+
+pub(crate) fn publish_guest_messages(
+    mut commands: Commands, mut publications: EventReader<HostMessageEvent<Publish>>,
+    instances: Query<(&ExtensionInstance, Option<&CatalogEntry>)>,
+) {
+    for publication in publications.iter() {
+        // The publisher is identified by the extension info of its module, which it
+        // can't change at runtime
+        let publisher = instances
+            .get(publication.extension)
+            .ok()
+            .and_then(|(instance, entry)| catalog::extension_info(instance, entry))
+            .map(|info| info.identifier().clone());
+        if publisher.is_none() {
+            warn!(
+                "Ignored a message published by the unregistered extension {:?}",
+                publication.extension
+            );
+            continue;
+        }
+        commands.add(PublishTopic {
+            topic: publication.topic().clone(),
+            publisher,
+            payload: publication.payload().to_vec(),
+        });
+    }
+}
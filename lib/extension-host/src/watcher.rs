@@ -0,0 +1,66 @@
+//! Reloads the extensions of the extensions directory when their WebAssembly
+//! module changes on disk
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Mutex, PoisonError};
+
+use bevy_ecs::prelude::*;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{info, warn};
+
+use crate::catalog::CatalogEntry;
+use crate::lifecycle::{ReloadExtension, Unloading};
+
+/// Watches the extensions directory, set up with
+/// [ExtensionHostPlugin::with_hot_reload](crate::ExtensionHostPlugin::with_hot_reload)
+#[derive(Resource)]
+pub(crate) struct ModuleWatcher {
+    _watcher: RecommendedWatcher,
+    events: Mutex<Receiver<notify::Result<Event>>>,
+}
+
+impl ModuleWatcher {
+    pub(crate) fn new(directory: &Path) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(directory, RecursiveMode::Recursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            events: Mutex::new(events),
+        })
+    }
+}
+
+pub(crate) fn reload_changed_modules(
+    mut commands: Commands, watcher: Res<ModuleWatcher>,
+    extensions: Query<(Entity, &CatalogEntry), Without<Unloading>>,
+) {
+    let mut changed = HashSet::new();
+    let events = watcher
+        .events
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    for event in events.try_iter() {
+        match event {
+            Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                changed.extend(event.paths);
+            },
+            Ok(_) => {},
+            Err(err) => warn!("Failed to watch the extensions directory: {err}"),
+        }
+    }
+    if changed.is_empty() {
+        return;
+    }
+
+    for (extension, entry) in &extensions {
+        if changed.contains(entry.module_path()) {
+            info!(
+                "The module of the extension '{}' changed, reloading it",
+                entry.info().identifier()
+            );
+            commands.add(ReloadExtension::new(extension));
+        }
+    }
+}
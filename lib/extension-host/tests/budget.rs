@@ -0,0 +1,228 @@
+//! Limits the fuel of the updates of the small extension guests of the
+//! `common` module.
+use std::fs;
+
+use bevy_app::App;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::Events;
+use etheryal_extension_common::message::debug::Ping;
+use etheryal_extension_host::{
+    BudgetExceeded, CatalogEntry, CatalogError, ExtensionBudget, ExtensionHostError,
+    ExtensionHostPlugin, ExtensionInstance, ExtensionRuntime, ExtensionTrapped, HostMessageEvent,
+    LoadReport, OverrunPolicy, Throttled, Unloading,
+};
+use etheryal_identifier::NamespacedIdentifier;
+use tempfile::TempDir;
+
+use crate::common::{
+    counting_guest_module, guest, info, looping_guest_module, looping_start_guest_module, manifest,
+    write_extension,
+};
+
+mod common;
+
+const FUEL: u64 = 100_000;
+
+fn exceeded(app: &mut App) -> Vec<BudgetExceeded> {
+    app.world
+        .resource_mut::<Events<BudgetExceeded>>()
+        .drain()
+        .collect()
+}
+
+fn trapped(app: &mut App) -> Vec<ExtensionTrapped> {
+    app.world
+        .resource_mut::<Events<ExtensionTrapped>>()
+        .drain()
+        .collect()
+}
+
+fn pings(app: &mut App) -> Vec<HostMessageEvent<Ping>> {
+    app.world
+        .resource_mut::<Events<HostMessageEvent<Ping>>>()
+        .drain()
+        .collect()
+}
+
+/// Writes `test:busy`, which never returns from its updates once registered,
+/// with the budget table appended to its manifest
+fn write_busy_extension(root: &TempDir, budget: &str) {
+    let manifest = format!("{}{budget}", manifest("test:busy"));
+    write_extension(root.path(), "busy", &manifest, None);
+    let wasm = wat::parse_str(looping_guest_module(&info("test:busy", "1.0.0", &[])))
+        .expect("valid module");
+    fs::write(root.path().join("busy").join("module.wasm"), wasm).expect("module written");
+}
+
+fn busy_extension(app: &mut App) -> Entity {
+    let mut entries = app.world.query::<(Entity, &CatalogEntry)>();
+    entries.single(&app.world).0
+}
+
+fn is_suspended(app: &App, extension: Entity) -> bool {
+    app.world
+        .get::<ExtensionInstance>(extension)
+        .is_some_and(ExtensionInstance::is_suspended)
+}
+
+#[test]
+fn test_skip_overrun() {
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new().with_default_budget(ExtensionBudget::new(FUEL)));
+    let runtime = app.world.resource::<ExtensionRuntime>();
+    let wasm = wat::parse_str(looping_guest_module(&info("test:busy", "1.0.0", &[])))
+        .expect("valid module");
+    let busy = runtime.load(&wasm).expect("extension loaded");
+    let other = runtime.load(&guest(true)).expect("extension loaded");
+    let busy = app.world.spawn(busy).id();
+    app.world.spawn(other);
+
+    // The guest registers itself within its budget
+    app.update();
+    assert!(exceeded(&mut app).is_empty());
+
+    // The update is suspended, then resumed during each update of the host
+    for _ in 0..2 {
+        app.update();
+        let exceeded = exceeded(&mut app);
+        assert_eq!(exceeded.len(), 1);
+        assert_eq!(exceeded[0].extension, busy);
+        assert_eq!(
+            exceeded[0]
+                .identifier
+                .as_ref()
+                .map(ToString::to_string)
+                .as_deref(),
+            Some("test:busy")
+        );
+        assert_eq!(exceeded[0].budget.on_overrun(), OverrunPolicy::Skip);
+        assert!(is_suspended(&app, busy));
+    }
+    assert!(trapped(&mut app).is_empty());
+}
+
+#[test]
+fn test_suspended_update_resumes() {
+    let mut app = App::new();
+    app.add_plugins(
+        ExtensionHostPlugin::new()
+            .without_default_handler::<Ping>()
+            .with_default_budget(ExtensionBudget::new(FUEL)),
+    );
+    let runtime = app.world.resource::<ExtensionRuntime>();
+    let wasm = wat::parse_str(counting_guest_module(
+        &info("test:counting", "1.0.0", &[]),
+        100_000,
+    ))
+    .expect("valid module");
+    let counting = runtime.load(&wasm).expect("extension loaded");
+    let counting = app.world.spawn(counting).id();
+    app.update();
+
+    // The update needs a few times its budget, so it is resumed until it counts
+    // down to zero and sends its ping
+    let mut suspensions = 0;
+    while pings(&mut app).is_empty() {
+        assert!(suspensions < 20, "the suspended update never returned");
+        app.update();
+        suspensions += exceeded(&mut app).len();
+    }
+    assert!(suspensions > 1);
+    assert!(!is_suspended(&app, counting));
+    assert!(trapped(&mut app).is_empty());
+}
+
+#[test]
+fn test_throttle_from_manifest() {
+    let root = TempDir::new().expect("temporary directory");
+    write_busy_extension(
+        &root,
+        "[budget]\nfuel = 100_000\non_overrun = { throttle = { ticks = 2 } }\n",
+    );
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    app.update();
+    let busy = busy_extension(&mut app);
+
+    app.update();
+    let events = exceeded(&mut app);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].budget.fuel(), FUEL);
+    assert!(app.world.get::<Throttled>(busy).is_some());
+
+    // The guest doesn't run the next two updates, then its update is resumed
+    for _ in 0..2 {
+        app.update();
+        assert!(exceeded(&mut app).is_empty());
+    }
+    app.update();
+    assert_eq!(exceeded(&mut app).len(), 1);
+    assert!(is_suspended(&app, busy));
+    assert!(trapped(&mut app).is_empty());
+}
+
+#[test]
+fn test_unload_from_host_budget() {
+    let root = TempDir::new().expect("temporary directory");
+    write_busy_extension(&root, "[budget]\nfuel = 1_000_000_000\n");
+    let budget = ExtensionBudget::new(FUEL).with_overrun_policy(OverrunPolicy::Unload);
+    let mut app = App::new();
+    app.add_plugins(
+        ExtensionHostPlugin::new()
+            .with_extensions_dir(root.path())
+            .with_extension_budget(
+                NamespacedIdentifier::try_from("test:busy").expect("valid identifier"),
+                budget,
+            ),
+    );
+    app.update();
+    let busy = busy_extension(&mut app);
+
+    app.update();
+    let events = exceeded(&mut app);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].budget, budget);
+    assert!(app.world.get::<Unloading>(busy).is_some());
+
+    // The guest never acknowledges the shutdown, so it is despawned once its
+    // grace period is over
+    let mut updates = 0;
+    while app.world.get_entity(busy).is_some() {
+        assert!(updates < 100, "the busy extension was never despawned");
+        app.update();
+        updates += 1;
+    }
+    assert!(trapped(&mut app).is_empty());
+}
+
+#[test]
+fn test_entry_point_limited_by_budget() {
+    let root = TempDir::new().expect("temporary directory");
+    let path = root.path().join("starting");
+    write_extension(
+        root.path(),
+        "starting",
+        &format!("{}[budget]\nfuel = 100_000\n", manifest("test:starting")),
+        None,
+    );
+    let wasm = wat::parse_str(looping_start_guest_module(&info(
+        "test:starting",
+        "1.0.0",
+        &[],
+    )))
+    .expect("valid module");
+    fs::write(path.join("module.wasm"), wasm).expect("module written");
+
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    app.update();
+
+    let report = app.world.resource::<LoadReport>();
+    assert!(report.loaded().is_empty());
+    assert_eq!(report.failed().len(), 1);
+    assert_eq!(report.failed()[0].path, path);
+    assert!(matches!(report.failed()[0].error, CatalogError::Load {
+        source: ExtensionHostError::OutOfFuel(FUEL),
+        ..
+    }));
+}
@@ -0,0 +1,221 @@
+//! Loads extensions directories of the small extension guests of the `common`
+//! module.
+use bevy_app::App;
+use etheryal_extension_host::{
+    CatalogEntry, CatalogError, ExtensionCatalog, ExtensionHostPlugin, LoadReport, SkipReason,
+    MANIFEST_FILE,
+};
+use etheryal_identifier::NamespacedIdentifier;
+use semver::Version;
+use tempfile::TempDir;
+
+use crate::common::{info, manifest, versioned_manifest, write_extension};
+
+mod common;
+
+fn identifiers<'a>(identifiers: impl IntoIterator<Item = &'a NamespacedIdentifier>) -> Vec<String> {
+    identifiers.into_iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn test_load_report() {
+    let root = TempDir::new().expect("temporary directory");
+    let path = root.path();
+    write_extension(
+        path,
+        "app",
+        &manifest("test:app"),
+        Some(&info("test:app", "1.0.0", &[
+            ("test:lib", "^1", false),
+            ("etheryal:etheryal", ">=0.1.0-nightly", false),
+            ("test:optional", "*", true),
+        ])),
+    );
+    write_extension(
+        path,
+        "lib",
+        &versioned_manifest("test:lib", "1.2.0"),
+        Some(&info("test:lib", "1.2.0", &[])),
+    );
+    write_extension(
+        path,
+        "lib_copy",
+        &versioned_manifest("test:lib", "1.3.0"),
+        Some(&info("test:lib", "1.3.0", &[])),
+    );
+    write_extension(
+        path,
+        "missing",
+        &manifest("test:missing"),
+        Some(&info("test:missing", "1.0.0", &[(
+            "test:unknown",
+            "*",
+            false,
+        )])),
+    );
+    write_extension(
+        path,
+        "outdated",
+        &manifest("test:outdated"),
+        Some(&info("test:outdated", "1.0.0", &[(
+            "test:lib", "^2", false,
+        )])),
+    );
+    write_extension(
+        path,
+        "transitive",
+        &manifest("test:transitive"),
+        Some(&info("test:transitive", "1.0.0", &[(
+            "test:missing",
+            "*",
+            false,
+        )])),
+    );
+    write_extension(
+        path,
+        "malformed",
+        "name = 42",
+        Some(&info("test:malformed", "1.0.0", &[])),
+    );
+    write_extension(path, "no_module", &manifest("test:no_module"), None);
+    write_extension(
+        path,
+        "mismatched",
+        &versioned_manifest("test:mismatched", "2.0.0"),
+        Some(&info("test:mismatched", "1.0.0", &[])),
+    );
+    write_extension(
+        path,
+        "escaping",
+        &format!(
+            "{}module = \"../lib/module.wasm\"\n",
+            manifest("test:escaping")
+        ),
+        None,
+    );
+
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new());
+    let report = ExtensionCatalog::scan(path)
+        .expect("directory scanned")
+        .with_provided(
+            NamespacedIdentifier::try_from("etheryal:etheryal").expect("valid identifier"),
+            Version::parse("0.1.0-nightly").expect("valid version"),
+        )
+        .load(&mut app.world);
+
+    // The dependencies are loaded first
+    assert_eq!(
+        identifiers(report.loaded().iter().map(|loaded| &loaded.identifier)),
+        ["test:lib", "test:app"]
+    );
+    let entity = report.loaded()[1].entity;
+    let entry = app
+        .world
+        .get::<CatalogEntry>(entity)
+        .expect("catalog entry");
+    assert_eq!(entry.directory(), &path.join("app"));
+
+    let reason = |name: &str| {
+        report
+            .skipped()
+            .iter()
+            .find(|skipped| skipped.path == path.join(name))
+            .map(|skipped| &skipped.reason)
+    };
+    assert_eq!(report.skipped().len(), 4);
+    assert!(matches!(
+        reason("lib_copy"),
+        Some(SkipReason::Duplicate { first }) if first == &path.join("lib")
+    ));
+    assert!(matches!(
+        reason("missing"),
+        Some(SkipReason::MissingDependency(identifier)) if identifier.to_string() == "test:unknown"
+    ));
+    assert!(matches!(
+        reason("outdated"),
+        Some(SkipReason::UnsatisfiedDependency { found, .. }) if found.to_string() == "1.2.0"
+    ));
+    assert!(matches!(
+        reason("transitive"),
+        Some(SkipReason::DependencyNotLoaded(identifier)) if identifier.to_string() == "test:missing"
+    ));
+
+    let error = |name: &str| {
+        report
+            .failed()
+            .iter()
+            .find(|failed| failed.path == path.join(name))
+            .map(|failed| &failed.error)
+    };
+    assert_eq!(report.failed().len(), 4);
+    assert!(matches!(
+        error("malformed"),
+        Some(CatalogError::Manifest { path: manifest, .. })
+            if manifest == &path.join("malformed").join(MANIFEST_FILE)
+    ));
+    assert!(matches!(
+        error("no_module"),
+        Some(CatalogError::MissingModule { .. })
+    ));
+    assert!(matches!(
+        error("mismatched"),
+        Some(CatalogError::InfoMismatch { manifest_version, info, .. })
+            if manifest_version.to_string() == "2.0.0" && info.version().to_string() == "1.0.0"
+    ));
+    assert!(matches!(
+        error("escaping"),
+        Some(CatalogError::ModuleOutsideDirectory { directory, .. })
+            if directory == &path.join("escaping")
+    ));
+}
+
+#[test]
+fn test_dependency_cycle() {
+    let root = TempDir::new().expect("temporary directory");
+    let path = root.path();
+    write_extension(
+        path,
+        "first",
+        &manifest("test:first"),
+        Some(&info("test:first", "1.0.0", &[("test:second", "*", false)])),
+    );
+    write_extension(
+        path,
+        "second",
+        &manifest("test:second"),
+        Some(&info("test:second", "1.0.0", &[("test:first", "*", false)])),
+    );
+
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new());
+    let report = ExtensionCatalog::scan(path)
+        .expect("directory scanned")
+        .load(&mut app.world);
+
+    assert!(report.loaded().is_empty());
+    assert_eq!(report.skipped().len(), 2);
+    assert!(report
+        .skipped()
+        .iter()
+        .all(|skipped| matches!(skipped.reason, SkipReason::DependencyCycle)));
+}
+
+#[test]
+fn test_load_extensions_dir_at_startup() {
+    let root = TempDir::new().expect("temporary directory");
+    write_extension(
+        root.path(),
+        "example",
+        &manifest("test:example"),
+        Some(&info("test:example", "1.0.0", &[])),
+    );
+
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    app.update();
+
+    let report = app.world.resource::<LoadReport>();
+    assert_eq!(report.loaded().len(), 1);
+    assert!(report.skipped().is_empty() && report.failed().is_empty());
+}
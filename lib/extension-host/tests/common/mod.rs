@@ -0,0 +1,348 @@
+//! Small extension guests written in the WebAssembly text format, which reply
+//! to every message received from the extension host with the same message, a
+//! `Ping` unless stated otherwise.
+#![allow(dead_code)]
+use std::fs;
+use std::path::Path;
+
+use etheryal_extension_common::{
+    ExtensionModuleDependency, ExtensionModuleInfo, EXTENSION_INFO_SECTION,
+};
+use etheryal_extension_host::MANIFEST_FILE;
+use etheryal_identifier::NamespacedIdentifier;
+use semver::{Version, VersionReq};
+
+/// The encoded `{ "type": "ping" }` host message
+pub const PING: &[u8] = b"\x81\xA4type\xA4ping";
+
+/// The encoded `{ "type": "shutdown_host" }` host message
+pub const SHUTDOWN_HOST: &[u8] = b"\x81\xA4type\xADshutdown_host";
+
+/// Returns the info of an extension with dependencies given as
+/// `(identifier, version requirement, optional)`
+pub fn info(
+    identifier: &str, version: &str, dependencies: &[(&str, &str, bool)],
+) -> ExtensionModuleInfo {
+    ExtensionModuleInfo::builder()
+        .name("test".into())
+        .identifier(NamespacedIdentifier::try_from(identifier).expect("valid identifier"))
+        .version(Version::parse(version).expect("valid version"))
+        .dependencies(
+            dependencies
+                .iter()
+                .map(|&(identifier, version, optional)| {
+                    ExtensionModuleDependency::builder()
+                        .identifier(
+                            NamespacedIdentifier::try_from(identifier).expect("valid identifier"),
+                        )
+                        .version(VersionReq::parse(version).expect("valid version requirement"))
+                        .optional(optional)
+                        .build()
+                })
+                .collect(),
+        )
+        .build()
+}
+
+/// Returns a guest replying with `Ping`, which registers itself on its first
+/// update if `register` is set
+pub fn guest(register: bool) -> Vec<u8> {
+    guest_replying(register, PING)
+}
+
+/// Returns a guest replying with the encoded `reply`, which registers itself on
+/// its first update if `register` is set
+pub fn guest_replying(register: bool, reply: &[u8]) -> Vec<u8> {
+    wat::parse_str(guest_module(
+        &info("etheryal:test", "1.0.0", &[]),
+        register,
+        reply,
+    ))
+    .expect("valid module")
+}
+
+/// Returns the text of a guest with the extension info embedded like
+/// `#[etheryal_extension::main]` does
+pub fn guest_module(info: &ExtensionModuleInfo, register: bool, reply: &[u8]) -> String {
+    guest_module_sending(info, register.then_some(info), reply)
+}
+
+/// Returns the text of a guest with the `embedded` extension info, which
+/// registers itself with the extension info `sent` on its first update,
+/// claiming to be another extension
+pub fn impostor_guest_module(
+    embedded: &ExtensionModuleInfo, sent: &ExtensionModuleInfo, reply: &[u8],
+) -> String {
+    guest_module_sending(embedded, Some(sent), reply)
+}
+
+fn guest_module_sending(
+    embedded: &ExtensionModuleInfo, sent: Option<&ExtensionModuleInfo>, reply: &[u8],
+) -> String {
+    let embedded = rmp_serde::to_vec_named(embedded).expect("info encoded");
+    let sent = sent.map_or_else(Vec::new, |sent| {
+        rmp_serde::to_vec_named(sent).expect("info encoded")
+    });
+    assert!(
+        sent.len() <= 512,
+        "the extension info fits before the reply"
+    );
+    let register = if sent.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "(call $extension_info (i32.const {}) (i32.const 0))",
+            sent.len()
+        )
+    };
+
+    format!(
+        r#"
+        (module
+          (import "host" "extension_info" (func $extension_info (param i32 i32)))
+          (import "host" "send_message" (func $send_message (param i32 i32)))
+          (import "host" "recv_message" (func $recv_message (result i32)))
+          (import "host" "read_message_buf" (func $read_message_buf (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{sent}")
+          (data (i32.const 512) "{reply}")
+          (global $registered (mut i32) (i32.const 0))
+          (func (export "_start"))
+          (func (export "etheryal_tick") (param i64)
+            (local $len i32)
+            (if (i32.eqz (global.get $registered))
+              (then {register} (global.set $registered (i32.const 1))))
+            (block $done
+              (loop $next
+                (local.set $len (call $recv_message))
+                (br_if $done (i32.eqz (local.get $len)))
+                (drop (call $read_message_buf (i32.const 256) (i32.const 1024)))
+                (call $send_message (i32.const {reply_len}) (i32.const 512))
+                (br $next))))
+          (@custom "{section}" "{embedded}"))
+        "#,
+        sent = escape(&sent),
+        embedded = escape(&embedded),
+        reply = escape(reply),
+        reply_len = reply.len(),
+        section = EXTENSION_INFO_SECTION,
+    )
+}
+
+/// Returns the text of a guest which replies to each message starting with
+/// `from` with the same message, its prefix `from` replaced by `to`, which must
+/// not be longer
+pub fn echo_guest_module(info: &ExtensionModuleInfo, from: &[u8], to: &[u8]) -> String {
+    assert!(to.len() <= from.len(), "the replacement prefix is longer");
+    let info = rmp_serde::to_vec_named(info).expect("info encoded");
+    format!(
+        r#"
+        (module
+          (import "host" "extension_info" (func $extension_info (param i32 i32)))
+          (import "host" "send_message" (func $send_message (param i32 i32)))
+          (import "host" "recv_message" (func $recv_message (result i32)))
+          (import "host" "read_message_buf" (func $read_message_buf (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{info}")
+          (data (i32.const 1024) "{from}")
+          (data (i32.const 2048) "{to}")
+          (global $registered (mut i32) (i32.const 0))
+          (func (export "_start"))
+          (func $starts_with_from (result i32)
+            (local $index i32)
+            (block $different
+              (loop $next
+                (if (i32.eq (local.get $index) (i32.const {from_len}))
+                  (then (return (i32.const 1))))
+                (br_if $different
+                  (i32.ne
+                    (i32.load8_u (i32.add (i32.const 1024) (local.get $index)))
+                    (i32.load8_u (i32.add (i32.const 4096) (local.get $index)))))
+                (local.set $index (i32.add (local.get $index) (i32.const 1)))
+                (br $next)))
+            (i32.const 0))
+          (func (export "etheryal_tick") (param i64)
+            (local $len i32)
+            (if (i32.eqz (global.get $registered))
+              (then
+                (call $extension_info (i32.const {info_len}) (i32.const 0))
+                (global.set $registered (i32.const 1))))
+            (block $done
+              (loop $next
+                (local.set $len (call $recv_message))
+                (br_if $done (i32.eqz (local.get $len)))
+                (drop (call $read_message_buf (i32.const 61440) (i32.const 4096)))
+                (if (i32.and
+                      (i32.ge_u (local.get $len) (i32.const {from_len}))
+                      (call $starts_with_from))
+                  (then
+                    (memory.copy
+                      (i32.const {to_start})
+                      (i32.const 2048)
+                      (i32.const {to_len}))
+                    (call $send_message
+                      (i32.sub (local.get $len) (i32.const {shift}))
+                      (i32.const {to_start}))))
+                (br $next))))
+          (@custom "{section}" "{info}"))
+        "#,
+        info = escape(&info),
+        info_len = info.len(),
+        from = escape(from),
+        from_len = from.len(),
+        to = escape(to),
+        to_len = to.len(),
+        to_start = 4096 + from.len() - to.len(),
+        shift = from.len() - to.len(),
+        section = EXTENSION_INFO_SECTION,
+    )
+}
+
+/// Returns the text of a guest which registers itself on its first update, then
+/// never returns from its next updates
+pub fn looping_guest_module(info: &ExtensionModuleInfo) -> String {
+    let info = rmp_serde::to_vec_named(info).expect("info encoded");
+    format!(
+        r#"
+        (module
+          (import "host" "extension_info" (func $extension_info (param i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{info}")
+          (global $registered (mut i32) (i32.const 0))
+          (func (export "_start"))
+          (func (export "etheryal_tick") (param i64)
+            (if (i32.eqz (global.get $registered))
+              (then
+                (call $extension_info (i32.const {info_len}) (i32.const 0))
+                (global.set $registered (i32.const 1))
+                (return)))
+            (loop $forever (br $forever)))
+          (@custom "{section}" "{info}"))
+        "#,
+        info = escape(&info),
+        info_len = info.len(),
+        section = EXTENSION_INFO_SECTION,
+    )
+}
+
+/// Returns the text of a guest which registers itself on its first update, then
+/// counts down from `count` during each update before sending a `Ping`
+pub fn counting_guest_module(info: &ExtensionModuleInfo, count: u32) -> String {
+    let info = rmp_serde::to_vec_named(info).expect("info encoded");
+    assert!(info.len() <= 512, "the extension info fits before the ping");
+    format!(
+        r#"
+        (module
+          (import "host" "extension_info" (func $extension_info (param i32 i32)))
+          (import "host" "send_message" (func $send_message (param i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{info}")
+          (data (i32.const 512) "{ping}")
+          (global $registered (mut i32) (i32.const 0))
+          (func (export "_start"))
+          (func (export "etheryal_tick") (param i64)
+            (local $remaining i32)
+            (if (i32.eqz (global.get $registered))
+              (then
+                (call $extension_info (i32.const {info_len}) (i32.const 0))
+                (global.set $registered (i32.const 1))
+                (return)))
+            (local.set $remaining (i32.const {count}))
+            (loop $next
+              (local.set $remaining (i32.sub (local.get $remaining) (i32.const 1)))
+              (br_if $next (local.get $remaining)))
+            (call $send_message (i32.const {ping_len}) (i32.const 512)))
+          (@custom "{section}" "{info}"))
+        "#,
+        info = escape(&info),
+        info_len = info.len(),
+        ping = escape(PING),
+        ping_len = PING.len(),
+        section = EXTENSION_INFO_SECTION,
+    )
+}
+
+/// Returns the text of a guest whose entry point never returns
+pub fn looping_start_guest_module(info: &ExtensionModuleInfo) -> String {
+    let info = rmp_serde::to_vec_named(info).expect("info encoded");
+    format!(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "_start")
+            (loop $forever (br $forever)))
+          (func (export "etheryal_tick") (param i64))
+          (@custom "{section}" "{info}"))
+        "#,
+        info = escape(&info),
+        section = EXTENSION_INFO_SECTION,
+    )
+}
+
+/// Returns the text of a guest which registers itself on its first update, then
+/// traps when it receives a message, such as the confirmation of its
+/// registration
+pub fn crashing_guest_module(info: &ExtensionModuleInfo) -> String {
+    let info = rmp_serde::to_vec_named(info).expect("info encoded");
+    format!(
+        r#"
+        (module
+          (import "host" "extension_info" (func $extension_info (param i32 i32)))
+          (import "host" "recv_message" (func $recv_message (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{info}")
+          (global $registered (mut i32) (i32.const 0))
+          (func (export "_start"))
+          (func (export "etheryal_tick") (param i64)
+            (if (i32.eqz (global.get $registered))
+              (then
+                (call $extension_info (i32.const {info_len}) (i32.const 0))
+                (global.set $registered (i32.const 1))
+                (return)))
+            (if (call $recv_message)
+              (then unreachable)))
+          (@custom "{section}" "{info}"))
+        "#,
+        info = escape(&info),
+        info_len = info.len(),
+        section = EXTENSION_INFO_SECTION,
+    )
+}
+
+/// Returns the manifest of version 1.0.0 of an extension named after its
+/// identifier
+pub fn manifest(id: &str) -> String {
+    versioned_manifest(id, "1.0.0")
+}
+
+/// Returns the manifest of an extension named after its identifier
+pub fn versioned_manifest(id: &str, version: &str) -> String {
+    format!("name = \"{id}\"\nid = \"{id}\"\nversion = \"{version}\"\n")
+}
+
+/// Writes an extension with its manifest, and a module with the embedded info
+pub fn write_extension(
+    root: &Path, directory: &str, manifest: &str, info: Option<&ExtensionModuleInfo>,
+) {
+    let directory = root.join(directory);
+    fs::create_dir_all(&directory).expect("directory created");
+    fs::write(directory.join(MANIFEST_FILE), manifest).expect("manifest written");
+    if let Some(info) = info {
+        let wasm = wat::parse_str(guest_module(info, true, PING)).expect("valid module");
+        fs::write(directory.join("module.wasm"), wasm).expect("module written");
+    }
+}
+
+/// Returns the custom section embedding the extension info in a module, like
+/// `#[etheryal_extension::main]` does
+pub fn info_section(info: &ExtensionModuleInfo) -> String {
+    let info = rmp_serde::to_vec_named(info).expect("info encoded");
+    format!(
+        r#"(@custom "{EXTENSION_INFO_SECTION}" "{}")"#,
+        escape(&info)
+    )
+}
+
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("\\{byte:02x}")).collect()
+}
@@ -0,0 +1,136 @@
+//! Routes the messages between the small extension guests of the `common`
+//! module.
+use bevy_app::App;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::Events;
+use etheryal_extension_common::message::debug::Ping;
+use etheryal_extension_common::message::direct::DirectMessage;
+use etheryal_extension_common::message::HostMessageEnum;
+use etheryal_extension_host::{ExtensionHostPlugin, ExtensionRuntime, HostMessageEvent};
+use etheryal_identifier::NamespacedIdentifier;
+use tempfile::TempDir;
+
+use crate::common::{guest_module, impostor_guest_module, info, manifest, write_extension, PING};
+
+mod common;
+
+/// The encoded `DirectMessage` sent to `test:lib`
+fn direct_message() -> Vec<u8> {
+    let to = NamespacedIdentifier::try_from("test:lib").expect("valid identifier");
+    let message = DirectMessage::new(to, "hello").expect("message encoded");
+    rmp_serde::to_vec_named(&HostMessageEnum::from(message)).expect("message encoded")
+}
+
+/// An extension host which doesn't reply to `Ping`, so the guests only reply to
+/// the messages they receive
+fn app(plugin: ExtensionHostPlugin) -> App {
+    let mut app = App::new();
+    app.add_plugins(plugin.without_default_handler::<Ping>());
+    app
+}
+
+/// Loads a guest replying with the encoded `reply`
+fn load(app: &mut App, identifier: &str, dependencies: &[&str], reply: &[u8]) -> Entity {
+    let dependencies: Vec<_> = dependencies
+        .iter()
+        .map(|&dependency| (dependency, "^1", false))
+        .collect();
+    let wasm = wat::parse_str(guest_module(
+        &info(identifier, "1.0.0", &dependencies),
+        true,
+        reply,
+    ))
+    .expect("valid module");
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&wasm)
+        .expect("extension loaded");
+    app.world.spawn(instance).id()
+}
+
+fn pings(app: &mut App) -> Vec<Entity> {
+    app.world
+        .resource_mut::<Events<HostMessageEvent<Ping>>>()
+        .drain()
+        .map(|ping| ping.extension)
+        .collect()
+}
+
+#[test]
+fn test_route_to_dependency() {
+    let mut app = app(ExtensionHostPlugin::new());
+    let lib = load(&mut app, "test:lib", &[], PING);
+    load(&mut app, "test:app", &["test:lib"], &direct_message());
+    load(&mut app, "test:other", &[], &direct_message());
+
+    // The guests send their message once their registration is confirmed, and
+    // `test:lib` replies to the message of `test:app` only
+    app.update();
+    assert_eq!(pings(&mut app), [lib]);
+    app.update();
+    assert_eq!(pings(&mut app), [lib]);
+    app.update();
+    assert!(pings(&mut app).is_empty());
+}
+
+#[test]
+fn test_accept_messages_from() {
+    let root = TempDir::new().expect("temporary directory");
+    let lib_manifest = format!("{}accept_messages_from = [\"*\"]\n", manifest("test:lib"));
+    write_extension(
+        root.path(),
+        "lib",
+        &lib_manifest,
+        Some(&info("test:lib", "1.0.0", &[])),
+    );
+    let mut app = app(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    load(&mut app, "test:other", &[], &direct_message());
+
+    app.update();
+    assert_eq!(pings(&mut app).len(), 1);
+    app.update();
+    assert_eq!(pings(&mut app).len(), 1);
+    app.update();
+    assert!(pings(&mut app).is_empty());
+}
+
+#[test]
+fn test_impostor_not_routed() {
+    let mut app = app(ExtensionHostPlugin::new());
+    let lib = load(&mut app, "test:lib", &[], PING);
+    // `test:app` claims to depend on `test:lib` at runtime only, and `test:fake`
+    // claims to be `test:lib`
+    let impostors = [
+        (
+            info("test:app", "1.0.0", &[]),
+            info("test:app", "1.0.0", &[("test:lib", "^1", false)]),
+        ),
+        (
+            info("test:fake", "1.0.0", &[]),
+            info("test:lib", "1.0.0", &[]),
+        ),
+    ];
+    let impostors: Vec<_> = impostors
+        .iter()
+        .map(|(embedded, sent)| {
+            let wasm = wat::parse_str(impostor_guest_module(embedded, sent, &direct_message()))
+                .expect("valid module");
+            let instance = app
+                .world
+                .resource::<ExtensionRuntime>()
+                .load(&wasm)
+                .expect("extension loaded");
+            app.world.spawn(instance).id()
+        })
+        .collect();
+
+    // Only `test:lib` replies to the confirmation of its registration
+    app.update();
+    assert_eq!(pings(&mut app), [lib]);
+    app.update();
+    assert!(pings(&mut app).is_empty());
+    for impostor in impostors {
+        assert!(app.world.get_entity(impostor).is_none());
+    }
+}
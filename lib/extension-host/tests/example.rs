@@ -0,0 +1,146 @@
+//! Runs the example extensions of the `etheryal-extension` crate, built for the
+//! `wasm32-wasip1` target.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fs};
+
+use bevy_app::{App, AppExit};
+use bevy_ecs::event::Events;
+use etheryal_extension_common::message::debug::Ping;
+use etheryal_extension_host::{
+    BudgetExceeded, ExtensionHostPlugin, ExtensionInstance, ExtensionRuntime, ExtensionTrapped,
+    HostMessageEvent, OverrunPolicy, ShutdownPermission, Unloading, MANIFEST_FILE,
+};
+use tempfile::TempDir;
+
+const TARGET: &str = "wasm32-wasip1";
+
+/// Builds an example extension, returning `None` if the `wasm32-wasip1`
+/// target isn't installed
+fn build_example(example: &str) -> Option<Vec<u8>> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let sysroot = Command::new(rustc)
+        .args(["--print", "sysroot"])
+        .output()
+        .expect("rustc runs");
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&sysroot.stdout).trim());
+    if !sysroot.join("lib/rustlib").join(TARGET).exists() {
+        eprintln!("Skipped the example {example}, the {TARGET} target isn't installed");
+        return None;
+    }
+
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    // The example is built in its own target directory, as the directory of the
+    // tests is locked while they run, and without debug info, which makes the
+    // module much slower to compile
+    let target_dir = workspace.join("target/tests/wasm");
+    let status = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+        .current_dir(&workspace)
+        .env("CARGO_PROFILE_DEV_DEBUG", "0")
+        .env("CARGO_PROFILE_DEV_STRIP", "true")
+        .args(["build", "-p", "etheryal-extension", "--example", example])
+        .args(["--target", TARGET])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("cargo runs");
+    assert!(status.success(), "the example {example} builds");
+
+    let wasm = target_dir
+        .join(TARGET)
+        .join("debug/examples")
+        .join(example)
+        .with_extension("wasm");
+    Some(fs::read(wasm).expect("example extension built"))
+}
+
+#[test]
+fn test_example_extension() {
+    let Some(wasm) = build_example("example_extension") else {
+        return;
+    };
+
+    let mut app = App::new();
+    app.add_plugins(
+        ExtensionHostPlugin::new().with_shutdown_permission(ShutdownPermission::AllowAll),
+    );
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&wasm)
+        .expect("extension loaded");
+    let extension = app.world.spawn(instance).id();
+
+    // The extension sends a `Ping` once its registration is confirmed, then
+    // requests the host to shut down when it receives the `Pong` reply
+    let mut pings = 0;
+    let mut updates = 0;
+    while app.world.resource::<Events<AppExit>>().is_empty() {
+        assert!(
+            updates < 10,
+            "the example extension never shut the host down"
+        );
+        app.update();
+        pings += app
+            .world
+            .resource_mut::<Events<HostMessageEvent<Ping>>>()
+            .drain()
+            .filter(|ping| ping.extension == extension)
+            .count();
+        updates += 1;
+    }
+    assert_eq!(pings, 1);
+
+    let info = app
+        .world
+        .get::<ExtensionInstance>(extension)
+        .and_then(|instance| instance.info())
+        .expect("extension registered");
+    assert_eq!(info.identifier().to_string(), "example:extension_module");
+}
+
+#[test]
+fn test_busy_extension_unloads() {
+    let Some(wasm) = build_example("busy_extension") else {
+        return;
+    };
+    let root = TempDir::new().expect("temporary directory");
+    let directory = root.path().join("busy");
+    fs::create_dir(&directory).expect("directory created");
+    fs::write(
+        directory.join(MANIFEST_FILE),
+        "name = \"busy\"\nid = \"example:busy_extension\"\nversion = \
+         \"0.1.0-nightly\"\n\n[budget]\nfuel = 100_000_000\non_overrun = \"unload\"\n",
+    )
+    .expect("manifest written");
+    fs::write(directory.join("module.wasm"), wasm).expect("module written");
+
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+
+    // The extension runs out of fuel once it is registered, so it is unloaded,
+    // and despawned once its grace period is over as its suspended update never
+    // returns to acknowledge the shutdown
+    let mut busy = None;
+    let mut updates = 0;
+    while busy.is_none_or(|busy| app.world.get_entity(busy).is_some()) {
+        assert!(updates < 100, "the busy extension was never unloaded");
+        app.update();
+        let exceeded: Vec<_> = app
+            .world
+            .resource_mut::<Events<BudgetExceeded>>()
+            .drain()
+            .collect();
+        for exceeded in exceeded {
+            assert_eq!(exceeded.budget.fuel(), 100_000_000);
+            assert_eq!(exceeded.budget.on_overrun(), OverrunPolicy::Unload);
+            // The suspended update keeps running out of fuel until it is despawned
+            if busy.replace(exceeded.extension).is_none() {
+                assert!(app.world.get::<Unloading>(exceeded.extension).is_some());
+            }
+            assert_eq!(busy, Some(exceeded.extension));
+        }
+        updates += 1;
+    }
+    assert!(app.world.resource::<Events<ExtensionTrapped>>().is_empty());
+}
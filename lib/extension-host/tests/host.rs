@@ -0,0 +1,219 @@
+//! Runs the small extension guests of the `common` module.
+use bevy_app::{App, AppExit};
+use bevy_ecs::event::Events;
+use bevy_ecs::system::Command;
+use etheryal_extension_common::message::debug::{Ping, Pong};
+use etheryal_extension_host::{
+    ExtensionHostError, ExtensionHostPlugin, ExtensionInstance, ExtensionRuntime, ExtensionTrapped,
+    HostMessageEvent, SendToGuest, ShutdownPermission,
+};
+use etheryal_identifier::NamespacedIdentifier;
+
+use crate::common::{
+    guest, guest_module, guest_replying, impostor_guest_module, info, PING, SHUTDOWN_HOST,
+};
+
+mod common;
+
+/// An extension host which doesn't reply to `Ping`, so the guests only reply to
+/// the messages sent by the tests
+fn app() -> App {
+    app_with(ExtensionHostPlugin::new().without_default_handler::<Ping>())
+}
+
+fn app_with(plugin: ExtensionHostPlugin) -> App {
+    let mut app = App::new();
+    app.add_plugins(plugin);
+    app
+}
+
+fn exited(app: &mut App) -> bool {
+    app.world.resource_mut::<Events<AppExit>>().drain().count() > 0
+}
+
+fn pings(app: &mut App) -> Vec<HostMessageEvent<Ping>> {
+    app.world
+        .resource_mut::<Events<HostMessageEvent<Ping>>>()
+        .drain()
+        .collect()
+}
+
+#[test]
+fn test_receive_host_message() {
+    let mut app = app();
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&guest(true))
+        .expect("extension loaded");
+    let extension = app.world.spawn(instance).id();
+
+    // The guest replies to the confirmation of its registration
+    app.update();
+    let pings = pings(&mut app);
+    assert_eq!(pings.len(), 1);
+    assert_eq!(pings[0].extension, extension);
+
+    let instance = app.world.get::<ExtensionInstance>(extension);
+    let info = instance
+        .and_then(|instance| instance.info())
+        .expect("extension registered");
+    assert_eq!(info.name(), "test");
+}
+
+#[test]
+fn test_send_to_guest() {
+    let mut app = app();
+    let runtime = app.world.resource::<ExtensionRuntime>();
+    let first = runtime.load(&guest(true)).expect("extension loaded");
+    let second = runtime.load(&guest(true)).expect("extension loaded");
+    let first = app.world.spawn(first).id();
+    let second = app.world.spawn(second).id();
+    app.update();
+    pings(&mut app);
+
+    SendToGuest::new(second, Pong).apply(&mut app.world);
+    app.update();
+    let received: Vec<_> = pings(&mut app).iter().map(|ping| ping.extension).collect();
+    assert_eq!(received, [second]);
+
+    SendToGuest::broadcast(Pong).apply(&mut app.world);
+    app.update();
+    let mut received: Vec<_> = pings(&mut app).iter().map(|ping| ping.extension).collect();
+    received.sort();
+    let mut expected = [first, second];
+    expected.sort();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn test_missing_tick_export() {
+    let app = app();
+    let wat = guest_module(&info("etheryal:test", "1.0.0", &[]), true, PING)
+        .replace("\"etheryal_tick\"", "\"tick\"");
+    let wasm = wat::parse_str(wat).expect("valid module");
+    let result = app.world.resource::<ExtensionRuntime>().load(&wasm);
+    assert!(matches!(
+        result,
+        Err(ExtensionHostError::MissingExport("etheryal_tick"))
+    ));
+}
+
+#[test]
+fn test_missing_embedded_info() {
+    let app = app();
+    let wat = r#"(module (memory (export "memory") 1) (func (export "_start")))"#;
+    let result = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(wat.as_bytes());
+    assert!(matches!(result, Err(ExtensionHostError::InvalidModule(_))));
+}
+
+#[test]
+fn test_unregistered_guest_traps() {
+    let mut app = app();
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&guest(false))
+        .expect("extension loaded");
+    let extension = app.world.spawn(instance).id();
+    SendToGuest::new(extension, Pong).apply(&mut app.world);
+
+    app.update();
+    assert!(app.world.get_entity(extension).is_none());
+    let trapped: Vec<_> = app
+        .world
+        .resource_mut::<Events<ExtensionTrapped>>()
+        .drain()
+        .collect();
+    assert_eq!(trapped.len(), 1);
+    assert_eq!(trapped[0].extension, extension);
+}
+
+#[test]
+fn test_default_ping_handler() {
+    let mut app = app_with(ExtensionHostPlugin::new());
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&guest(true))
+        .expect("extension loaded");
+    app.world.spawn(instance);
+
+    // The guest replies to the `Pong` sent by the host with another `Ping`
+    app.update();
+    assert_eq!(pings(&mut app).len(), 1);
+    app.update();
+    assert_eq!(pings(&mut app).len(), 1);
+}
+
+#[test]
+fn test_shutdown_permission() {
+    for (permission, allowed) in [
+        (ShutdownPermission::Deny, false),
+        (ShutdownPermission::AllowAll, true),
+        (
+            ShutdownPermission::Allow(
+                [NamespacedIdentifier::try_from("etheryal:test").expect("valid identifier")].into(),
+            ),
+            true,
+        ),
+        (
+            ShutdownPermission::Allow(
+                [NamespacedIdentifier::try_from("etheryal:other").expect("valid identifier")]
+                    .into(),
+            ),
+            false,
+        ),
+    ] {
+        let mut app = app_with(ExtensionHostPlugin::new().with_shutdown_permission(permission));
+        let instance = app
+            .world
+            .resource::<ExtensionRuntime>()
+            .load(&guest_replying(true, SHUTDOWN_HOST))
+            .expect("extension loaded");
+        app.world.spawn(instance);
+
+        app.update();
+        assert_eq!(exited(&mut app), allowed);
+    }
+}
+
+#[test]
+fn test_impostor_guest_traps() {
+    let allowed = NamespacedIdentifier::try_from("etheryal:admin").expect("valid identifier");
+    let mut app = app_with(
+        ExtensionHostPlugin::new()
+            .with_shutdown_permission(ShutdownPermission::Allow([allowed].into())),
+    );
+    let wasm = wat::parse_str(impostor_guest_module(
+        &info("etheryal:test", "1.0.0", &[]),
+        &info("etheryal:admin", "1.0.0", &[]),
+        SHUTDOWN_HOST,
+    ))
+    .expect("valid module");
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&wasm)
+        .expect("extension loaded");
+    let extension = app.world.spawn(instance).id();
+
+    // The guest claims the identity of an extension allowed to shut down the
+    // extension host, which doesn't match its module
+    app.update();
+    assert!(!exited(&mut app));
+    assert!(app.world.get_entity(extension).is_none());
+    let trapped: Vec<_> = app
+        .world
+        .resource_mut::<Events<ExtensionTrapped>>()
+        .drain()
+        .collect();
+    assert_eq!(trapped.len(), 1);
+    assert!(matches!(
+        &trapped[0].error,
+        ExtensionHostError::InfoMismatch(identifier) if identifier.to_string() == "etheryal:admin"
+    ));
+}
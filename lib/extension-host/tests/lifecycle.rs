@@ -0,0 +1,230 @@
+//! Loads, unloads and reloads the small extension guests of the `common` module
+//! while the extension host is running.
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+
+use bevy_app::App;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::Command;
+use etheryal_extension_common::message::snapshot::{GuestSnapshot, RestoreSnapshot, Snapshot};
+use etheryal_extension_common::message::{GuestMessageEnum, HostMessageEnum};
+use etheryal_extension_host::{
+    CatalogEntry, ExtensionHostPlugin, HostMessageEvent, LoadExtension, LoadReport,
+    ReloadExtension, SkipReason, UnloadExtension, Unloading, MANIFEST_FILE,
+};
+use semver::Version;
+use tempfile::TempDir;
+
+use crate::common::{
+    echo_guest_module, guest_module, info, manifest, versioned_manifest, write_extension, PING,
+};
+
+mod common;
+
+/// Writes `test:lib`, `test:app` depending on it, and the independent
+/// `test:other`
+fn write_extensions(root: &Path) {
+    write_extension(
+        root,
+        "lib",
+        &manifest("test:lib"),
+        Some(&info("test:lib", "1.0.0", &[])),
+    );
+    write_extension(
+        root,
+        "app",
+        &manifest("test:app"),
+        Some(&info("test:app", "1.0.0", &[("test:lib", "^1", false)])),
+    );
+    write_extension(
+        root,
+        "other",
+        &manifest("test:other"),
+        Some(&info("test:other", "1.0.0", &[])),
+    );
+}
+
+/// Returns an extension host which loaded the extensions directory, and
+/// despawns the unloading guests after a single update
+fn app(plugin: ExtensionHostPlugin) -> App {
+    let mut app = App::new();
+    app.add_plugins(plugin.with_unload_grace_ticks(1));
+    app.update();
+    app
+}
+
+fn extension(app: &mut App, identifier: &str) -> Option<Entity> {
+    let mut entries = app.world.query::<(Entity, &CatalogEntry)>();
+    entries
+        .iter(&app.world)
+        .find(|(_, entry)| entry.info().identifier().to_string() == identifier)
+        .map(|(extension, _)| extension)
+}
+
+fn reports(app: &mut App) -> Vec<LoadReport> {
+    app.world
+        .resource_mut::<Events<LoadReport>>()
+        .drain()
+        .collect()
+}
+
+#[test]
+fn test_reload_with_dependents() {
+    let root = TempDir::new().expect("temporary directory");
+    write_extensions(root.path());
+    let mut app = app(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    let lib = extension(&mut app, "test:lib").expect("lib loaded");
+    let application = extension(&mut app, "test:app").expect("app loaded");
+    let other = extension(&mut app, "test:other").expect("other loaded");
+
+    ReloadExtension::new(lib).apply(&mut app.world);
+    assert!(app.world.get::<Unloading>(lib).is_some());
+    assert!(app.world.get::<Unloading>(application).is_some());
+    assert!(app.world.get::<Unloading>(other).is_none());
+
+    // The extensions are loaded again once the previous instances are despawned
+    app.update();
+    assert!(reports(&mut app).is_empty());
+    app.update();
+    let reports = reports(&mut app);
+    assert_eq!(reports.len(), 1);
+    let loaded: Vec<_> = reports[0]
+        .loaded()
+        .iter()
+        .map(|loaded| loaded.identifier.to_string())
+        .collect();
+    assert_eq!(loaded, ["test:lib", "test:app"]);
+
+    assert!(app.world.get_entity(lib).is_none());
+    assert!(app.world.get_entity(application).is_none());
+    assert_eq!(extension(&mut app, "test:other"), Some(other));
+    assert!(extension(&mut app, "test:lib").is_some());
+    assert!(extension(&mut app, "test:app").is_some());
+}
+
+#[test]
+fn test_unload_and_load() {
+    let root = TempDir::new().expect("temporary directory");
+    write_extensions(root.path());
+    let mut app = app(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    let lib = extension(&mut app, "test:lib").expect("lib loaded");
+
+    UnloadExtension::new(lib).apply(&mut app.world);
+    app.update();
+    app.update();
+    assert!(extension(&mut app, "test:lib").is_none());
+    assert!(extension(&mut app, "test:app").is_none());
+    assert!(extension(&mut app, "test:other").is_some());
+
+    // The dependent can't be loaded without its dependency
+    LoadExtension::new(root.path().join("app")).apply(&mut app.world);
+    LoadExtension::new(root.path().join("lib")).apply(&mut app.world);
+    LoadExtension::new(root.path().join("app")).apply(&mut app.world);
+    LoadExtension::new(root.path().join("app")).apply(&mut app.world);
+    let reports = reports(&mut app);
+    assert!(matches!(
+        reports[0].skipped()[0].reason,
+        SkipReason::MissingDependency(_)
+    ));
+    assert_eq!(reports[1].loaded().len(), 1);
+    assert_eq!(reports[2].loaded().len(), 1);
+    let application = extension(&mut app, "test:app").expect("app loaded");
+    assert!(matches!(
+        reports[3].skipped()[0].reason,
+        SkipReason::AlreadyLoaded(extension) if extension == application
+    ));
+}
+
+#[test]
+fn test_hot_reload() {
+    let root = TempDir::new().expect("temporary directory");
+    write_extensions(root.path());
+    let mut app = app(ExtensionHostPlugin::new()
+        .with_extensions_dir(root.path())
+        .with_hot_reload());
+    let lib = extension(&mut app, "test:lib").expect("lib loaded");
+
+    let wasm = wat::parse_str(guest_module(&info("test:lib", "1.1.0", &[]), true, PING))
+        .expect("valid module");
+    fs::write(
+        root.path().join("lib").join(MANIFEST_FILE),
+        versioned_manifest("test:lib", "1.1.0"),
+    )
+    .expect("manifest written");
+    fs::write(root.path().join("lib").join("module.wasm"), wasm).expect("module written");
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let reloaded = loop {
+        app.update();
+        let reloaded = extension(&mut app, "test:lib")
+            .filter(|&extension| extension != lib)
+            .and_then(|extension| app.world.get::<CatalogEntry>(extension))
+            .map(|entry| entry.info().version().to_string());
+        if reloaded.is_some() || Instant::now() > deadline {
+            break reloaded;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(reloaded.as_deref(), Some("1.1.0"));
+    assert!(extension(&mut app, "test:app").is_some());
+}
+
+#[test]
+fn test_reload_restores_snapshot() {
+    let mut snapshot = Snapshot::new(Version::new(1, 0, 0));
+    snapshot
+        .resources_mut()
+        .insert("score", &42)
+        .expect("value encoded");
+    let encoded_snapshot =
+        rmp_serde::to_vec_named(&HostMessageEnum::from(GuestSnapshot::new(snapshot.clone())))
+            .expect("snapshot encoded");
+
+    // The first instance replies to every message, including `SnapshotRequest`,
+    // with the snapshot
+    let root = TempDir::new().expect("temporary directory");
+    let info = info("test:persist", "1.0.0", &[]);
+    write_extension(root.path(), "persist", &manifest("test:persist"), None);
+    let module = root.path().join("persist").join("module.wasm");
+    let wasm = wat::parse_str(guest_module(&info, true, &encoded_snapshot)).expect("valid module");
+    fs::write(&module, wasm).expect("module written");
+    let mut app = app(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    let first = extension(&mut app, "test:persist").expect("extension loaded");
+
+    // The second instance sends back the snapshot it is restored from, as the
+    // messages differ only by their tag
+    let restore = rmp_serde::to_vec_named(&GuestMessageEnum::from(RestoreSnapshot::new(
+        snapshot.clone(),
+    )))
+    .expect("snapshot encoded");
+    let common_suffix = restore
+        .iter()
+        .rev()
+        .zip(encoded_snapshot.iter().rev())
+        .take_while(|(restore, snapshot)| restore == snapshot)
+        .count();
+    let wasm = wat::parse_str(echo_guest_module(
+        &info,
+        &restore[..restore.len() - common_suffix],
+        &encoded_snapshot[..encoded_snapshot.len() - common_suffix],
+    ))
+    .expect("valid module");
+    fs::write(&module, wasm).expect("module written");
+
+    ReloadExtension::new(first).apply(&mut app.world);
+    let mut restored = Vec::new();
+    for _ in 0..5 {
+        app.update();
+        restored.extend(
+            app.world
+                .resource_mut::<Events<HostMessageEvent<GuestSnapshot>>>()
+                .drain()
+                .filter(|event| event.extension != first)
+                .map(|event| event.into_inner().into_snapshot()),
+        );
+    }
+    assert!(extension(&mut app, "test:persist").is_some_and(|extension| extension != first));
+    assert_eq!(restored, [snapshot]);
+}
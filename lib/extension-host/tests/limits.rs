@@ -0,0 +1,157 @@
+//! Limits the memory and the messages of the small extension guests of the
+//! `common` module.
+use bevy_app::App;
+use bevy_ecs::event::Events;
+use etheryal_extension_common::message::debug::Ping;
+use etheryal_extension_common::message::limits::{ExhaustedResource, ResourceExhausted};
+use etheryal_extension_common::message::snapshot::{RestoreSnapshot, Snapshot};
+use etheryal_extension_host::{
+    CatalogError, ExtensionHostError, ExtensionHostPlugin, ExtensionInstance, ExtensionRuntime,
+    ExtensionTrapped, HostMessageEvent, LoadReport, MemoryLimits,
+};
+use semver::Version;
+use tempfile::TempDir;
+
+use crate::common::{guest, guest_replying, info, info_section, manifest, write_extension};
+
+mod common;
+
+const PAGE: usize = 65536;
+
+/// Returns a guest which grows its memory by a page on each update, without
+/// registering itself
+fn growing_guest() -> Vec<u8> {
+    wat::parse_str(format!(
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "_start"))
+          (func (export "etheryal_tick") (param i64)
+            (drop (memory.grow (i32.const 1))))
+          {})
+        "#,
+        info_section(&info("test:growing", "1.0.0", &[]))
+    ))
+    .expect("valid module")
+}
+
+fn app(limits: MemoryLimits) -> App {
+    let mut app = App::new();
+    app.add_plugins(
+        ExtensionHostPlugin::new()
+            .without_default_handler::<Ping>()
+            .with_memory_limits(limits),
+    );
+    app
+}
+
+fn trapped(app: &mut App) -> Vec<ExtensionTrapped> {
+    app.world
+        .resource_mut::<Events<ExtensionTrapped>>()
+        .drain()
+        .collect()
+}
+
+fn exhausted(error: &ExtensionHostError) -> Option<ExhaustedResource> {
+    match error {
+        ExtensionHostError::ResourceExhausted(exhausted) => Some(exhausted.resource()),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_memory_growth() {
+    let mut app = app(MemoryLimits::default().with_max_memory(3 * PAGE));
+    let wasm = growing_guest();
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&wasm)
+        .expect("extension loaded");
+    assert_eq!(instance.memory_usage(), PAGE);
+    let extension = app.world.spawn(instance).id();
+
+    app.update();
+    app.update();
+    let instance = app
+        .world
+        .get::<ExtensionInstance>(extension)
+        .expect("extension running");
+    assert_eq!(instance.peak_memory_usage(), 3 * PAGE);
+    assert!(trapped(&mut app).is_empty());
+
+    app.update();
+    let trapped = trapped(&mut app);
+    assert_eq!(trapped.len(), 1);
+    assert_eq!(trapped[0].extension, extension);
+    assert_eq!(
+        exhausted(&trapped[0].error),
+        Some(ExhaustedResource::Memory)
+    );
+}
+
+#[test]
+fn test_message_size() {
+    let mut app = app(MemoryLimits::default().with_max_message_size(1024));
+    let runtime = app.world.resource::<ExtensionRuntime>();
+    let mut replying = runtime.load(&guest(true)).expect("extension loaded");
+    let sending = runtime
+        .load(&guest_replying(true, &[0; 2048]))
+        .expect("extension loaded");
+
+    // The guest is sent `ResourceExhausted` instead of the large message
+    let mut snapshot = Snapshot::new(Version::new(1, 0, 0));
+    snapshot
+        .resources_mut()
+        .insert("large", &[0u8; 2048][..])
+        .expect("value encoded");
+    let sent = replying.send_message(RestoreSnapshot::new(snapshot));
+    assert_eq!(
+        sent.as_ref().err().and_then(exhausted),
+        Some(ExhaustedResource::MessageSize)
+    );
+    replying
+        .send_message(ResourceExhausted::new(ExhaustedResource::Memory, 2, 1))
+        .expect("message sent");
+    let replying = app.world.spawn(replying).id();
+    let sending = app.world.spawn(sending).id();
+
+    // The guest sending a large message traps
+    app.update();
+    let pings: Vec<_> = app
+        .world
+        .resource_mut::<Events<HostMessageEvent<Ping>>>()
+        .drain()
+        .map(|ping| ping.extension)
+        .collect();
+    assert_eq!(pings, [replying, replying, replying]);
+    let trapped = trapped(&mut app);
+    assert_eq!(trapped.len(), 1);
+    assert_eq!(trapped[0].extension, sending);
+    assert_eq!(
+        exhausted(&trapped[0].error),
+        Some(ExhaustedResource::MessageSize)
+    );
+}
+
+#[test]
+fn test_limits_from_manifest() {
+    let root = TempDir::new().expect("temporary directory");
+    let manifest = format!("{}[memory]\nmax_memory = 0\n", manifest("test:small"));
+    write_extension(
+        root.path(),
+        "small",
+        &manifest,
+        Some(&info("test:small", "1.0.0", &[])),
+    );
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    app.update();
+
+    let report = app.world.resource::<LoadReport>();
+    assert!(report.loaded().is_empty());
+    assert!(matches!(
+        &report.failed()[0].error,
+        CatalogError::Load { source, .. } if exhausted(source) == Some(ExhaustedResource::Memory)
+    ));
+}
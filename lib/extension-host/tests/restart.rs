@@ -0,0 +1,219 @@
+//! Restarts the small extension guests of the `common` module when they trap.
+use std::fs;
+use std::path::Path;
+
+use bevy_app::App;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::Events;
+use etheryal_extension_host::{
+    CatalogEntry, ExtensionHostPlugin, ExtensionTrapped, LoadReport, RestartPolicy, MANIFEST_FILE,
+};
+use etheryal_identifier::NamespacedIdentifier;
+use tempfile::TempDir;
+
+use crate::common::{crashing_guest_module, info, manifest, write_extension};
+
+mod common;
+
+/// Writes `test:lib`, which traps on its second update, `test:app` depending
+/// on it, and the independent `test:other`
+fn write_extensions(root: &Path, lib_manifest: &str) {
+    write_extension(root, "lib", lib_manifest, None);
+    let wasm = wat::parse_str(crashing_guest_module(&info("test:lib", "1.0.0", &[])))
+        .expect("valid module");
+    fs::write(root.join("lib").join("module.wasm"), wasm).expect("module written");
+    write_extension(
+        root,
+        "app",
+        &manifest("test:app"),
+        Some(&info("test:app", "1.0.0", &[("test:lib", "^1", false)])),
+    );
+    write_extension(
+        root,
+        "other",
+        &manifest("test:other"),
+        Some(&info("test:other", "1.0.0", &[])),
+    );
+}
+
+fn app(plugin: ExtensionHostPlugin) -> App {
+    let mut app = App::new();
+    app.add_plugins(plugin.with_unload_grace_ticks(1));
+    app
+}
+
+fn extension(app: &mut App, identifier: &str) -> Option<Entity> {
+    let mut entries = app.world.query::<(Entity, &CatalogEntry)>();
+    entries
+        .iter(&app.world)
+        .find(|(_, entry)| entry.info().identifier().to_string() == identifier)
+        .map(|(extension, _)| extension)
+}
+
+fn trapped(app: &mut App) -> Vec<ExtensionTrapped> {
+    app.world
+        .resource_mut::<Events<ExtensionTrapped>>()
+        .drain()
+        .collect()
+}
+
+fn reports(app: &mut App) -> Vec<LoadReport> {
+    app.world
+        .resource_mut::<Events<LoadReport>>()
+        .drain()
+        .collect()
+}
+
+#[test]
+fn test_never_restart() {
+    let root = TempDir::new().expect("temporary directory");
+    write_extensions(root.path(), &manifest("test:lib"));
+    let mut app = app(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    app.update();
+    let application = extension(&mut app, "test:app").expect("app loaded");
+
+    app.update();
+    let trapped = trapped(&mut app);
+    assert_eq!(trapped.len(), 1);
+    assert_eq!(
+        trapped[0]
+            .identifier
+            .as_ref()
+            .map(ToString::to_string)
+            .as_deref(),
+        Some("test:lib")
+    );
+    for _ in 0..3 {
+        app.update();
+    }
+    assert!(extension(&mut app, "test:lib").is_none());
+    assert_eq!(extension(&mut app, "test:app"), Some(application));
+    assert!(reports(&mut app).is_empty());
+}
+
+#[test]
+fn test_restart_with_dependents() {
+    let root = TempDir::new().expect("temporary directory");
+    let lib_manifest = format!("{}restart = \"always\"\n", manifest("test:lib"));
+    write_extensions(root.path(), &lib_manifest);
+    let mut app = app(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    app.update();
+    let lib = extension(&mut app, "test:lib").expect("lib loaded");
+    let application = extension(&mut app, "test:app").expect("app loaded");
+    let other = extension(&mut app, "test:other").expect("other loaded");
+
+    // The dependent is restarted once it is unloaded
+    app.update();
+    assert_eq!(trapped(&mut app).len(), 1);
+    assert!(app.world.get_entity(lib).is_none());
+    app.update();
+    let reports = reports(&mut app);
+    assert_eq!(reports.len(), 1);
+    let loaded: Vec<_> = reports[0]
+        .loaded()
+        .iter()
+        .map(|loaded| loaded.identifier.to_string())
+        .collect();
+    assert_eq!(loaded, ["test:lib", "test:app"]);
+    assert!(app.world.get_entity(application).is_none());
+    assert_eq!(extension(&mut app, "test:other"), Some(other));
+}
+
+#[test]
+fn test_restart_backoff() {
+    let root = TempDir::new().expect("temporary directory");
+    write_extensions(root.path(), &manifest("test:lib"));
+    let policy = RestartPolicy::Backoff {
+        initial_ticks: 2,
+        max_restarts: 2,
+    };
+    let mut app = app(ExtensionHostPlugin::new()
+        .with_extensions_dir(root.path())
+        .with_extension_restart_policy(
+            NamespacedIdentifier::try_from("test:lib").expect("valid identifier"),
+            policy,
+        ));
+
+    // The delay before each restart doubles, until the extension stays unloaded
+    let mut traps = Vec::new();
+    let mut restarts = 0;
+    for update in 0..30 {
+        app.update();
+        if !trapped(&mut app).is_empty() {
+            traps.push(update);
+        }
+        restarts += reports(&mut app).len();
+    }
+    assert_eq!(traps.len(), 3);
+    assert!(traps[2] - traps[1] > traps[1] - traps[0]);
+    assert_eq!(restarts, 2);
+    assert!(extension(&mut app, "test:lib").is_none());
+    assert_eq!(policy.restart_delay(1), Some(4));
+    assert_eq!(policy.restart_delay(2), None);
+}
+
+#[test]
+fn test_restart_count_resets() {
+    let root = TempDir::new().expect("temporary directory");
+    write_extensions(root.path(), &manifest("test:lib"));
+    let policy = RestartPolicy::Backoff {
+        initial_ticks: 1,
+        max_restarts: 1,
+    };
+    let mut app = app(ExtensionHostPlugin::new()
+        .with_extensions_dir(root.path())
+        .with_extension_restart_policy(
+            NamespacedIdentifier::try_from("test:lib").expect("valid identifier"),
+            policy,
+        )
+        .with_restart_reset_ticks(1));
+
+    // The extension runs an update without trapping after each restart, so it
+    // keeps being restarted with the initial delay
+    let mut traps = Vec::new();
+    let mut restarts = 0;
+    for update in 0..30 {
+        app.update();
+        if !trapped(&mut app).is_empty() {
+            traps.push(update);
+        }
+        restarts += reports(&mut app).len();
+    }
+    assert!(traps.len() > 2);
+    let interval = traps[1] - traps[0];
+    assert!(traps
+        .windows(2)
+        .all(|traps| traps[1] - traps[0] == interval));
+    assert!(restarts > 1);
+}
+
+#[test]
+fn test_dependent_trapping_with_dependency() {
+    let root = TempDir::new().expect("temporary directory");
+    let restart = "restart = \"always\"\n";
+    write_extensions(root.path(), &format!("{}{restart}", manifest("test:lib")));
+    let app_manifest = format!("{}{restart}", manifest("test:app"));
+    fs::write(root.path().join("app").join(MANIFEST_FILE), app_manifest).expect("manifest written");
+    let wasm = wat::parse_str(crashing_guest_module(&info("test:app", "1.0.0", &[(
+        "test:lib", "^1", false,
+    )])))
+    .expect("valid module");
+    fs::write(root.path().join("app").join("module.wasm"), wasm).expect("module written");
+    let mut app = app(ExtensionHostPlugin::new().with_extensions_dir(root.path()));
+    app.update();
+
+    // Both extensions trap during the same update, and are reloaded once
+    // together, the dependency first
+    app.update();
+    assert_eq!(trapped(&mut app).len(), 2);
+    app.update();
+    let reports = reports(&mut app);
+    assert_eq!(reports.len(), 1);
+    let loaded: Vec<_> = reports[0]
+        .loaded()
+        .iter()
+        .map(|loaded| loaded.identifier.to_string())
+        .collect();
+    assert_eq!(loaded, ["test:lib", "test:app"]);
+    assert!(reports[0].skipped().is_empty());
+}
@@ -0,0 +1,146 @@
+//! Publishes messages on topics between the small extension guests of the
+//! `common` module and the host.
+use bevy_app::App;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::Command;
+use etheryal_extension_common::message::debug::Ping;
+use etheryal_extension_common::message::topic::{Publish, Subscribe, TopicPattern};
+use etheryal_extension_common::message::HostMessageEnum;
+use etheryal_extension_host::{
+    ExtensionHostPlugin, ExtensionRuntime, HostMessageEvent, PublishTopic, TopicSubscriptions,
+};
+use etheryal_identifier::NamespacedIdentifier;
+
+use crate::common::{guest_module, impostor_guest_module, info};
+
+mod common;
+
+fn topic(topic: &str) -> NamespacedIdentifier {
+    NamespacedIdentifier::try_from(topic).expect("valid identifier")
+}
+
+/// The encoded `Subscribe` to `pattern`
+fn subscribe(pattern: &str) -> Vec<u8> {
+    let pattern = TopicPattern::try_from(pattern).expect("valid pattern");
+    rmp_serde::to_vec_named(&HostMessageEnum::from(Subscribe::new(pattern)))
+        .expect("message encoded")
+}
+
+/// The encoded `Publish` on `topic`
+fn publish(topic_name: &str) -> Vec<u8> {
+    let message = Publish::new(topic(topic_name), "hello").expect("message encoded");
+    rmp_serde::to_vec_named(&HostMessageEnum::from(message)).expect("message encoded")
+}
+
+/// An extension host which doesn't reply to `Ping`, so the guests only reply to
+/// the messages they receive
+fn app() -> App {
+    let mut app = App::new();
+    app.add_plugins(ExtensionHostPlugin::new().without_default_handler::<Ping>());
+    app
+}
+
+/// Loads a guest replying with the encoded `reply`
+fn load(app: &mut App, identifier: &str, reply: &[u8]) -> Entity {
+    let wasm = wat::parse_str(guest_module(&info(identifier, "1.0.0", &[]), true, reply))
+        .expect("valid module");
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&wasm)
+        .expect("extension loaded");
+    app.world.spawn(instance).id()
+}
+
+/// The extensions which sent a `Subscribe`, which the subscribers send again
+/// for every message they receive
+fn subscribes(app: &mut App) -> Vec<Entity> {
+    let mut subscribes: Vec<_> = app
+        .world
+        .resource_mut::<Events<HostMessageEvent<Subscribe>>>()
+        .drain()
+        .map(|subscribe| subscribe.extension)
+        .collect();
+    subscribes.sort();
+    subscribes
+}
+
+#[test]
+fn test_publish_from_guest() {
+    let mut app = app();
+    let exact = load(&mut app, "test:exact", &subscribe("example:news"));
+    let namespace = load(&mut app, "test:namespace", &subscribe("example:*"));
+    load(&mut app, "test:other", &subscribe("other:news"));
+    load(&mut app, "test:publisher", &publish("example:news"));
+
+    app.update();
+    assert_eq!(subscribes(&mut app).len(), 3);
+    let patterns: Vec<_> = app
+        .world
+        .resource::<TopicSubscriptions>()
+        .patterns(namespace)
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(patterns, ["example:*"]);
+
+    // Only the subscribers matching the topic receive the message
+    app.update();
+    let mut expected = vec![exact, namespace];
+    expected.sort();
+    assert_eq!(subscribes(&mut app), expected);
+    app.update();
+    assert!(subscribes(&mut app).is_empty());
+}
+
+#[test]
+fn test_publish_from_host() {
+    let mut app = app();
+    let subscriber = load(&mut app, "test:subscriber", &subscribe("*"));
+    app.update();
+    assert_eq!(subscribes(&mut app), [subscriber]);
+
+    PublishTopic::new(topic("example:news"), "hello")
+        .expect("message encoded")
+        .apply(&mut app.world);
+    app.update();
+    assert_eq!(subscribes(&mut app), [subscriber]);
+
+    // The subscriptions of despawned extensions are removed
+    app.world.despawn(subscriber);
+    PublishTopic::new(topic("example:news"), "hello")
+        .expect("message encoded")
+        .apply(&mut app.world);
+    assert_eq!(
+        app.world
+            .resource::<TopicSubscriptions>()
+            .patterns(subscriber)
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_impostor_publisher_traps() {
+    let mut app = app();
+    let subscriber = load(&mut app, "test:subscriber", &subscribe("*"));
+    let wasm = wat::parse_str(impostor_guest_module(
+        &info("test:fake", "1.0.0", &[]),
+        &info("test:publisher", "1.0.0", &[]),
+        &publish("example:news"),
+    ))
+    .expect("valid module");
+    let instance = app
+        .world
+        .resource::<ExtensionRuntime>()
+        .load(&wasm)
+        .expect("extension loaded");
+    let impostor = app.world.spawn(instance).id();
+
+    // The guest publishing as another extension traps before publishing
+    app.update();
+    assert_eq!(subscribes(&mut app), [subscriber]);
+    assert!(app.world.get_entity(impostor).is_none());
+    app.update();
+    assert!(subscribes(&mut app).is_empty());
+}
@@ -7,6 +7,9 @@ use std::fmt;
 
 use derive_more::{Display, Into};
 use getset::{Getters, Setters};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
 use serde::de::{MapAccess, Visitor};
 use serde::{de, Deserialize, Serialize};
 use smol_str::SmolStr;
@@ -32,7 +35,18 @@ pub enum IdentifierError {
 /// An [Identifier] with an additional namespace field to prevent name
 /// collisions
 #[derive(
-    Serialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Display, Getters, Setters,
+    Serialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Display,
+    Getters,
+    Setters,
+    JsonSchema,
 )]
 #[display(fmt = "{namespace}:{value}")]
 #[getset(get = "pub", set = "pub")]
@@ -235,6 +249,25 @@ impl Identifier {
     }
 }
 
+impl JsonSchema for Identifier {
+    fn schema_name() -> String {
+        "Identifier".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                min_length: Some(1),
+                pattern: Some("^[a-z_][a-z0-9_]*$".into()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 impl Default for Identifier {
     fn default() -> Self {
         Self::ETHERYAL
@@ -1,4 +1,7 @@
 //! Most commonly used re-exported types.
 pub use crate::common::{ExtensionModuleDependency, ExtensionModuleInfo};
 pub use crate::identifier::{Identifier, NamespacedIdentifier};
-pub use crate::plugin::{EtheryalExtensionPlugin, ExtensionEvent, ExtensionGuest};
+pub use crate::plugin::{
+    extension_message_matches, on_extension_message, EtheryalExtensionPlugin, ExtensionEvent,
+    ExtensionGuest, ExtensionMessages, ExtensionSet, ExtensionState, Persist, PersistApp, ToHost,
+};